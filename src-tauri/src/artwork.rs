@@ -0,0 +1,131 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader};
+use lofty::picture::{Picture, PictureType};
+use lofty::prelude::*;
+
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::metadata::{preferred_tag, tag_error};
+
+/// Embedded covers larger than this on either side are downscaled before
+/// being sent over IPC.
+pub const MAX_ART_DIMENSION: u32 = 1000;
+
+/// An encoded image and its MIME type.
+pub struct Artwork {
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+impl Artwork {
+    pub fn to_data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.mime, STANDARD.encode(&self.data))
+    }
+}
+
+/// The front cover if one is tagged as such, otherwise the first picture found.
+fn pick_picture(file: &lofty::file::TaggedFile) -> Option<&Picture> {
+    let tags = preferred_tag(file).into_iter().chain(file.tags());
+    let pictures: Vec<&Picture> = tags.flat_map(|tag| tag.pictures()).collect();
+    pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+        .copied()
+}
+
+/// Reads the embedded cover of `path`, or `None` if the file has no pictures.
+/// A CUE sheet's virtual track has the cover of the image it's cut from.
+pub fn embedded(path: &Path) -> Result<Option<Artwork>, PlayerError> {
+    let (path, _) = decoder::locate(path)?;
+    let file = lofty::read_from_path(&path).map_err(|e| tag_error("read tags from", &path, e))?;
+    let Some(picture) = pick_picture(&file) else {
+        return Ok(None);
+    };
+
+    // Tag-declared MIME types are frequently missing or wrong; sniff the bytes first.
+    let format = image::guess_format(picture.data()).ok();
+    let mime = match (format, picture.mime_type()) {
+        (Some(format), _) => format.to_mime_type().to_string(),
+        (None, Some(mime)) => mime.as_str().to_string(),
+        (None, None) => return Ok(None),
+    };
+    Ok(Some(Artwork {
+        mime,
+        data: picture.data().to_vec(),
+    }))
+}
+
+/// Image files next to the tracks taken as the album cover, by stem in order
+/// of preference. Matched case-insensitively.
+pub const FOLDER_ART_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+
+/// Reads a cover image from the folder holding `path`, for tracks without
+/// embedded art.
+pub fn from_folder(path: &Path) -> Option<Artwork> {
+    let mut candidates: Vec<(usize, PathBuf)> = fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file = entry.path();
+            let stem = file.file_stem()?.to_str()?.to_ascii_lowercase();
+            let rank = FOLDER_ART_NAMES.iter().position(|name| *name == stem)?;
+            ImageFormat::from_path(&file).ok()?;
+            Some((rank, file))
+        })
+        .collect();
+    candidates.sort();
+    candidates.into_iter().find_map(|(_, file)| {
+        let data = fs::read(&file).ok()?;
+        let format = image::guess_format(&data).ok()?;
+        Some(Artwork {
+            mime: format.to_mime_type().to_string(),
+            data,
+        })
+    })
+}
+
+/// Re-encodes `art` as JPEG if it exceeds `max` pixels on either side.
+pub fn limit_size(art: Artwork, max: u32) -> Result<Artwork, String> {
+    let reader = ImageReader::new(Cursor::new(&art.data))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let Ok((width, height)) = reader.into_dimensions() else {
+        // Undecodable by `image`; let the webview try its luck with the original.
+        return Ok(art);
+    };
+    if width <= max && height <= max {
+        return Ok(art);
+    }
+
+    let resized = image::load_from_memory(&art.data)
+        .map_err(|e| format!("failed to decode album art: {e}"))?
+        .resize(max, max, FilterType::Lanczos3);
+    let mut data = Vec::new();
+    resized
+        .to_rgb8()
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .map_err(|e| format!("failed to encode album art: {e}"))?;
+    Ok(Artwork {
+        mime: ImageFormat::Jpeg.to_mime_type().to_string(),
+        data,
+    })
+}
+
+#[tauri::command]
+pub async fn get_album_art(path: String) -> Result<Option<String>, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let Some(art) = embedded(Path::new(&path))? else {
+            return Ok(None);
+        };
+        Ok(Some(limit_size(art, MAX_ART_DIMENSION)?.to_data_uri()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
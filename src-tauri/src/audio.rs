@@ -0,0 +1,1119 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rodio::{OutputStreamHandle, Sink};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::channels::{ChannelControl, ChannelMix};
+use crate::decoder::{self, LoopRegion, PlaybackClock, TrackSource};
+use crate::dsp::{AtomicGain, Envelope, Faded, Guarded, Normalized};
+use crate::equalizer::{Equalizer, EqualizerControl};
+use crate::error::PlayerError;
+use crate::logging;
+use crate::loudness;
+use crate::output::{self, DeviceChange, Output, DEVICE_CHANGED_EVENT};
+use crate::queue::Queue;
+use crate::remote::{self, StreamTitle, StreamTitleChange, STREAM_TITLE_EVENT};
+use crate::replaygain::{db_to_gain, NormalizationMode, ReplayGain};
+use crate::settings::SettingsStore;
+use crate::silence::{self, Trim};
+use crate::sleep::SleepTimer;
+use crate::stretch::{SpeedControl, Stretched};
+use crate::sync::MutexExt;
+use crate::visualizer::{SampleTap, Tapped, Visualizer};
+
+pub const PLAYBACK_STATE_EVENT: &str = "playback-state-changed";
+pub const PLAYBACK_PROGRESS_EVENT: &str = "playback-progress";
+
+/// How often `playback-progress` is emitted while a track is playing.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Attenuation at the bottom of the volume slider. Levels are mapped onto this
+/// decibel range so equal slider steps sound like equal loudness steps.
+pub const VOLUME_RANGE_DB: f32 = 60.0;
+
+/// How often the monitor checks that the selected output device is still present.
+pub const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// With gapless playback on, the next track is decoded and queued on the sink
+/// once the current one has this much left.
+pub const GAPLESS_PRELOAD: Duration = Duration::from_secs(10);
+
+/// Default fade when playback starts, resumes, or pauses.
+pub const DEFAULT_FADE_MS: u64 = 200;
+
+/// Shortest loop region accepted; anything tighter would stutter.
+pub const MIN_LOOP_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// Payload of the `playback-state-changed` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybackStatus {
+    pub state: PlaybackState,
+    pub path: Option<String>,
+    /// Normalization gain currently applied to the track.
+    pub gain_db: f32,
+    /// Region the current track is looping over.
+    pub loop_region: Option<LoopRegion>,
+    /// Changes each time a track starts, including the same one again (a
+    /// replay, or repeat-one), but not when it's seeked, paused, or moved to
+    /// another device.
+    pub start_id: Option<u64>,
+}
+
+/// Payload of the `playback-progress` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybackProgress {
+    pub position_ms: u64,
+    pub duration_ms: Option<u64>,
+}
+
+/// A track loaded into the sink.
+struct NowPlaying {
+    path: String,
+    duration: Option<Duration>,
+    clock: Arc<PlaybackClock>,
+    envelope: Arc<Envelope>,
+    /// Play/pause fades, kept apart from `envelope` so they never disturb a
+    /// crossfade or the sleep timer. Survives seeks and device switches.
+    transport: Arc<Envelope>,
+    replay_gain: ReplayGain,
+    normalization: Arc<AtomicGain>,
+    gain_db: f32,
+    tap: Arc<SampleTap>,
+    /// Silence skipped at the end; kept across seeks and device switches.
+    trim: Trim,
+    /// ICY title of a remote stream.
+    stream_title: Arc<StreamTitle>,
+    speed: Arc<SpeedControl>,
+    /// See [`PlaybackStatus::start_id`]; kept across seeks and device switches.
+    start_id: u64,
+}
+
+impl NowPlaying {
+    fn apply_normalization(&mut self, mode: NormalizationMode) {
+        self.gain_db = self.replay_gain.gain_db(mode);
+        self.normalization.set(db_to_gain(self.gain_db));
+    }
+
+    /// Time until the track ends at the current speed.
+    fn remaining(&self) -> Option<Duration> {
+        self.duration.map(|d| {
+            d.saturating_sub(self.clock.position())
+                .div_f32(self.speed.get())
+        })
+    }
+}
+
+/// Slider level plus mute flag; the level survives muting.
+#[derive(Debug, Clone, Copy)]
+struct Volume {
+    level: f32,
+    muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume {
+            level: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl Volume {
+    /// Amplitude factor for the sink.
+    fn gain(self) -> f32 {
+        if self.muted || self.level <= 0.0 {
+            0.0
+        } else {
+            10f32.powf(VOLUME_RANGE_DB * (self.level - 1.0) / 20.0)
+        }
+    }
+}
+
+/// A sink ramping down under a crossfade, dropped once `until` has passed.
+struct FadingOut {
+    sink: Sink,
+    until: Instant,
+}
+
+/// Processing chain from decoder to sink.
+type Pipeline =
+    Guarded<Tapped<Faded<Faded<Normalized<ChannelMix<Equalizer<Stretched<TrackSource>>>>>>>>;
+
+/// Audio engine shared between commands. Registered with `tauri::Builder::manage`.
+///
+/// Locks nest in the order `queue`, `current`, `preloaded`, `sink`.
+#[derive(Default)]
+pub struct PlayerState {
+    output: Mutex<Option<Output>>,
+    last_device_check: Mutex<Option<Instant>>,
+    /// Falling back from an unplugged device failed and has been reported.
+    fallback_failed: AtomicBool,
+    sink: Arc<Mutex<Option<Sink>>>,
+    current: Mutex<Option<NowPlaying>>,
+    /// Next track already appended to the sink behind `current` (gapless mode).
+    preloaded: Mutex<Option<NowPlaying>>,
+    gapless: AtomicBool,
+    /// Last [`PlaybackStatus::start_id`] handed out.
+    starts: AtomicU64,
+    crossfade_ms: AtomicU64,
+    fading_out: Mutex<Vec<FadingOut>>,
+    fade_ms: AtomicU64,
+    /// Bumped by every play, pause, and stop, so a fade-out that finishes
+    /// after the user changed their mind doesn't pause the sink.
+    transport_epoch: AtomicU64,
+    /// A pause is fading out and the sink will be paused once it's silent.
+    pausing: AtomicBool,
+    volume: Mutex<Volume>,
+    normalization: Mutex<NormalizationMode>,
+    pub(crate) equalizer: Arc<EqualizerControl>,
+    pub(crate) speed: Arc<SpeedControl>,
+    pub(crate) channels: Arc<ChannelControl>,
+    pub(crate) visualizer: Visualizer,
+    pub(crate) sleep: SleepTimer,
+    monitor: Mutex<Option<Monitor>>,
+    pub(crate) queue: Mutex<Queue>,
+}
+
+/// Background thread reporting progress and advancing the queue; stopped by
+/// sending on (or dropping) `stop`.
+struct Monitor {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl PlayerState {
+    /// Opens `path` at `start` behind a gain envelope at full level and the
+    /// play/pause envelope `transport`. A local file ends early at `trim.end`,
+    /// and is normalized with the `measured` gains if it has no gain tags.
+    fn open_track(
+        &self,
+        path: &str,
+        start: Duration,
+        mode: NormalizationMode,
+        transport: Arc<Envelope>,
+        trim: Trim,
+        measured: ReplayGain,
+    ) -> Result<(Pipeline, NowPlaying), PlayerError> {
+        let clock = Arc::new(PlaybackClock::default());
+        let envelope = Arc::new(Envelope::new(1.0));
+        let normalization = Arc::new(AtomicGain::new(1.0));
+        let tap = self.visualizer.tap();
+        let stream_title = Arc::new(StreamTitle::default());
+        let (source, replay_gain) = if remote::is_url(path) {
+            let source = TrackSource::open_url(path, start, clock.clone(), stream_title.clone())?;
+            (source, ReplayGain::default())
+        } else {
+            let (file, mut span) = decoder::locate(Path::new(path))?;
+            // The trim is relative to the track, which a CUE sheet cuts from a file.
+            if let Some(end) = trim.end.map(|end| span.offset + end) {
+                span.end = Some(span.end.map_or(end, |e| e.min(end)));
+            }
+            let source = TrackSource::open_span(&file, span, start, clock.clone())?;
+            (source, ReplayGain::read(&file).or(measured))
+        };
+        let mut track = NowPlaying {
+            path: path.to_string(),
+            duration: source.duration(),
+            clock,
+            envelope: envelope.clone(),
+            transport: transport.clone(),
+            replay_gain,
+            normalization: normalization.clone(),
+            gain_db: 0.0,
+            tap: tap.clone(),
+            trim,
+            stream_title,
+            speed: self.speed.clone(),
+            start_id: self.starts.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        track.apply_normalization(mode);
+        let source = Stretched::new(source, self.speed.clone());
+        let source = Equalizer::new(source, self.equalizer.clone());
+        let source = ChannelMix::new(source, self.channels.clone());
+        let source = Faded::new(Normalized::new(source, normalization), envelope);
+        let source = Faded::new(source, transport);
+        Ok((Guarded::new(Tapped::new(source, tap)), track))
+    }
+
+    /// Returns a handle to the output stream, opening the default device on first use.
+    fn output_handle(&self) -> Result<OutputStreamHandle, PlayerError> {
+        let mut output = self.output.locked();
+        if let Some(output) = output.as_ref() {
+            return Ok(output.handle.clone());
+        }
+        let opened = Output::open(None)?;
+        let handle = opened.handle.clone();
+        *output = Some(opened);
+        Ok(handle)
+    }
+
+    /// Reopens output on `device` (`None` for the default) and moves the
+    /// current track onto it at its current position and play/pause state.
+    /// Emits `device-changed`; `disconnected` marks a fallback to the default.
+    pub(crate) fn switch_output(
+        &self,
+        device: Option<String>,
+        disconnected: bool,
+        app: &AppHandle,
+    ) -> Result<(), PlayerError> {
+        let opened = Output::open(device.clone())?;
+        let handle = opened.handle.clone();
+        let previous = self.output.locked().replace(opened);
+        let gain = self.volume.locked().gain();
+        let mode = self.normalization();
+
+        self.cut_fades();
+        self.discard_preloaded();
+        let playing = {
+            let current = self.current.locked();
+            let active = self.sink.locked();
+            current.as_ref().zip(active.as_ref()).map(|(track, sink)| {
+                (
+                    track.path.clone(),
+                    track.clock.position(),
+                    track.transport.clone(),
+                    track.trim,
+                    track.replay_gain,
+                    track.start_id,
+                    sink.is_paused(),
+                )
+            })
+        };
+        if let Some((path, position, transport, trim, replay_gain, start_id, paused)) = playing {
+            // Opened unlocked, as in `seek_to`.
+            let (source, mut reopened) =
+                self.open_track(&path, position, mode, transport, trim, replay_gain)?;
+            let sink = Sink::try_new(&handle)
+                .map_err(|e| PlayerError::device(device.clone(), e.to_string()))?;
+            sink.set_volume(gain);
+            if paused {
+                sink.pause();
+            }
+            let mut current = self.current.locked();
+            let mut active = self.sink.locked();
+            // A track started meanwhile already plays on the new output.
+            if let Some(track) = current.as_mut().filter(|t| t.start_id == start_id) {
+                reopened.clock.set_loop(track.clock.loop_region());
+                reopened.start_id = track.start_id;
+                sink.append(source);
+                if let Some(old_sink) = active.replace(sink) {
+                    old_sink.stop();
+                }
+                *track = reopened;
+            }
+        }
+        // Only close the old stream once nothing plays through it any more.
+        drop(previous);
+        let _ = app.emit(
+            DEVICE_CHANGED_EVENT,
+            DeviceChange {
+                name: device,
+                disconnected,
+            },
+        );
+        Ok(())
+    }
+
+    /// Falls back to the default device if the selected one was unplugged.
+    fn check_device(&self, app: &AppHandle) {
+        {
+            let mut last = self.last_device_check.locked();
+            if last.is_some_and(|t| t.elapsed() < DEVICE_CHECK_INTERVAL) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        let selected = self.output.locked().as_ref().and_then(|o| o.device.clone());
+        let Some(name) = selected else {
+            return;
+        };
+        if output::device_exists(&name) {
+            return;
+        }
+        // Retried on the next check if no default device is available either,
+        // but only reported the first time.
+        match self.switch_output(None, true, app) {
+            Ok(()) => self.fallback_failed.store(false, Ordering::Relaxed),
+            Err(error) => {
+                if !self.fallback_failed.swap(true, Ordering::Relaxed) {
+                    error.emit(app);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn status(&self) -> PlaybackStatus {
+        let state = match self.sink.locked().as_ref() {
+            Some(sink) if sink.is_paused() || self.pausing.load(Ordering::Relaxed) => {
+                PlaybackState::Paused
+            }
+            Some(_) => PlaybackState::Playing,
+            None => PlaybackState::Stopped,
+        };
+        let current = self.current.locked();
+        PlaybackStatus {
+            state,
+            path: current.as_ref().map(|t| t.path.clone()),
+            gain_db: current.as_ref().map_or(0.0, |t| t.gain_db),
+            loop_region: current.as_ref().and_then(|t| t.clock.loop_region()),
+            start_id: current.as_ref().map(|t| t.start_id),
+        }
+    }
+
+    pub(crate) fn emit_state(&self, app: &AppHandle) {
+        let _ = app.emit(PLAYBACK_STATE_EVENT, self.status());
+    }
+
+    /// Loads `path` into a fresh sink and starts playing it, fading in.
+    pub(crate) fn load(&self, path: &str, app: &AppHandle) -> Result<(), PlayerError> {
+        self.start_track(path, None, true, app)
+    }
+
+    /// Like [`PlayerState::load`], but fades the outgoing track out while the
+    /// new one fades in over `window`, each on its own sink.
+    pub(crate) fn crossfade_into(
+        &self,
+        path: &str,
+        window: Duration,
+        app: &AppHandle,
+    ) -> Result<(), PlayerError> {
+        self.start_track(path, Some(window), true, app)
+    }
+
+    /// Starts `path` on a fresh sink, past any leading silence being skipped.
+    /// Without a crossfade, `fade_in` ramps it up from silence; the queue
+    /// moving on by itself starts at full level.
+    fn start_track(
+        &self,
+        path: &str,
+        crossfade: Option<Duration>,
+        fade_in: bool,
+        app: &AppHandle,
+    ) -> Result<(), PlayerError> {
+        let transport = Arc::new(Envelope::new(1.0));
+        let trim = silence::trim_for(app, path);
+        let (source, track) = self.open_track(
+            path,
+            trim.start,
+            self.normalization(),
+            transport,
+            trim,
+            loudness::measured(app, path),
+        )?;
+        let sink = Sink::try_new(&self.output_handle()?)
+            .map_err(|e| PlayerError::device(None, e.to_string()))?;
+        sink.set_volume(self.volume.locked().gain());
+
+        self.discard_preloaded();
+        {
+            let mut current = self.current.locked();
+            let mut active = self.sink.locked();
+            let audible = active
+                .as_ref()
+                .is_some_and(|s| !s.is_paused() && !s.empty());
+            let fade = crossfade.filter(|_| audible);
+            if let (Some(window), Some(previous)) = (fade, current.as_ref()) {
+                previous.envelope.ramp_to(0.0, window);
+                // A fresh source starts silent, so this fades it in.
+                track.envelope.ramp_to(1.0, window);
+            } else if fade_in {
+                track.transport.ramp_from(0.0, 1.0, self.fade());
+            }
+            self.cancel_pause();
+            sink.append(source);
+            match (active.replace(sink), fade) {
+                (Some(previous), Some(window)) => self.fading_out.locked().push(FadingOut {
+                    sink: previous,
+                    until: Instant::now() + window,
+                }),
+                (Some(previous), None) => previous.stop(),
+                (None, _) => {}
+            }
+            *current = Some(track);
+        }
+        self.emit_state(app);
+        Ok(())
+    }
+
+    /// Loads `path` paused at `position`, e.g. to pick up a restored session.
+    pub(crate) fn cue(
+        &self,
+        path: &str,
+        position: Duration,
+        app: &AppHandle,
+    ) -> Result<(), PlayerError> {
+        let transport = Arc::new(Envelope::new(1.0));
+        let trim = silence::trim_for(app, path);
+        let (source, track) = self.open_track(
+            path,
+            position,
+            self.normalization(),
+            transport,
+            trim,
+            loudness::measured(app, path),
+        )?;
+        let sink = Sink::try_new(&self.output_handle()?)
+            .map_err(|e| PlayerError::device(None, e.to_string()))?;
+        sink.set_volume(self.volume.locked().gain());
+        sink.pause();
+        sink.append(source);
+
+        self.cut_fades();
+        self.discard_preloaded();
+        {
+            let mut current = self.current.locked();
+            let mut active = self.sink.locked();
+            self.cancel_pause();
+            if let Some(previous) = active.replace(sink) {
+                previous.stop();
+            }
+            *current = Some(track);
+        }
+        self.emit_state(app);
+        Ok(())
+    }
+
+    /// Sets the slider level (clamped to `0.0..=1.0`), unmuting if muted.
+    pub(crate) fn set_volume_level(&self, level: f32) {
+        let mut volume = self.volume.locked();
+        volume.level = level.clamp(0.0, 1.0);
+        volume.muted = false;
+        self.apply_volume(*volume);
+    }
+
+    pub(crate) fn volume_level(&self) -> f32 {
+        self.volume.locked().level
+    }
+
+    fn toggle_mute(&self) -> bool {
+        let mut volume = self.volume.locked();
+        volume.muted = !volume.muted;
+        self.apply_volume(*volume);
+        volume.muted
+    }
+
+    fn apply_volume(&self, volume: Volume) {
+        if let Some(sink) = self.sink.locked().as_ref() {
+            sink.set_volume(volume.gain());
+        }
+    }
+
+    fn normalization(&self) -> NormalizationMode {
+        *self.normalization.locked()
+    }
+
+    /// Switches normalization mode, re-deriving the gain of loaded tracks.
+    pub(crate) fn set_normalization(&self, mode: NormalizationMode) {
+        *self.normalization.locked() = mode;
+        if let Some(track) = self.current.locked().as_mut() {
+            track.apply_normalization(mode);
+        }
+        if let Some(track) = self.preloaded.locked().as_mut() {
+            track.apply_normalization(mode);
+        }
+    }
+
+    /// Ramps the gain of the current track, leaving the volume setting alone.
+    pub(crate) fn fade_current(&self, gain: f32, over: Duration) {
+        if let Some(track) = self.current.locked().as_ref() {
+            track.envelope.ramp_to(gain, over);
+        }
+    }
+
+    fn fade(&self) -> Duration {
+        Duration::from_millis(self.fade_ms.load(Ordering::Relaxed))
+    }
+
+    /// Sets the fade applied on play, resume, and pause; zero disables it.
+    pub(crate) fn set_fade(&self, fade: Duration) {
+        self.fade_ms
+            .store(fade.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Drops a pending fade-out pause. Called with the sink lock held.
+    fn cancel_pause(&self) {
+        self.transport_epoch.fetch_add(1, Ordering::Relaxed);
+        self.pausing.store(false, Ordering::Relaxed);
+    }
+
+    /// Fades out over the configured fade, then pauses.
+    pub(crate) fn pause_playback(&self, app: &AppHandle) {
+        self.pause_after(self.fade(), app);
+    }
+
+    /// Fades the track out over `fade`, then pauses the sink, unless playback
+    /// is resumed, stopped, or replaced first. The state reads as paused
+    /// straight away.
+    pub(crate) fn pause_after(&self, fade: Duration, app: &AppHandle) {
+        self.cut_fades();
+        self.fade_out_and_pause(fade, app);
+        self.emit_state(app);
+    }
+
+    fn fade_out_and_pause(&self, fade: Duration, app: &AppHandle) {
+        let current = self.current.locked();
+        let preloaded = self.preloaded.locked();
+        let active = self.sink.locked();
+        let Some(sink) = active.as_ref() else {
+            return;
+        };
+        self.cancel_pause();
+        if fade.is_zero() || sink.is_paused() || sink.empty() {
+            sink.pause();
+            return;
+        }
+        for track in current.iter().chain(preloaded.iter()) {
+            track.transport.ramp_to(0.0, fade);
+        }
+        self.pausing.store(true, Ordering::Relaxed);
+        let epoch = self.transport_epoch.load(Ordering::Relaxed);
+        let app = app.clone();
+        let spawned = thread::Builder::new()
+            .name("pause-fade".into())
+            .spawn(move || {
+                thread::sleep(fade);
+                let player = app.state::<PlayerState>();
+                let active = player.sink.locked();
+                if player.transport_epoch.load(Ordering::Relaxed) == epoch {
+                    player.pausing.store(false, Ordering::Relaxed);
+                    if let Some(sink) = active.as_ref() {
+                        sink.pause();
+                    }
+                }
+            });
+        if spawned.is_err() {
+            self.pausing.store(false, Ordering::Relaxed);
+            sink.pause();
+        }
+    }
+
+    /// Plays on, fading back in from wherever a fade-out got to, or from
+    /// silence if the sink had paused.
+    pub(crate) fn resume_playback(&self, app: &AppHandle) {
+        {
+            let current = self.current.locked();
+            let active = self.sink.locked();
+            if let Some(sink) = active.as_ref() {
+                self.cancel_pause();
+                if let Some(track) = current.as_ref() {
+                    if sink.is_paused() {
+                        track.transport.ramp_from(0.0, 1.0, self.fade());
+                    } else {
+                        track.transport.ramp_to(1.0, self.fade());
+                    }
+                }
+                sink.play();
+            }
+        }
+        self.emit_state(app);
+    }
+
+    /// Stops any sinks still fading out, immediately.
+    fn cut_fades(&self) {
+        for fade in self.fading_out.locked().drain(..) {
+            fade.sink.stop();
+        }
+    }
+
+    /// Drops outgoing sinks whose crossfade has completed.
+    fn reap_fades(&self) {
+        let now = Instant::now();
+        self.fading_out.locked().retain(|fade| {
+            let done = now >= fade.until || fade.sink.empty();
+            if done {
+                fade.sink.stop();
+            }
+            !done
+        });
+    }
+
+    pub(crate) fn crossfade(&self) -> Duration {
+        Duration::from_millis(self.crossfade_ms.load(Ordering::Relaxed))
+    }
+
+    /// Starts crossfading into the next queued track once the current one is
+    /// within the crossfade window of its end.
+    fn crossfade_if_due(&self, app: &AppHandle) {
+        let window = self.crossfade();
+        if window.is_zero() || self.sleep.stops_after_track() {
+            return;
+        }
+        let playing = self
+            .sink
+            .locked()
+            .as_ref()
+            .is_some_and(|s| !s.is_paused() && !s.empty())
+            && !self.pausing.load(Ordering::Relaxed);
+        let remaining = self.current.locked().as_ref().and_then(|t| {
+            // Never let the fade swallow more than half of a short track.
+            let window = window.min(t.duration? / 2);
+            let remaining = t.remaining()?;
+            // A looping track never reaches its end.
+            let due = t.clock.loop_region().is_none() && remaining <= window;
+            (!t.clock.is_finished() && due).then_some(remaining)
+        });
+        let Some(remaining) = remaining.filter(|_| playing) else {
+            return;
+        };
+        let next = {
+            let mut queue = self.queue.locked();
+            if queue.peek_after_finish().is_none() {
+                return;
+            }
+            queue.advance_after_finish().map(str::to_string)
+        };
+        if let Some(path) = next {
+            // On failure the track just plays out and the regular advance skips past it.
+            if let Err(error) = self.crossfade_into(&path, remaining, app) {
+                error.emit(app);
+            }
+        }
+    }
+
+    pub(crate) fn stop_playback(&self, app: &AppHandle) {
+        self.cut_fades();
+        self.discard_preloaded();
+        *self.current.locked() = None;
+        let mut active = self.sink.locked();
+        self.cancel_pause();
+        if let Some(sink) = active.take() {
+            sink.stop();
+        }
+        drop(active);
+        self.emit_state(app);
+    }
+
+    /// Position within the current track, if one is loaded.
+    pub(crate) fn position(&self) -> Option<Duration> {
+        let current = self.current.locked();
+        current.as_ref().map(|t| t.clock.position())
+    }
+
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        let current = self.current.locked();
+        current.as_ref().and_then(NowPlaying::remaining)
+    }
+
+    pub(crate) fn seek_to(&self, position: Duration) -> Result<(), PlayerError> {
+        // Opening can wait on the network for a stream, so it happens with
+        // nothing locked and the result is only swapped in afterwards.
+        let (path, position, transport, trim, replay_gain, start_id) = {
+            let current = self.current.locked();
+            let track = current.as_ref().ok_or("nothing is playing")?;
+            (
+                track.path.clone(),
+                track.duration.map_or(position, |d| position.min(d)),
+                // The shared transport envelope keeps a pause that's fading out going.
+                track.transport.clone(),
+                track.trim,
+                track.replay_gain,
+                track.start_id,
+            )
+        };
+
+        // A fresh clock keeps the outgoing source from skewing the reported position.
+        let (source, mut reopened) = self.open_track(
+            &path,
+            position,
+            self.normalization(),
+            transport,
+            trim,
+            replay_gain,
+        )?;
+
+        let mut current = self.current.locked();
+        let track = current
+            .as_mut()
+            .filter(|t| t.start_id == start_id)
+            .ok_or("the track changed while seeking")?;
+        self.cut_fades();
+
+        // `clear` also drops any preloaded track; it is queued again on a later tick.
+        // Held until the sink is cleared, so a preload can't slip in between.
+        let mut preloaded = self.preloaded.locked();
+        let sink = self.sink.locked();
+        let sink = sink.as_ref().ok_or("nothing is playing")?;
+        let paused = sink.is_paused();
+        preloaded.take();
+        // `clear` pauses the sink too, so restore the previous state afterwards.
+        reopened.clock.set_loop(track.clock.loop_region());
+        reopened.start_id = track.start_id;
+        sink.clear();
+        sink.append(source);
+        if !paused {
+            sink.play();
+        }
+        *track = reopened;
+        Ok(())
+    }
+
+    /// Loops the current track over `region`, or lets it play on past the
+    /// region's end when `None`. The loop lasts until the track changes.
+    pub(crate) fn set_loop(&self, region: Option<LoopRegion>) -> Result<(), PlayerError> {
+        let current = self.current.locked();
+        let Some(track) = current.as_ref() else {
+            return match region {
+                Some(_) => Err("nothing is playing".into()),
+                None => Ok(()),
+            };
+        };
+        if let Some(region) = region {
+            if remote::is_url(&track.path) {
+                // Jumping back would mean waiting on the network mid-callback.
+                return Err("remote streams can't be looped".into());
+            }
+            if region.end_ms < region.start_ms + MIN_LOOP_MS {
+                return Err(format!("loop region must span at least {MIN_LOOP_MS} ms").into());
+            }
+            if track
+                .duration
+                .is_some_and(|d| region.end_ms > d.as_millis() as u64)
+            {
+                return Err("loop region ends after the track".into());
+            }
+            // A preloaded next track would otherwise wait behind the loop forever.
+            self.discard_preloaded();
+        }
+        track.clock.set_loop(region);
+        Ok(())
+    }
+
+    /// Cancels the preloaded track, if any. Called whenever the queue changes
+    /// so a stale "next" can't start playing.
+    pub(crate) fn discard_preloaded(&self) {
+        if let Some(track) = self.preloaded.locked().take() {
+            track.clock.cancel();
+        }
+    }
+
+    fn set_gapless(&self, enabled: bool) {
+        self.gapless.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.discard_preloaded();
+        }
+    }
+
+    /// Appends the upcoming track to the sink once the current one nears its end.
+    fn preload_next(&self, app: &AppHandle) {
+        // Crossfading takes over track transitions when enabled.
+        if !self.gapless.load(Ordering::Relaxed)
+            || !self.crossfade().is_zero()
+            || self.sleep.stops_after_track()
+            || self.preloaded.locked().is_some()
+        {
+            return;
+        }
+        let near_end = self.current.locked().as_ref().is_some_and(|t| {
+            !t.clock.is_finished()
+                && t.clock.loop_region().is_none()
+                && t.remaining().is_none_or(|r| r <= GAPLESS_PRELOAD)
+        });
+        if !near_end {
+            return;
+        }
+        let Some(path) = self.queue.locked().peek_after_finish() else {
+            return;
+        };
+        // Connecting would hold up the monitor thread for as long as the
+        // server takes; a stream starts with a regular advance instead.
+        if remote::is_url(&path) {
+            return;
+        }
+
+        let transport = Arc::new(Envelope::new(1.0));
+        let trim = silence::trim_for(app, &path);
+        let Ok((source, track)) = self.open_track(
+            &path,
+            trim.start,
+            self.normalization(),
+            transport,
+            trim,
+            loudness::measured(app, &path),
+        ) else {
+            // Fall back to a regular (gapped) advance when the track finishes.
+            return;
+        };
+        let mut preloaded = self.preloaded.locked();
+        if let Some(sink) = self.sink.locked().as_ref() {
+            sink.append(source);
+            *preloaded = Some(track);
+        }
+    }
+
+    /// Once the current source has played out and the preloaded one has taken
+    /// over in the sink, makes it current and moves the queue along.
+    fn promote_preloaded(&self, app: &AppHandle) {
+        let finished = self
+            .current
+            .locked()
+            .as_ref()
+            .is_some_and(|t| t.clock.is_finished());
+        if !finished {
+            return;
+        }
+        let Some(next) = self.preloaded.locked().take() else {
+            return;
+        };
+        {
+            let mut queue = self.queue.locked();
+            // The peeked entry can differ from a real advance when a repeating
+            // shuffle reshuffles on wrap; what's already playing wins.
+            if queue.advance_after_finish() != Some(next.path.as_str()) {
+                queue.select_path(&next.path);
+            }
+        }
+        *self.current.locked() = Some(next);
+        self.emit_state(app);
+    }
+
+    /// Starts the next queued track once the current one has played out.
+    fn advance_if_finished(&self, app: &AppHandle) {
+        let finished = self
+            .sink
+            .locked()
+            .as_ref()
+            .is_some_and(|s| !s.is_paused() && s.empty())
+            && !self.pausing.load(Ordering::Relaxed);
+        if !finished {
+            return;
+        }
+        if self.sleep.take_end_of_track() {
+            self.stop_playback(app);
+            return;
+        }
+        let mut next = self
+            .queue
+            .locked()
+            .advance_after_finish()
+            .map(str::to_string);
+        // Skip entries that fail to open, giving up after one pass over the queue.
+        let mut attempts = self.queue.locked().len();
+        while let Some(path) = next {
+            match self.start_track(&path, None, false, app) {
+                Ok(()) => return,
+                Err(error) => error.emit(app),
+            }
+            if attempts == 0 {
+                break;
+            }
+            attempts -= 1;
+            next = self.queue.locked().advance().map(str::to_string);
+        }
+        self.stop_playback(app);
+    }
+
+    /// Current position, or `None` unless a track is actively playing.
+    fn progress(&self) -> Option<PlaybackProgress> {
+        let playing = self
+            .sink
+            .locked()
+            .as_ref()
+            .is_some_and(|s| !s.is_paused() && !s.empty());
+        if !playing {
+            return None;
+        }
+        let current = self.current.locked();
+        let track = current.as_ref()?;
+        Some(PlaybackProgress {
+            position_ms: track.clock.position().as_millis() as u64,
+            duration_ms: track.duration.map(|d| d.as_millis() as u64),
+        })
+    }
+
+    /// Forwards a new ICY title announced by the current stream.
+    fn emit_stream_title(&self, app: &AppHandle) {
+        let change = self.current.locked().as_ref().and_then(|t| {
+            let title = t.stream_title.take_changed()?;
+            Some(StreamTitleChange {
+                path: t.path.clone(),
+                title,
+            })
+        });
+        if let Some(change) = change {
+            let _ = app.emit(STREAM_TITLE_EVENT, change);
+        }
+    }
+
+    /// One pass of the monitor thread.
+    fn tick(&self, app: &AppHandle) {
+        self.check_device(app);
+        self.reap_fades();
+        self.sleep.tick(self, app);
+        self.crossfade_if_due(app);
+        self.promote_preloaded(app);
+        self.advance_if_finished(app);
+        self.preload_next(app);
+        self.emit_stream_title(app);
+        if let Some(progress) = self.progress() {
+            let _ = app.emit(PLAYBACK_PROGRESS_EVENT, progress);
+        }
+    }
+
+    /// Spawns the monitor thread. Called once from the `setup` closure.
+    pub fn start_monitor(&self, app: AppHandle) -> std::io::Result<()> {
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let handle = thread::Builder::new()
+            .name("playback-monitor".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(PROGRESS_INTERVAL) {
+                    logging::guarded(|| app.state::<PlayerState>().tick(&app));
+                }
+            })?;
+        *self.monitor.locked() = Some(Monitor { stop, handle });
+        Ok(())
+    }
+
+    /// The sample tap of the track being heard, for the spectrum analyzer.
+    pub(crate) fn current_tap(&self) -> Option<Arc<SampleTap>> {
+        self.current.locked().as_ref().map(|t| t.tap.clone())
+    }
+
+    /// Stops and joins the background threads. Called on `RunEvent::Exit`.
+    pub fn shutdown(&self) {
+        self.visualizer.stop();
+        if let Some(monitor) = self.monitor.locked().take() {
+            let _ = monitor.stop.send(());
+            let _ = monitor.handle.join();
+        }
+    }
+}
+
+/// Runs `action` on the blocking pool, for tray, shortcut, and media-key
+/// events. Starting or seeking a stream can wait on the network, which
+/// mustn't stall the thread those events arrive on.
+pub(crate) fn in_background(
+    app: &AppHandle,
+    action: impl FnOnce(&PlayerState, &AppHandle) -> Result<(), PlayerError> + Send + 'static,
+) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let _ = action(&app.state::<PlayerState>(), &app);
+    });
+}
+
+/// Plays `path`, selecting it in the queue (and queueing it after the current
+/// entry if it isn't there yet) so playback continues from it.
+#[tauri::command]
+pub async fn play(path: String, app: AppHandle) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let player = app.state::<PlayerState>();
+        player.load(&path, &app)?;
+        player.queue.locked().select_path(&path);
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn pause(app: AppHandle, player: State<'_, PlayerState>) {
+    player.pause_playback(&app);
+}
+
+#[tauri::command]
+pub fn resume(app: AppHandle, player: State<'_, PlayerState>) {
+    player.resume_playback(&app);
+}
+
+#[tauri::command]
+pub fn stop(app: AppHandle, player: State<'_, PlayerState>) {
+    player.stop_playback(&app);
+}
+
+/// Repositions the current track. `position_ms` is clamped to the track duration.
+#[tauri::command]
+pub async fn seek(position_ms: u64, app: AppHandle) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<PlayerState>()
+            .seek_to(Duration::from_millis(position_ms))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Repeats `start_ms..end_ms` of the current track: playback carries on from
+/// where it is and jumps back to `start_ms` each time it reaches `end_ms`.
+/// Works at any playback speed.
+#[tauri::command]
+pub fn set_loop_region(
+    start_ms: u64,
+    end_ms: u64,
+    app: AppHandle,
+    player: State<'_, PlayerState>,
+) -> Result<(), PlayerError> {
+    player.set_loop(Some(LoopRegion { start_ms, end_ms }))?;
+    player.emit_state(&app);
+    Ok(())
+}
+
+/// Ends the loop; the track carries on from where it is.
+#[tauri::command]
+pub fn clear_loop_region(
+    app: AppHandle,
+    player: State<'_, PlayerState>,
+) -> Result<(), PlayerError> {
+    player.set_loop(None)?;
+    player.emit_state(&app);
+    Ok(())
+}
+
+/// Toggles gapless playback. Off by default since it keeps a second decoder open.
+#[tauri::command]
+pub fn set_gapless(enabled: bool, player: State<'_, PlayerState>) {
+    player.set_gapless(enabled);
+}
+
+/// Sets the crossfade applied on auto-advance and `next_track`; 0 disables it.
+#[tauri::command]
+pub fn set_crossfade(duration_ms: u64, player: State<'_, PlayerState>) {
+    player.crossfade_ms.store(duration_ms, Ordering::Relaxed);
+    if duration_ms > 0 {
+        player.discard_preloaded();
+    }
+}
+
+/// Sets the volume from a linear `0.0..=1.0` slider value, mapped through
+/// [`VOLUME_RANGE_DB`] before reaching the sink, and persists it.
+#[tauri::command]
+pub fn set_volume(
+    level: f32,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    player.set_volume_level(level);
+    let level = player.volume_level();
+    settings.update(|s| s.volume = level)
+}
+
+/// Mutes or unmutes, keeping the slider level. Returns the new mute state.
+#[tauri::command]
+pub fn toggle_mute(player: State<'_, PlayerState>) -> bool {
+    player.toggle_mute()
+}
+
+/// Sets the fade applied when playback starts, resumes, or pauses, and
+/// persists it; 0 disables it.
+#[tauri::command]
+pub fn set_fade_duration(
+    ms: u64,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    player.set_fade(Duration::from_millis(ms));
+    settings.update(|s| s.fade_ms = ms)
+}
@@ -0,0 +1,120 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+
+use crate::audio::{PlaybackStatus, PlayerState, PLAYBACK_STATE_EVENT};
+use crate::error::PlayerError;
+use crate::index::LibraryIndex;
+use crate::sync::MutexExt;
+
+/// Emitted with a track's bookmarks when it starts playing and whenever they change.
+pub const BOOKMARKS_EVENT: &str = "track-bookmarks";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bookmark {
+    pub id: i64,
+    pub path: String,
+    pub position_ms: u64,
+    pub label: String,
+    /// Unix time in milliseconds.
+    pub created_at: i64,
+}
+
+/// Payload of the `track-bookmarks` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackBookmarks {
+    pub path: String,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+fn emit_bookmarks(app: &AppHandle, index: &LibraryIndex, path: &str) {
+    if let Ok(bookmarks) = index.bookmarks(path) {
+        let _ = app.emit(
+            BOOKMARKS_EVENT,
+            TrackBookmarks {
+                path: path.to_string(),
+                bookmarks,
+            },
+        );
+    }
+}
+
+/// Sends each track's bookmarks as it's opened, for markers on the seek bar.
+pub fn start(app: &AppHandle) {
+    let handle = app.clone();
+    // Pausing and resuming re-send the same path; only a new track is announced.
+    let last = Mutex::new(None::<String>);
+    app.listen(PLAYBACK_STATE_EVENT, move |event| {
+        let Ok(status) = serde_json::from_str::<PlaybackStatus>(event.payload()) else {
+            return;
+        };
+        let mut last = last.locked();
+        if status.path == *last {
+            return;
+        }
+        last.clone_from(&status.path);
+        if let (Some(path), Some(index)) = (status.path, handle.try_state::<LibraryIndex>()) {
+            emit_bookmarks(&handle, &index, &path);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn add_bookmark(
+    path: String,
+    position_ms: u64,
+    label: String,
+    index: State<'_, LibraryIndex>,
+    app: AppHandle,
+) -> Result<Bookmark, PlayerError> {
+    let bookmark = index.add_bookmark(&path, position_ms, label.trim())?;
+    emit_bookmarks(&app, &index, &path);
+    Ok(bookmark)
+}
+
+#[tauri::command]
+pub fn remove_bookmark(
+    id: i64,
+    index: State<'_, LibraryIndex>,
+    app: AppHandle,
+) -> Result<(), PlayerError> {
+    if let Some(removed) = index.remove_bookmark(id)? {
+        emit_bookmarks(&app, &index, &removed.path);
+    }
+    Ok(())
+}
+
+/// Bookmarks in `path`, in playback order.
+#[tauri::command]
+pub fn list_bookmarks(
+    path: String,
+    index: State<'_, LibraryIndex>,
+) -> Result<Vec<Bookmark>, PlayerError> {
+    index.bookmarks(&path)
+}
+
+/// Seeks to bookmark `id` and plays from there, opening its track first if
+/// another one is loaded.
+#[tauri::command]
+pub async fn jump_to_bookmark(id: i64, app: AppHandle) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let bookmark = app
+            .state::<LibraryIndex>()
+            .bookmark(id)?
+            .ok_or_else(|| format!("no bookmark with id {id}"))?;
+        let player = app.state::<PlayerState>();
+        let position = Duration::from_millis(bookmark.position_ms);
+        if player.status().path.as_deref() == Some(bookmark.path.as_str()) {
+            player.seek_to(position)?;
+        } else {
+            player.cue(&bookmark.path, position, &app)?;
+            player.queue.locked().select_path(&bookmark.path);
+        }
+        player.resume_playback(&app);
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
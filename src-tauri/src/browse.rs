@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::error::PlayerError;
+use crate::index::{absolute, LibraryIndex};
+use crate::library::{audio_extension, TrackInfo};
+use crate::sort::{self, natural_cmp, SortField};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderEntry {
+    pub name: String,
+    pub path: String,
+}
+
+/// One directory's contents, directories and audio files each in natural order.
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderListing {
+    pub path: String,
+    pub parent: Option<String>,
+    pub folders: Vec<FolderEntry>,
+    /// Tag data is filled in for files already in the library index.
+    pub files: Vec<TrackInfo>,
+}
+
+/// Lists `dir` without descending into it. Symlinks are followed, except
+/// directory links back to `dir` or one of its ancestors, which would only
+/// lead in circles. Unreadable entries and broken links are skipped.
+pub fn list(dir: &Path, index: Option<&LibraryIndex>) -> Result<FolderListing, PlayerError> {
+    let dir = absolute(dir)?;
+    let entries = fs::read_dir(&dir).map_err(|e| PlayerError::io("read", &dir, e))?;
+    let mut folders = Vec::new();
+    let mut files = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        // `fs::metadata` follows the link, where the entry's own type doesn't.
+        let Ok(meta) = fs::metadata(&path) else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if meta.is_dir() {
+            if file_type.is_symlink() && absolute(&path).is_ok_and(|target| dir.starts_with(target))
+            {
+                continue;
+            }
+            folders.push(FolderEntry {
+                name,
+                path: path.to_string_lossy().into_owned(),
+            });
+        } else if let Some(extension) = audio_extension(&path) {
+            let key = path.to_string_lossy().into_owned();
+            let indexed = index.and_then(|index| index.track(&key).ok().flatten());
+            files.push(indexed.unwrap_or(TrackInfo {
+                path: key,
+                size: meta.len(),
+                extension,
+                metadata: None,
+                rating: None,
+            }));
+        }
+    }
+    folders.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+    files.sort_by(|a, b| sort::compare(a, b, SortField::FileName, true));
+    Ok(FolderListing {
+        path: dir.to_string_lossy().into_owned(),
+        parent: dir.parent().map(|p| p.to_string_lossy().into_owned()),
+        folders,
+        files,
+    })
+}
+
+/// Lists the subdirectories and audio files directly inside `path`, indexed
+/// or not, for browsing by folder rather than by tags.
+#[tauri::command]
+pub async fn list_folder(path: String, app: AppHandle) -> Result<FolderListing, PlayerError> {
+    // Listings of slow or removable drives block; keep them off the async runtime threads.
+    tauri::async_runtime::spawn_blocking(move || {
+        list(Path::new(&path), app.try_state::<LibraryIndex>().as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,182 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+use tauri::State;
+
+use crate::audio::PlayerState;
+use crate::dsp::{limit, AtomicGain};
+use crate::error::PlayerError;
+use crate::settings::SettingsStore;
+
+/// Each channel's share of the mono sum. -6 dB keeps fully correlated
+/// channels at their original level, so the sum can never clip.
+pub const MONO_GAIN: f32 = 0.5;
+
+/// Time constant of the glide between settings, so moving the balance
+/// slider or toggling mono doesn't click.
+const SMOOTHING: Duration = Duration::from_millis(10);
+
+/// Downmix and balance shared by every track's [`ChannelMix`] stage.
+#[derive(Debug, Default)]
+pub struct ChannelControl {
+    mono: AtomicBool,
+    balance: AtomicGain,
+}
+
+impl ChannelControl {
+    pub fn is_mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mono(&self, enabled: bool) {
+        self.mono.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn balance(&self) -> f32 {
+        self.balance.get()
+    }
+
+    /// Sets the balance, clamped to `-1.0..=1.0`; non-finite values centre it.
+    pub fn set_balance(&self, pan: f32) {
+        let pan = if pan.is_finite() {
+            pan.clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        self.balance.set(pan);
+    }
+}
+
+/// Left and right gains for `pan` under an equal-power law, scaled so the
+/// centre is unity: the summed power of both sides stays the same as the
+/// balance moves, and the louder side rises to +3 dB at the extremes.
+fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan + 1.0) * FRAC_PI_4;
+    (SQRT_2 * angle.cos(), SQRT_2 * angle.sin())
+}
+
+/// Sums stereo to mono and applies the L/R balance. Sources with any other
+/// channel count pass through untouched.
+pub struct ChannelMix<S> {
+    inner: S,
+    control: Arc<ChannelControl>,
+    /// Right sample of the frame already mixed, waiting to be emitted.
+    pending: Option<f32>,
+    /// How far the output has moved towards the mono sum, `0.0..=1.0`.
+    mono: f32,
+    gains: (f32, f32),
+    /// Per-frame smoothing factor, and the sample rate it was computed for.
+    coefficient: f32,
+    rate: u32,
+}
+
+impl<S: Source<Item = f32>> ChannelMix<S> {
+    pub fn new(inner: S, control: Arc<ChannelControl>) -> Self {
+        // Start at the current settings rather than gliding into them.
+        let mono = if control.is_mono() { 1.0 } else { 0.0 };
+        let gains = pan_gains(control.balance());
+        ChannelMix {
+            inner,
+            control,
+            pending: None,
+            mono,
+            gains,
+            coefficient: 1.0,
+            rate: 0,
+        }
+    }
+
+    fn advance_frame(&mut self) {
+        let rate = self.inner.sample_rate();
+        if rate != self.rate {
+            self.rate = rate;
+            self.coefficient = 1.0 - (-1.0 / (SMOOTHING.as_secs_f32() * rate.max(1) as f32)).exp();
+        }
+        let mono = if self.control.is_mono() { 1.0 } else { 0.0 };
+        let (left, right) = pan_gains(self.control.balance());
+        let k = self.coefficient;
+        self.mono += (mono - self.mono) * k;
+        self.gains.0 += (left - self.gains.0) * k;
+        self.gains.1 += (right - self.gains.1) * k;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for ChannelMix<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.pending.take() {
+            return Some(right);
+        }
+        if self.inner.channels() != 2 {
+            return self.inner.next();
+        }
+        let left = self.inner.next()?;
+        let Some(right) = self.inner.next() else {
+            return Some(left);
+        };
+        self.advance_frame();
+        let sum = (left + right) * MONO_GAIN;
+        let left = (left + (sum - left) * self.mono) * self.gains.0;
+        let right = (right + (sum - right) * self.mono) * self.gains.1;
+        // Only the boosted side of an off-centre balance can exceed full scale.
+        let (left, right) = if self.gains.0 > 1.0 || self.gains.1 > 1.0 {
+            (limit(left), limit(right))
+        } else {
+            (left, right)
+        };
+        self.pending = Some(right);
+        Some(left)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for ChannelMix<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        // The pending sample has already been taken from the inner source.
+        let pending = usize::from(self.pending.is_some());
+        self.inner.current_frame_len().map(|len| len + pending)
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Sums stereo tracks to mono, e.g. for a single speaker, and persists the
+/// choice. Applies to the playing track at once.
+#[tauri::command]
+pub fn set_downmix_mono(
+    enabled: bool,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    player.channels.set_mono(enabled);
+    settings.update(|s| s.downmix_mono = enabled)
+}
+
+/// Shifts the stereo balance from -1.0 (full left) through 0.0 (centre) to
+/// 1.0 (full right), and persists it. Applies to the playing track at once.
+#[tauri::command]
+pub fn set_balance(
+    pan: f32,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    if !pan.is_finite() {
+        return Err(format!("invalid balance {pan}").into());
+    }
+    player.channels.set_balance(pan);
+    let pan = player.channels.balance();
+    settings.update(|s| s.balance = pan)
+}
@@ -0,0 +1,575 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::decoder::Span;
+use crate::library::{audio_extension, TrackInfo};
+use crate::metadata::{self, TrackMetadata, UNKNOWN_ALBUM, UNKNOWN_ARTIST, UNKNOWN_GENRE};
+use crate::playlist;
+
+/// Emitted by `scan_directory` for each CUE sheet that couldn't be used.
+pub const CUE_SHEET_INVALID_EVENT: &str = "cue-sheet-invalid";
+
+/// CUE timestamps count frames of 1/75 s (CD sectors).
+const FRAMES_PER_SECOND: u64 = 75;
+
+/// Payload of the `cue-sheet-invalid` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct CueSheetError {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub year: u32,
+    pub disc: u32,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Backing audio file, resolved against the sheet's directory.
+    pub file: PathBuf,
+    /// `INDEX 01` of the track within `file`.
+    pub start: Duration,
+}
+
+impl CueSheet {
+    /// Track `i`'s slice of its backing file: it ends where the next track in
+    /// the same file starts, or with the file.
+    pub fn span(&self, i: usize) -> Span {
+        let track = &self.tracks[i];
+        let end = self
+            .tracks
+            .get(i + 1)
+            .filter(|next| next.file == track.file)
+            .map(|next| next.start);
+        Span {
+            offset: track.start,
+            end,
+        }
+    }
+}
+
+/// Whether `path` names a CUE sheet, going by its extension.
+pub fn is_sheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("cue"))
+}
+
+/// Virtual track paths are the sheet's path plus `#` and the track number,
+/// e.g. `/music/album.cue#3`, so they fit wherever a file path goes.
+pub fn virtual_path(sheet: &Path, number: u32) -> String {
+    format!("{}#{number}", sheet.display())
+}
+
+/// Splits a virtual track path into the sheet and track number.
+pub fn split_virtual(path: &str) -> Option<(&Path, u32)> {
+    let (sheet, number) = path.rsplit_once('#')?;
+    let is_cue = Path::new(sheet)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"));
+    if !is_cue {
+        return None;
+    }
+    Some((Path::new(sheet), number.parse().ok()?))
+}
+
+/// Reads the sheet behind a virtual track path and finds the track in it.
+/// `None` if `path` isn't a virtual track.
+pub fn lookup(path: &str) -> Option<Result<(CueSheet, usize), String>> {
+    let (sheet_path, number) = split_virtual(path)?;
+    Some(find(sheet_path, number))
+}
+
+/// Reads the sheet at `sheet_path` and finds track `number` in it.
+pub fn find(sheet_path: &Path, number: u32) -> Result<(CueSheet, usize), String> {
+    let sheet = read(sheet_path)?;
+    let i = sheet
+        .tracks
+        .iter()
+        .position(|t| t.number == number)
+        .ok_or_else(|| format!("{} has no track {number}", sheet_path.display()))?;
+    Ok((sheet, i))
+}
+
+pub fn read(path: &Path) -> Result<CueSheet, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    parse(&playlist::decode(&bytes), dir, path)
+        .map_err(|e| format!("invalid CUE sheet {}: {e}", path.display()))
+}
+
+/// Splits a command's arguments, keeping quoted strings together.
+fn arguments(rest: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = rest.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            args.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else {
+            let mut arg = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+            args.push(arg);
+        }
+    }
+    args
+}
+
+/// Parses `mm:ss:ff`.
+fn timestamp(text: &str) -> Option<Duration> {
+    let mut parts = text.split(':').map(|p| p.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+    let frames = (minutes * 60 + seconds) * FRAMES_PER_SECOND + frames;
+    Some(Duration::from_millis(frames * 1000 / FRAMES_PER_SECOND))
+}
+
+/// Finds the audio file a `FILE` line names. Rips are often renamed or
+/// re-encoded after the sheet was written, so this falls back to a
+/// case-insensitive match, then to the same name with another audio
+/// extension, then to an audio file named like the sheet itself.
+fn resolve_file(dir: &Path, name: &str, sheet: &Path) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let exact = dir.join(&name);
+    if exact.is_file() {
+        return Some(exact);
+    }
+    let wanted = Path::new(&name);
+    let dir = exact.parent().unwrap_or(dir);
+    let entries: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    let lower = |p: &Path, part: fn(&Path) -> Option<&std::ffi::OsStr>| {
+        part(p).map(|s| s.to_string_lossy().to_lowercase())
+    };
+    let file_name = lower(wanted, Path::file_name);
+    if let Some(found) = entries
+        .iter()
+        .find(|p| lower(p, Path::file_name) == file_name)
+    {
+        return Some(found.clone());
+    }
+    [
+        lower(wanted, Path::file_stem),
+        lower(sheet, Path::file_stem),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|stem| {
+        entries
+            .iter()
+            .find(|p| {
+                lower(p, Path::file_stem).as_deref() == Some(stem.as_str())
+                    && audio_extension(p).is_some()
+            })
+            .cloned()
+    })
+}
+
+/// Parses a CUE sheet. `dir` resolves `FILE` names; `sheet` is the sheet's
+/// own path, used when a named file can't be found.
+pub fn parse(text: &str, dir: &Path, sheet: &Path) -> Result<CueSheet, String> {
+    let mut cue = CueSheet::default();
+    let mut file: Option<PathBuf> = None;
+    // Set between an audio `TRACK` line and its `INDEX 01`.
+    let mut awaiting_index = false;
+    // Set from a data `TRACK` line to the next track, whose lines are ignored.
+    let mut in_data_track = false;
+    for (n, line) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = arguments(rest);
+        let arg = |i: usize| {
+            args.get(i)
+                .cloned()
+                .ok_or_else(|| format!("line {line_no}: {command} is missing an argument"))
+        };
+        match command.to_ascii_uppercase().as_str() {
+            "" => {}
+            "REM" => {
+                let value = args.get(1..).map(|v| v.join(" ")).unwrap_or_default();
+                match args.first().map(|k| k.to_ascii_uppercase()).as_deref() {
+                    Some("GENRE") => cue.genre = Some(value),
+                    Some("DATE") => {
+                        cue.year = value.get(..4).and_then(|y| y.parse().ok()).unwrap_or(0)
+                    }
+                    Some("DISCNUMBER") => cue.disc = value.parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+            "FILE" => {
+                let name = arg(0)?;
+                let resolved = resolve_file(dir, &name, sheet)
+                    .ok_or_else(|| format!("line {line_no}: audio file {name} not found"))?;
+                file = Some(resolved);
+            }
+            "TRACK" => {
+                if awaiting_index {
+                    return Err(format!("line {line_no}: previous track has no INDEX 01"));
+                }
+                let number = arg(0)?
+                    .parse()
+                    .map_err(|_| format!("line {line_no}: invalid track number"))?;
+                let file = file
+                    .clone()
+                    .ok_or_else(|| format!("line {line_no}: TRACK before any FILE"))?;
+                // Data tracks on mixed-mode CDs have nothing to play.
+                awaiting_index = arg(1)?.eq_ignore_ascii_case("AUDIO");
+                in_data_track = !awaiting_index;
+                if awaiting_index {
+                    cue.tracks.push(CueTrack {
+                        number,
+                        title: None,
+                        performer: None,
+                        file,
+                        start: Duration::ZERO,
+                    });
+                }
+            }
+            "INDEX" | "TITLE" | "PERFORMER" if in_data_track => {}
+            "INDEX" => {
+                let point: u32 = arg(0)?
+                    .parse()
+                    .map_err(|_| format!("line {line_no}: invalid index number"))?;
+                let time = timestamp(&arg(1)?).ok_or_else(|| {
+                    format!(
+                        "line {line_no}: invalid time {}",
+                        arg(1).unwrap_or_default()
+                    )
+                })?;
+                if point == 1 {
+                    let track = cue
+                        .tracks
+                        .last_mut()
+                        .filter(|_| awaiting_index)
+                        .ok_or_else(|| format!("line {line_no}: INDEX outside a track"))?;
+                    track.start = time;
+                    awaiting_index = false;
+                }
+            }
+            "TITLE" | "PERFORMER" => {
+                let value = Some(arg(0)?).filter(|v| !v.trim().is_empty());
+                let title = command.eq_ignore_ascii_case("TITLE");
+                match (cue.tracks.last_mut(), title) {
+                    (Some(track), true) => track.title = value,
+                    (Some(track), false) => track.performer = value,
+                    (None, true) => cue.title = value,
+                    (None, false) => cue.performer = value,
+                }
+            }
+            // CATALOG, FLAGS, ISRC, PREGAP, SONGWRITER, ... don't affect playback.
+            _ => {}
+        }
+    }
+    if awaiting_index {
+        return Err("last track has no INDEX 01".into());
+    }
+    if cue.tracks.is_empty() {
+        return Err("no tracks".into());
+    }
+    let mut seen = HashSet::new();
+    if let Some(track) = cue.tracks.iter().find(|t| !seen.insert(t.number)) {
+        return Err(format!("track {} appears twice", track.number));
+    }
+    Ok(cue)
+}
+
+/// Tag data for track `i`, drawn from the sheet. `file_duration_ms` is the
+/// length of the backing file, which bounds the last track in it.
+pub fn track_metadata(sheet: &CueSheet, i: usize, file_duration_ms: u64) -> TrackMetadata {
+    let track = &sheet.tracks[i];
+    let span = sheet.span(i);
+    let end_ms = span
+        .end
+        .map_or(file_duration_ms, |end| end.as_millis() as u64);
+    let album_artist = sheet
+        .performer
+        .clone()
+        .unwrap_or_else(|| UNKNOWN_ARTIST.to_string());
+    TrackMetadata {
+        title: track
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Track {}", track.number)),
+        artist: track
+            .performer
+            .clone()
+            .unwrap_or_else(|| album_artist.clone()),
+        album: sheet
+            .title
+            .clone()
+            .unwrap_or_else(|| UNKNOWN_ALBUM.to_string()),
+        album_artist,
+        track_number: track.number,
+        disc_number: sheet.disc,
+        year: sheet.year,
+        genre: sheet
+            .genre
+            .clone()
+            .unwrap_or_else(|| UNKNOWN_GENRE.to_string()),
+        duration_ms: end_ms.saturating_sub(span.offset.as_millis() as u64),
+    }
+}
+
+/// Metadata for a virtual track path, or `None` if `path` isn't one.
+pub fn read_metadata(path: &str) -> Option<Result<TrackMetadata, String>> {
+    Some(lookup(path)?.and_then(|(sheet, i)| {
+        let file = metadata::read(&sheet.tracks[i].file)?;
+        Ok(track_metadata(&sheet, i, file.duration_ms))
+    }))
+}
+
+/// Replaces files in `tracks` that a CUE sheet next to them splits up with
+/// that sheet's virtual tracks. Sheets that fail to parse leave their files
+/// as they are and are reported alongside.
+pub fn expand(tracks: Vec<TrackInfo>) -> (Vec<TrackInfo>, Vec<CueSheetError>) {
+    let dirs: HashSet<PathBuf> = tracks
+        .iter()
+        .filter_map(|t| Path::new(&t.path).parent().map(Path::to_path_buf))
+        .collect();
+    let mut errors = Vec::new();
+    // Virtual tracks keyed by backing file, in sheet order.
+    let mut split: BTreeMap<PathBuf, Vec<TrackInfo>> = BTreeMap::new();
+    // Size and duration of each backing file, read once however many tracks
+    // it holds; `None` if its tags couldn't be read.
+    let mut files: HashMap<PathBuf, Option<(u64, u64)>> = HashMap::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut sheets: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| is_sheet(p))
+            .collect();
+        sheets.sort();
+        for sheet_path in sheets {
+            match read(&sheet_path) {
+                Ok(sheet) => {
+                    for (i, track) in sheet.tracks.iter().enumerate() {
+                        let file = files.entry(track.file.clone()).or_insert_with(|| {
+                            let duration_ms = metadata::read(&track.file).ok()?.duration_ms;
+                            let size = fs::metadata(&track.file).map_or(0, |m| m.len());
+                            Some((size, duration_ms))
+                        });
+                        let Some((size, duration_ms)) = *file else {
+                            continue;
+                        };
+                        split
+                            .entry(track.file.clone())
+                            .or_default()
+                            .push(TrackInfo {
+                                path: virtual_path(&sheet_path, track.number),
+                                size,
+                                extension: audio_extension(&track.file).unwrap_or_default(),
+                                metadata: Some(track_metadata(&sheet, i, duration_ms)),
+                                rating: None,
+                            });
+                    }
+                }
+                Err(error) => errors.push(CueSheetError {
+                    path: sheet_path.to_string_lossy().into_owned(),
+                    error,
+                }),
+            }
+        }
+    }
+    let mut expanded = Vec::with_capacity(tracks.len());
+    for track in tracks {
+        match split.remove(Path::new(&track.path)) {
+            Some(virtual_tracks) => expanded.extend(virtual_tracks),
+            None => expanded.push(track),
+        }
+    }
+    (expanded, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory of empty files, removed again on drop.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn with_files(name: &str, files: &[&str]) -> Self {
+            let dir = std::env::temp_dir().join(format!("cue-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            for file in files {
+                fs::write(dir.join(file), b"").unwrap();
+            }
+            Dir(dir)
+        }
+
+        fn parse(&self, text: &str) -> Result<CueSheet, String> {
+            parse(text, &self.0, &self.0.join("album.cue"))
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const SHEET: &str = r#"
+REM GENRE "Progressive Rock"
+REM DATE 1973-03-01
+REM DISCNUMBER 2
+PERFORMER "The Band"
+TITLE "The Album"
+FILE "album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Opening"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    PERFORMER "A Guest"
+    INDEX 00 03:58:50
+    INDEX 01 04:00:00
+  TRACK 03 AUDIO
+    INDEX 01 09:12:37
+"#;
+
+    #[test]
+    fn parses_sheet_and_track_fields() {
+        let dir = Dir::with_files("fields", &["album.flac"]);
+        let sheet = dir.parse(SHEET).unwrap();
+        assert_eq!(sheet.title.as_deref(), Some("The Album"));
+        assert_eq!(sheet.performer.as_deref(), Some("The Band"));
+        assert_eq!(sheet.genre.as_deref(), Some("Progressive Rock"));
+        assert_eq!((sheet.year, sheet.disc), (1973, 2));
+
+        let numbers: Vec<u32> = sheet.tracks.iter().map(|t| t.number).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert!(sheet
+            .tracks
+            .iter()
+            .all(|t| t.file == dir.0.join("album.flac")));
+        let second = &sheet.tracks[1];
+        assert_eq!(second.title.as_deref(), Some("Second"));
+        assert_eq!(second.performer.as_deref(), Some("A Guest"));
+        // INDEX 01 marks the start; the INDEX 00 pregap belongs to the track before.
+        assert_eq!(second.start, Duration::from_secs(240));
+        // 37 frames of 1/75 s.
+        assert_eq!(sheet.tracks[2].start, Duration::from_millis(552_493));
+        assert_eq!(sheet.tracks[2].title, None);
+    }
+
+    #[test]
+    fn spans_end_where_the_next_track_in_the_file_starts() {
+        let dir = Dir::with_files("spans", &["album.flac"]);
+        let sheet = dir.parse(SHEET).unwrap();
+        assert_eq!(sheet.span(0).offset, Duration::ZERO);
+        assert_eq!(sheet.span(0).end, Some(Duration::from_secs(240)));
+        assert_eq!(sheet.span(2).end, None);
+    }
+
+    #[test]
+    fn tracks_in_separate_files_play_to_the_end_of_their_file() {
+        let dir = Dir::with_files("files", &["one.wav", "two.wav"]);
+        let sheet = dir
+            .parse(
+                "FILE one.wav WAVE\n TRACK 1 AUDIO\n  INDEX 01 00:00:00\n\
+                 FILE two.wav WAVE\n TRACK 2 AUDIO\n  INDEX 01 00:02:00\n",
+            )
+            .unwrap();
+        assert_eq!(sheet.span(0).end, None);
+        assert_eq!(sheet.tracks[1].file, dir.0.join("two.wav"));
+        assert_eq!(sheet.span(1).offset, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn skips_data_tracks() {
+        let dir = Dir::with_files("data", &["album.flac"]);
+        let sheet = dir
+            .parse(
+                "FILE album.flac WAVE\n TRACK 1 MODE1/2352\n  TITLE Data\n  INDEX 01 00:00:00\n\
+                 TRACK 2 AUDIO\n  INDEX 01 01:00:00\n",
+            )
+            .unwrap();
+        let numbers: Vec<u32> = sheet.tracks.iter().map(|t| t.number).collect();
+        assert_eq!(numbers, [2]);
+        assert_eq!(sheet.title, None);
+    }
+
+    #[test]
+    fn finds_renamed_and_reencoded_files() {
+        let one_track =
+            |name: &str| format!("FILE \"{name}\" WAVE\nTRACK 1 AUDIO\nINDEX 01 00:00:00");
+        let dir = Dir::with_files("renamed", &["Album.FLAC", "notes.txt"]);
+        let file = |name: &str| dir.parse(&one_track(name)).unwrap().tracks[0].file.clone();
+        // Different case.
+        assert_eq!(file("album.flac"), dir.0.join("Album.FLAC"));
+        // Re-encoded with another extension.
+        assert_eq!(file("album.wav"), dir.0.join("Album.FLAC"));
+        // Named after the sheet when nothing else matches.
+        assert_eq!(file("CDImage.ape"), dir.0.join("Album.FLAC"));
+        // Windows separators.
+        let nested = Dir::with_files("nested", &[]);
+        fs::create_dir_all(nested.0.join("disc 1")).unwrap();
+        fs::write(nested.0.join("disc 1").join("a.mp3"), b"").unwrap();
+        let sheet = nested.parse(&one_track(r"disc 1\a.mp3")).unwrap();
+        assert_eq!(sheet.tracks[0].file, nested.0.join("disc 1").join("a.mp3"));
+    }
+
+    #[test]
+    fn rejects_broken_sheets() {
+        let dir = Dir::with_files("broken", &["album.flac"]);
+        let broken = [
+            ("TRACK 1 AUDIO\nINDEX 01 00:00:00", "before any FILE"),
+            ("FILE album.flac WAVE\nTRACK 1 AUDIO\nTRACK 2 AUDIO", "no INDEX 01"),
+            ("FILE album.flac WAVE\nTRACK 1 AUDIO", "no INDEX 01"),
+            ("FILE album.flac WAVE\nTRACK 1 AUDIO\nINDEX 01 00:61:00", "invalid time"),
+            ("FILE album.flac WAVE\nTRACK one AUDIO", "invalid track number"),
+            ("FILE album.flac WAVE\nINDEX 01 00:00:00", "outside a track"),
+            ("FILE album.flac WAVE", "no tracks"),
+            (
+                "FILE album.flac WAVE\nTRACK 1 AUDIO\nINDEX 01 00:00:00\nTRACK 1 AUDIO\nINDEX 01 00:01:00",
+                "appears twice",
+            ),
+        ];
+        for (text, expected) in broken {
+            let error = dir.parse(text).unwrap_err();
+            assert!(error.contains(expected), "{text:?}: {error}");
+        }
+        let empty = Dir::with_files("missing", &["notes.txt"]);
+        let error = empty.parse("FILE album.flac WAVE").unwrap_err();
+        assert!(error.contains("not found"), "{error}");
+    }
+
+    #[test]
+    fn virtual_paths_round_trip() {
+        let path = virtual_path(Path::new("/music/album.cue"), 3);
+        assert_eq!(path, "/music/album.cue#3");
+        assert_eq!(
+            split_virtual(&path),
+            Some((Path::new("/music/album.cue"), 3))
+        );
+        assert_eq!(split_virtual("/music/song#1.flac"), None);
+        assert_eq!(split_virtual("/music/album.cue#x"), None);
+    }
+}
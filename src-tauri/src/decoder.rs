@@ -0,0 +1,632 @@
+use std::fs::File;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+use crate::cue;
+use crate::error::PlayerError;
+use crate::remote::{HttpMedia, StreamBuffer, StreamTitle};
+
+/// Silence played at a time while a remote stream buffers, before checking
+/// again whether the next packet has arrived.
+const STALL: Duration = Duration::from_millis(20);
+
+/// Shared between a source on the audio thread and the engine: reports the
+/// source's position and lets the engine cut it short.
+#[derive(Debug, Default)]
+pub struct PlaybackClock {
+    start_ms: AtomicU64,
+    frames: AtomicU64,
+    sample_rate: AtomicU64,
+    finished: AtomicBool,
+    cancelled: AtomicBool,
+    /// Loop region bounds in milliseconds; an end of 0 means no loop.
+    loop_start_ms: AtomicU64,
+    loop_end_ms: AtomicU64,
+}
+
+/// A stretch of a track repeated until cleared, relative to the track start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopRegion {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl LoopRegion {
+    fn start(self) -> Duration {
+        Duration::from_millis(self.start_ms)
+    }
+
+    fn end(self) -> Duration {
+        Duration::from_millis(self.end_ms)
+    }
+}
+
+impl PlaybackClock {
+    /// Whether the source has played out (or was cancelled).
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Makes the source end at its next sample, e.g. to drop a preloaded track
+    /// still waiting in the sink.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn position(&self) -> Duration {
+        let rate = self.sample_rate.load(Ordering::Relaxed).max(1);
+        let frames = self.frames.load(Ordering::Relaxed);
+        Duration::from_millis(self.start_ms.load(Ordering::Relaxed))
+            + Duration::from_secs_f64(frames as f64 / rate as f64)
+    }
+
+    /// The region the source is looping over, if any.
+    pub fn loop_region(&self) -> Option<LoopRegion> {
+        let end_ms = self.loop_end_ms.load(Ordering::Relaxed);
+        (end_ms > 0).then(|| LoopRegion {
+            start_ms: self.loop_start_ms.load(Ordering::Relaxed),
+            end_ms,
+        })
+    }
+
+    /// Makes the source jump back to the region's start each time it reaches
+    /// its end; `None` lets it play on.
+    pub fn set_loop(&self, region: Option<LoopRegion>) {
+        let region = region.filter(|r| r.end_ms > r.start_ms);
+        self.loop_end_ms.store(0, Ordering::Relaxed);
+        if let Some(region) = region {
+            self.loop_start_ms.store(region.start_ms, Ordering::Relaxed);
+            self.loop_end_ms.store(region.end_ms, Ordering::Relaxed);
+        }
+    }
+
+    fn reset(&self, start: Duration, sample_rate: u32) {
+        self.start_ms
+            .store(start.as_millis() as u64, Ordering::Relaxed);
+        self.frames.store(0, Ordering::Relaxed);
+        self.sample_rate
+            .store(sample_rate as u64, Ordering::Relaxed);
+    }
+}
+
+/// The part of a file a track covers, for tracks cut from a larger file by a
+/// CUE sheet. The default is the whole file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub offset: Duration,
+    /// Where the track stops, or `None` to play to the end of the file.
+    pub end: Option<Duration>,
+}
+
+/// Demuxer and decoder for the first audio track of a file.
+pub(crate) struct Stream {
+    pub(crate) format: Box<dyn FormatReader>,
+    pub(crate) decoder: Box<dyn Decoder>,
+    pub(crate) track_id: u32,
+    pub(crate) params: CodecParameters,
+}
+
+impl Stream {
+    pub(crate) fn open(path: &Path) -> Result<Self, PlayerError> {
+        let file = File::open(path).map_err(|e| PlayerError::io("open", path, e))?;
+        Self::probe(
+            Box::new(file),
+            path.extension().and_then(|ext| ext.to_str()),
+        )
+    }
+
+    fn probe(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<Self, PlayerError> {
+        let stream = MediaSourceStream::new(source, Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = extension {
+            hint.with_extension(ext);
+        }
+        let format_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &format_opts, &MetadataOptions::default())
+            .map_err(|e| PlayerError::unsupported(format!("unsupported format: {e}")))?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| PlayerError::unsupported("no playable audio track"))?;
+        let params = track.codec_params.clone();
+        let track_id = track.id;
+        let decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions::default())
+            .map_err(|e| PlayerError::unsupported(format!("unsupported codec: {e}")))?;
+        Ok(Stream {
+            format,
+            decoder,
+            track_id,
+            params,
+        })
+    }
+}
+
+/// The file holding `path`'s audio and the part of it `path` covers: all of
+/// `path` itself, or a CUE sheet's virtual track's part of its image.
+pub fn locate(path: &Path) -> Result<(PathBuf, Span), PlayerError> {
+    match path.to_str().and_then(cue::lookup) {
+        Some(found) => {
+            let (sheet, i) = found?;
+            Ok((sheet.tracks[i].file.clone(), sheet.span(i)))
+        }
+        None => Ok((path.to_path_buf(), Span::default())),
+    }
+}
+
+/// Seeks `format` to `position`, returning the timestamp decoding must reach
+/// before samples count; the first packet usually starts a little earlier.
+pub(crate) fn seek_accurate(
+    format: &mut dyn FormatReader,
+    track_id: u32,
+    position: Duration,
+) -> Result<u64, PlayerError> {
+    let time = Time::new(position.as_secs(), position.subsec_nanos() as f64 / 1e9);
+    // Accurate mode lets the demuxer scan packets instead of estimating a
+    // byte offset, which is what keeps VBR streams on the right sample.
+    let seeked = format
+        .seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time,
+                track_id: Some(track_id),
+            },
+        )
+        .map_err(|e| PlayerError::decode(format!("seek failed: {e}")))?;
+    Ok(seeked.required_ts)
+}
+
+/// Decodes `path` at the file's own rate, passing each frame mixed down to
+/// mono to `frame` until the file ends or `frame` breaks. Returns that rate.
+pub fn decode_mono(
+    path: &Path,
+    mut frame: impl FnMut(f32) -> ControlFlow<()>,
+) -> Result<u32, PlayerError> {
+    decode_interleaved(path, |samples, channels, _| {
+        for samples in samples.chunks_exact(channels) {
+            frame(samples.iter().sum::<f32>() / channels as f32)?;
+        }
+        ControlFlow::Continue(())
+    })
+}
+
+/// Decodes `path` at the file's own rate, passing the interleaved samples of
+/// each packet to `packet` with their channel count and sample rate, until
+/// the track ends or `packet` breaks. Returns the last sample rate seen.
+///
+/// A CUE sheet's virtual track decodes only its part of the image.
+pub fn decode_interleaved(
+    path: &Path,
+    mut packet: impl FnMut(&[f32], usize, u32) -> ControlFlow<()>,
+) -> Result<u32, PlayerError> {
+    let (file, span) = locate(path)?;
+    let Stream {
+        mut format,
+        mut decoder,
+        track_id,
+        params,
+    } = Stream::open(&file)?;
+    let mut rate = params.sample_rate.unwrap_or(44_100);
+    let mut skip_until = match span.offset.is_zero() {
+        true => 0,
+        false => seek_accurate(format.as_mut(), track_id, span.offset)?,
+    };
+    let length = span.end.map(|end| end.saturating_sub(span.offset));
+    let mut frames: u64 = 0;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let next = match format.next_packet() {
+            Ok(next) => next,
+            Err(SymphoniaError::ResetRequired) => {
+                decoder.reset();
+                continue;
+            }
+            Err(_) => break,
+        };
+        if next.track_id() != track_id || next.ts() + next.dur() <= skip_until {
+            continue;
+        }
+        let decoded = match decoder.decode(&next) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(PlayerError::decode(format!("decode failed: {e}"))),
+        };
+        let spec = *decoded.spec();
+        rate = spec.rate;
+        let buffer = match &mut buffer {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() => buffer,
+            slot => slot.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        let channels = spec.channels.count().max(1);
+        let skip = (skip_until.saturating_sub(next.ts()) as usize * channels).min(buffer.len());
+        skip_until = 0;
+        let mut samples = &buffer.samples()[skip..];
+        let total = length.map(|length| (length.as_secs_f64() * rate as f64).round() as u64);
+        if let Some(total) = total {
+            let left = total.saturating_sub(frames) as usize;
+            samples = &samples[..samples.len().min(left * channels)];
+        }
+        frames += (samples.len() / channels) as u64;
+        if packet(samples, channels, rate).is_break() || total.is_some_and(|t| frames >= t) {
+            break;
+        }
+    }
+    Ok(rate)
+}
+
+/// A `rodio::Source` decoding a local file or HTTP stream with symphonia.
+///
+/// rodio's own decoder can't reposition reliably, so seeking reopens the file
+/// through [`TrackSource::open_span`] with a start offset instead.
+pub struct TrackSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    buffer: Option<SampleBuffer<f32>>,
+    cursor: usize,
+    channels: u16,
+    sample_rate: u32,
+    duration: Option<Duration>,
+    skip_until: u64,
+    emitted: u64,
+    span: Span,
+    /// Frames left before the end of the span, for tracks that stop mid-file.
+    end_frames: Option<u64>,
+    clock: Arc<PlaybackClock>,
+    /// Download buffer of a remote stream; packets are only decoded once it
+    /// holds enough, so the audio thread never waits on the network.
+    remote: Option<StreamBuffer>,
+    /// Samples of silence left to play while `remote` buffers.
+    stall: usize,
+}
+
+impl TrackSource {
+    /// Opens the `span` of `path` as though it were a file of its own:
+    /// `start`, the reported position, and the duration are all relative to
+    /// the span, and the source ends with it. `start` is clamped to the
+    /// duration; [`Span::default`] opens the whole file.
+    pub fn open_span(
+        path: &Path,
+        span: Span,
+        start: Duration,
+        clock: Arc<PlaybackClock>,
+    ) -> Result<Self, PlayerError> {
+        Self::from_stream(Stream::open(path)?, span, start, clock)
+    }
+
+    /// Streams `url` over HTTP, starting at `start` when the server allows
+    /// seeking and from wherever a live broadcast is otherwise. Blocks until
+    /// the first packets have arrived; after that the source plays silence
+    /// whenever the download falls behind.
+    pub fn open_url(
+        url: &str,
+        start: Duration,
+        clock: Arc<PlaybackClock>,
+        title: Arc<StreamTitle>,
+    ) -> Result<Self, PlayerError> {
+        let media = HttpMedia::open(url, title).map_err(|message| PlayerError::IoError {
+            path: Some(url.to_string()),
+            message,
+        })?;
+        let start = if media.is_seekable() {
+            start
+        } else {
+            Duration::ZERO
+        };
+        let extension = media.extension_hint();
+        let buffer = media.stream_buffer();
+        let mut source = Self::from_stream(
+            Stream::probe(Box::new(media), extension.as_deref())?,
+            Span::default(),
+            start,
+            clock,
+        )?;
+        buffer.stop_blocking();
+        source.remote = Some(buffer);
+        Ok(source)
+    }
+
+    fn from_stream(
+        stream: Stream,
+        span: Span,
+        start: Duration,
+        clock: Arc<PlaybackClock>,
+    ) -> Result<Self, PlayerError> {
+        let Stream {
+            format,
+            decoder,
+            track_id,
+            params,
+        } = stream;
+        let file_duration = match (params.time_base, params.n_frames) {
+            (Some(tb), Some(frames)) => Some(time_to_duration(tb.calc_time(frames))),
+            _ => None,
+        };
+        let duration = span
+            .end
+            .or(file_duration)
+            .map(|end| end.saturating_sub(span.offset));
+
+        let mut source = TrackSource {
+            track_id,
+            channels: params.channels.map_or(2, |c| c.count() as u16),
+            sample_rate: params.sample_rate.unwrap_or(44_100),
+            format,
+            decoder,
+            buffer: None,
+            cursor: 0,
+            duration,
+            skip_until: 0,
+            emitted: 0,
+            span,
+            end_frames: None,
+            clock,
+            remote: None,
+            stall: 0,
+        };
+
+        let start = duration.map_or(start, |d| start.min(d));
+        let from = span.offset + start;
+        if !from.is_zero() {
+            source.seek_to(from)?;
+        }
+        // Decode ahead so the stream parameters reported to rodio are the real ones.
+        if !source.fill_buffer() {
+            source.buffer = None;
+        }
+        source.stop_at_span_end(from);
+        source.clock.reset(start, source.sample_rate);
+        Ok(source)
+    }
+
+    /// Counts the frames left in the span from file position `from`.
+    fn stop_at_span_end(&mut self, from: Duration) {
+        self.end_frames = self.span.end.map(|end| {
+            (end.saturating_sub(from).as_secs_f64() * self.sample_rate as f64).round() as u64
+        });
+    }
+
+    /// Jumps back to the start of the loop region once its end is reached.
+    /// Checked between frames on the audio thread, so the repeat neither
+    /// overshoots the end nor leaves a gap; speed changes happen downstream.
+    fn loop_if_due(&mut self) {
+        let Some(region) = self.clock.loop_region() else {
+            return;
+        };
+        if self.clock.cancelled.load(Ordering::Relaxed) || self.clock.position() < region.end() {
+            return;
+        }
+        let from = self.span.offset + region.start();
+        if self.seek_to(from).is_err() {
+            // Streams that can't seek just play on past the end.
+            self.clock.set_loop(None);
+            return;
+        }
+        if !self.fill_buffer() {
+            self.buffer = None;
+        }
+        self.stop_at_span_end(from);
+        self.clock.reset(region.start(), self.sample_rate);
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn seek_to(&mut self, position: Duration) -> Result<(), PlayerError> {
+        self.skip_until = seek_accurate(self.format.as_mut(), self.track_id, position)?;
+        self.decoder.reset();
+        Ok(())
+    }
+
+    /// Decodes the next samples once the current ones have played, or pads
+    /// with silence while a remote stream is still downloading them.
+    fn refill(&mut self) {
+        if self.remote.as_ref().is_some_and(|r| !r.is_ready()) {
+            let frames = (self.sample_rate as f64 * STALL.as_secs_f64()) as usize;
+            self.stall = frames.max(1) * self.channels.max(1) as usize;
+        } else if !self.fill_buffer() {
+            self.buffer = None;
+        }
+    }
+
+    /// Decodes packets until samples are available. Returns `false` at end of stream.
+    fn fill_buffer(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            if packet.ts() + packet.dur() <= self.skip_until {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            };
+            let spec = *decoded.spec();
+            let buffer = match &mut self.buffer {
+                Some(buffer) if buffer.capacity() >= decoded.capacity() => buffer,
+                slot => slot.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+            };
+            buffer.copy_interleaved_ref(decoded);
+            self.channels = spec.channels.count() as u16;
+            self.sample_rate = spec.rate;
+
+            // Drop the leading part of the first packet after an accurate seek.
+            let skip_frames = self.skip_until.saturating_sub(packet.ts()) as usize;
+            self.cursor = (skip_frames * self.channels as usize).min(buffer.len());
+            self.skip_until = 0;
+            if self.cursor < buffer.len() {
+                return true;
+            }
+        }
+    }
+}
+
+impl Iterator for TrackSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.emitted.is_multiple_of(self.channels.max(1) as u64) {
+            self.loop_if_due();
+        }
+        let span_over = self
+            .end_frames
+            .is_some_and(|end| self.clock.frames.load(Ordering::Relaxed) >= end);
+        if span_over || self.clock.cancelled.load(Ordering::Relaxed) {
+            self.buffer = None;
+        }
+        let Some(buffer) = self.buffer.as_ref() else {
+            self.clock.finished.store(true, Ordering::Relaxed);
+            return None;
+        };
+        if self.stall > 0 {
+            // The position holds still while nothing of the track is heard.
+            self.stall -= 1;
+            if self.stall == 0 {
+                self.refill();
+            }
+            return Some(0.0);
+        }
+        let sample = buffer.samples()[self.cursor];
+        self.cursor += 1;
+        self.emitted += 1;
+        if self.emitted.is_multiple_of(self.channels.max(1) as u64) {
+            self.clock.frames.fetch_add(1, Ordering::Relaxed);
+        }
+        // Refill eagerly so `current_frame_len` always describes the next samples.
+        if self.cursor >= buffer.len() {
+            self.refill();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for TrackSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.stall > 0 {
+            return Some(self.stall);
+        }
+        Some(self.buffer.as_ref().map_or(0, |b| b.len() - self.cursor))
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+fn time_to_duration(time: Time) -> Duration {
+    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const RATE: u32 = 8000;
+
+    /// 16-bit mono PCM holding `levels[i]` throughout second `i`.
+    fn wav(levels: &[f32]) -> Vec<u8> {
+        let samples: Vec<i16> = levels
+            .iter()
+            .flat_map(|&level| std::iter::repeat_n((level * i16::MAX as f32) as i16, RATE as usize))
+            .collect();
+        let data_len = samples.len() as u32 * 2;
+        let mut out = Vec::new();
+        out.extend(b"RIFF");
+        out.extend((36 + data_len).to_le_bytes());
+        out.extend(b"WAVEfmt ");
+        out.extend(16u32.to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        out.extend(RATE.to_le_bytes());
+        out.extend((RATE * 2).to_le_bytes());
+        out.extend(2u16.to_le_bytes());
+        out.extend(16u16.to_le_bytes());
+        out.extend(b"data");
+        out.extend(data_len.to_le_bytes());
+        out.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+        out
+    }
+
+    /// Decodes `path`, returning its samples.
+    fn decode(path: &Path) -> Vec<f32> {
+        let mut samples = Vec::new();
+        decode_mono(path, |sample| {
+            samples.push(sample);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        samples
+    }
+
+    #[test]
+    fn cue_tracks_decode_only_their_span() {
+        let dir = std::env::temp_dir().join(format!("decoder-cue-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("album.wav"), wav(&[0.25, 0.5, 0.75])).unwrap();
+        fs::write(
+            dir.join("album.cue"),
+            "FILE \"album.wav\" WAVE\n\
+             TRACK 01 AUDIO\n INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n INDEX 01 00:01:00\n\
+             TRACK 03 AUDIO\n INDEX 01 00:02:00\n",
+        )
+        .unwrap();
+        let sheet = dir.join("album.cue");
+        let track = |n| decode(Path::new(&cue::virtual_path(&sheet, n)));
+
+        let (first, second, last) = (track(1), track(2), track(3));
+        let whole = decode(&dir.join("album.wav"));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(whole.len(), 3 * RATE as usize);
+        for (samples, level) in [(first, 0.25), (second, 0.5), (last, 0.75)] {
+            assert_eq!(samples.len(), RATE as usize);
+            assert!(samples.iter().all(|s| (s - level).abs() < 1e-3), "{level}");
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+/// An `f32` that can be shared with the audio thread.
+#[derive(Debug, Default)]
+pub struct AtomicGain(AtomicU32);
+
+impl AtomicGain {
+    pub fn new(gain: f32) -> Self {
+        AtomicGain(AtomicU32::new(gain.to_bits()))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Samples above this magnitude are soft-limited by [`Normalized`].
+pub const LIMITER_THRESHOLD: f32 = 0.95;
+
+/// Soft knee that keeps `x` strictly inside `-1.0..1.0`.
+pub fn limit(x: f32) -> f32 {
+    let magnitude = x.abs();
+    if magnitude <= LIMITER_THRESHOLD {
+        return x;
+    }
+    let headroom = 1.0 - LIMITER_THRESHOLD;
+    x.signum()
+        * (LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh())
+}
+
+/// Applies a normalization gain followed by a safety limiter, so a badly
+/// tagged file can't clip the output.
+pub struct Normalized<S> {
+    inner: S,
+    gain: Arc<AtomicGain>,
+}
+
+impl<S> Normalized<S> {
+    pub fn new(inner: S, gain: Arc<AtomicGain>) -> Self {
+        Normalized { inner, gain }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Normalized<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        let gain = self.gain.get();
+        // Unity gain passes through untouched rather than through the limiter.
+        Some(if gain == 1.0 {
+            sample
+        } else {
+            limit(sample * gain)
+        })
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Normalized<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Gain ramp requested by the engine and applied per frame on the audio thread.
+///
+/// Each call to [`Envelope::ramp_to`] starts from whatever gain the source has
+/// actually reached, so overlapping ramps never leave it stuck part-way.
+#[derive(Debug)]
+pub struct Envelope {
+    /// Gain the ramp jumps to before it starts; NaN to start from the
+    /// gain already reached.
+    from: AtomicU32,
+    target: AtomicU32,
+    ramp_ms: AtomicU64,
+    generation: AtomicU64,
+}
+
+impl Envelope {
+    pub fn new(gain: f32) -> Self {
+        Envelope {
+            from: AtomicU32::new(f32::NAN.to_bits()),
+            target: AtomicU32::new(gain.to_bits()),
+            ramp_ms: AtomicU64::new(0),
+            generation: AtomicU64::new(1),
+        }
+    }
+
+    pub fn ramp_to(&self, gain: f32, over: Duration) {
+        self.ramp_from(f32::NAN, gain, over);
+    }
+
+    /// Like [`Envelope::ramp_to`], but starting from `from`, e.g. to fade in a
+    /// source that was paused at full gain.
+    pub fn ramp_from(&self, from: f32, gain: f32, over: Duration) {
+        self.from.store(from.to_bits(), Ordering::Relaxed);
+        self.target.store(gain.to_bits(), Ordering::Relaxed);
+        self.ramp_ms
+            .store(over.as_millis() as u64, Ordering::Relaxed);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Applies an [`Envelope`] to the wrapped source.
+pub struct Faded<S> {
+    inner: S,
+    envelope: Arc<Envelope>,
+    seen: u64,
+    gain: f32,
+    target: f32,
+    step: f32,
+    remaining: u64,
+    sample_in_frame: u16,
+}
+
+impl<S: Source<Item = f32>> Faded<S> {
+    pub fn new(inner: S, envelope: Arc<Envelope>) -> Self {
+        Faded {
+            inner,
+            envelope,
+            seen: 0,
+            gain: 0.0,
+            target: 0.0,
+            step: 0.0,
+            remaining: 0,
+            sample_in_frame: 0,
+        }
+    }
+
+    fn advance_frame(&mut self) {
+        let generation = self.envelope.generation.load(Ordering::Acquire);
+        if generation != self.seen {
+            self.seen = generation;
+            let from = f32::from_bits(self.envelope.from.load(Ordering::Relaxed));
+            if !from.is_nan() {
+                self.gain = from;
+            }
+            self.target = f32::from_bits(self.envelope.target.load(Ordering::Relaxed));
+            let ramp_ms = self.envelope.ramp_ms.load(Ordering::Relaxed);
+            self.remaining = ramp_ms * self.inner.sample_rate() as u64 / 1000;
+            if self.remaining == 0 {
+                self.gain = self.target;
+            } else {
+                self.step = (self.target - self.gain) / self.remaining as f32;
+            }
+        }
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.gain = if self.remaining == 0 {
+                self.target
+            } else {
+                self.gain + self.step
+            };
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Faded<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        if self.sample_in_frame == 0 {
+            self.advance_frame();
+        }
+        self.sample_in_frame = (self.sample_in_frame + 1) % self.inner.channels().max(1);
+        Some(sample * self.gain)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Faded<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Outermost stage of a track: plays on rodio's callback thread, where
+/// [`crate::logging::guarded`] doesn't reach, so a panic anywhere in the
+/// chain would take the output down. Instead it's logged by the panic hook
+/// and the track ends there, letting the queue move on.
+pub struct Guarded<S> {
+    inner: S,
+    failed: bool,
+}
+
+impl<S> Guarded<S> {
+    pub fn new(inner: S) -> Self {
+        Guarded {
+            inner,
+            failed: false,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Guarded<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.failed {
+            return None;
+        }
+        let sample = panic::catch_unwind(AssertUnwindSafe(|| self.inner.next()));
+        sample.unwrap_or_else(|_| {
+            self.failed = true;
+            None
+        })
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Guarded<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.failed {
+            Some(0)
+        } else {
+            self.inner.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use serde::Deserialize;
+use tauri::{AppHandle, Manager};
+
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::index::LibraryIndex;
+use crate::library::TrackInfo;
+
+/// Durations closer than this count as the same length.
+pub const DURATION_TOLERANCE_MS: u64 = 2_000;
+
+/// Mono frames hashed for a content fingerprint, about six seconds at 44.1 kHz.
+const FINGERPRINT_FRAMES: usize = 1 << 18;
+
+/// Samples quieter than this are treated as leading silence, which encoders
+/// pad differently.
+const SILENCE_THRESHOLD: f32 = 1.0e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateStrategy {
+    /// Same artist and title, with durations within [`DURATION_TOLERANCE_MS`].
+    Metadata,
+    /// Same decoded audio, regardless of tags or container.
+    Content,
+}
+
+fn duration_ms(track: &TrackInfo) -> u64 {
+    track.metadata.as_ref().map_or(0, |m| m.duration_ms)
+}
+
+/// Case- and whitespace-insensitive form of a tag value.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Splits `tracks` into runs whose neighbouring durations are within the
+/// tolerance, dropping runs with a single track.
+fn by_duration(mut tracks: Vec<TrackInfo>) -> Vec<Vec<TrackInfo>> {
+    tracks.sort_by_key(duration_ms);
+    let mut groups: Vec<Vec<TrackInfo>> = Vec::new();
+    for track in tracks {
+        match groups.last_mut() {
+            Some(group)
+                if duration_ms(&track) - duration_ms(group.last().unwrap())
+                    <= DURATION_TOLERANCE_MS =>
+            {
+                group.push(track)
+            }
+            _ => groups.push(vec![track]),
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+fn by_metadata(tracks: Vec<TrackInfo>) -> Vec<Vec<TrackInfo>> {
+    let mut by_name: HashMap<(String, String), Vec<TrackInfo>> = HashMap::new();
+    for track in tracks {
+        let Some(tags) = &track.metadata else {
+            continue;
+        };
+        let key = (normalize(&tags.artist), normalize(&tags.title));
+        by_name.entry(key).or_default().push(track);
+    }
+    by_name.into_values().flat_map(by_duration).collect()
+}
+
+/// Hashes a stretch of the file's audio after any leading silence. Samples
+/// are mixed to mono and quantised to 16 bits, so the same audio matches
+/// whether it's stored as FLAC, WAV, or with different tags.
+fn fingerprint(path: &Path) -> Result<[u8; 16], String> {
+    let mut hash = md5::Context::new();
+    let mut hashed = 0;
+    decoder::decode_mono(path, |sample| {
+        if hashed == 0 && sample.abs() < SILENCE_THRESHOLD {
+            return ControlFlow::Continue(());
+        }
+        let quantized = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        hash.consume(quantized.to_le_bytes());
+        hashed += 1;
+        if hashed == FINGERPRINT_FRAMES {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })?;
+    Ok(hash.compute().0)
+}
+
+fn by_content(tracks: Vec<TrackInfo>) -> Vec<Vec<TrackInfo>> {
+    // Only tracks of about the same length can hold the same audio, so most
+    // of the library never needs decoding.
+    let mut groups = Vec::new();
+    for candidates in by_duration(tracks) {
+        let mut by_hash: HashMap<[u8; 16], Vec<TrackInfo>> = HashMap::new();
+        for track in candidates {
+            // Files that no longer decode can't be compared; leave them out.
+            if let Ok(hash) = fingerprint(Path::new(&track.path)) {
+                by_hash.entry(hash).or_default().push(track);
+            }
+        }
+        groups.extend(by_hash.into_values().filter(|group| group.len() > 1));
+    }
+    groups
+}
+
+/// Groups indexed tracks that are likely the same recording. Each group has
+/// at least two tracks, sorted by path; groups are ordered by their first path.
+#[tauri::command]
+pub async fn find_duplicates(
+    strategy: DuplicateStrategy,
+    app: AppHandle,
+) -> Result<Vec<Vec<TrackInfo>>, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let tracks = app.state::<LibraryIndex>().all_tracks()?;
+        let mut groups = match strategy {
+            DuplicateStrategy::Metadata => by_metadata(tracks),
+            DuplicateStrategy::Content => by_content(tracks),
+        };
+        for group in &mut groups {
+            group.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+        Ok(groups)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
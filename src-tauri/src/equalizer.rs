@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+use tauri::{AppHandle, Manager, State};
+
+use crate::audio::PlayerState;
+use crate::dsp::AtomicGain;
+use crate::error::PlayerError;
+use crate::settings::{write_atomic, SettingsStore};
+
+pub const PRESETS_FILE: &str = "eq-presets.json";
+
+pub const BAND_COUNT: usize = 10;
+
+/// Centre frequency of each band, in Hz.
+pub const BAND_FREQUENCIES: [f64; BAND_COUNT] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Band gains are clamped to `-MAX_BAND_GAIN_DB..=MAX_BAND_GAIN_DB`.
+pub const MAX_BAND_GAIN_DB: f32 = 12.0;
+
+/// Peaking filter bandwidth, roughly one octave per band.
+const BAND_Q: f64 = 1.41;
+
+/// Band gains set from the UI and read by every [`Equalizer`] stage.
+#[derive(Debug, Default)]
+pub struct EqualizerControl {
+    enabled: AtomicBool,
+    gains_db: [AtomicGain; BAND_COUNT],
+    generation: AtomicU64,
+}
+
+impl EqualizerControl {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn bands(&self) -> [f32; BAND_COUNT] {
+        std::array::from_fn(|i| self.gains_db[i].get())
+    }
+
+    pub fn set_band(&self, index: usize, gain_db: f32) -> Result<(), String> {
+        let band = self
+            .gains_db
+            .get(index)
+            .ok_or_else(|| format!("EQ band {index} out of range"))?;
+        band.set(clamp_gain(gain_db));
+        self.generation.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn set_bands(&self, gains_db: [f32; BAND_COUNT]) {
+        for (band, gain) in self.gains_db.iter().zip(gains_db) {
+            band.set(clamp_gain(gain));
+        }
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+fn clamp_gain(gain_db: f32) -> f32 {
+    if gain_db.is_finite() {
+        gain_db.clamp(-MAX_BAND_GAIN_DB, MAX_BAND_GAIN_DB)
+    } else {
+        0.0
+    }
+}
+
+/// Normalized biquad coefficients (`a0 == 1`).
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    /// RBJ cookbook peaking EQ. `None` when the filter would do nothing.
+    fn peaking(frequency: f64, gain_db: f32, sample_rate: u32) -> Option<Self> {
+        let nyquist = sample_rate as f64 / 2.0;
+        if gain_db == 0.0 || frequency >= nyquist {
+            return None;
+        }
+        let a = 10f64.powf(gain_db as f64 / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * BAND_Q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / a;
+        Some(Coefficients {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * cos / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha / a) / a0,
+        })
+    }
+}
+
+/// Graphic EQ stage: one peaking filter per band, per channel.
+///
+/// Coefficients are recomputed at frame boundaries whenever the gains or the
+/// stream format change. While disabled, samples pass through untouched.
+pub struct Equalizer<S> {
+    inner: S,
+    control: Arc<EqualizerControl>,
+    seen: u64,
+    enabled: bool,
+    sample_rate: u32,
+    channels: u16,
+    filters: [Option<Coefficients>; BAND_COUNT],
+    /// Transposed direct form II state, `BAND_COUNT` entries per channel.
+    state: Vec<[f64; 2]>,
+    channel: u16,
+}
+
+impl<S: Source<Item = f32>> Equalizer<S> {
+    pub fn new(inner: S, control: Arc<EqualizerControl>) -> Self {
+        Equalizer {
+            inner,
+            control,
+            seen: 0,
+            enabled: false,
+            sample_rate: 0,
+            channels: 0,
+            filters: [None; BAND_COUNT],
+            state: Vec::new(),
+            channel: 0,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let generation = self.control.generation.load(Ordering::Acquire);
+        let sample_rate = self.inner.sample_rate();
+        let channels = self.inner.channels().max(1);
+        if generation == self.seen && sample_rate == self.sample_rate && channels == self.channels {
+            return;
+        }
+        let enabled = self.control.is_enabled();
+        if enabled != self.enabled || sample_rate != self.sample_rate || channels != self.channels {
+            // Stale filter memory would click, so start from silence.
+            self.state = vec![[0.0; 2]; BAND_COUNT * channels as usize];
+        }
+        self.seen = generation;
+        self.enabled = enabled;
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        let gains = self.control.bands();
+        self.filters = std::array::from_fn(|i| {
+            Coefficients::peaking(BAND_FREQUENCIES[i], gains[i], sample_rate)
+        });
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let offset = self.channel as usize * BAND_COUNT;
+        let state = &mut self.state[offset..offset + BAND_COUNT];
+        let mut x = sample as f64;
+        for (filter, z) in self.filters.iter().zip(state) {
+            let Some(c) = filter else {
+                continue;
+            };
+            let y = c.b0 * x + z[0];
+            z[0] = c.b1 * x - c.a1 * y + z[1];
+            z[1] = c.b2 * x - c.a2 * y;
+            x = y;
+        }
+        x as f32
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Equalizer<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.refresh();
+        }
+        let sample = self.inner.next()?;
+        let out = if self.enabled {
+            self.process(sample)
+        } else {
+            sample
+        };
+        self.channel = (self.channel + 1) % self.channels;
+        Some(out)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Equalizer<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+fn presets_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join(PRESETS_FILE))
+}
+
+/// Reads saved presets, treating a missing or unreadable file as empty.
+fn read_presets(app: &AppHandle) -> Result<BTreeMap<String, [f32; BAND_COUNT]>, String> {
+    let path = presets_path(app)?;
+    Ok(fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+fn save_settings(player: &PlayerState, settings: &SettingsStore) -> Result<(), PlayerError> {
+    let enabled = player.equalizer.is_enabled();
+    let bands = player.equalizer.bands();
+    settings.update(|s| {
+        s.eq_enabled = enabled;
+        s.eq_bands = bands;
+    })
+}
+
+/// Sets band `index` (see [`BAND_FREQUENCIES`]) to `gain_db`, clamped to
+/// [`MAX_BAND_GAIN_DB`], and persists it.
+#[tauri::command]
+pub fn set_eq_band(
+    index: usize,
+    gain_db: f32,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    player.equalizer.set_band(index, gain_db)?;
+    save_settings(&player, &settings)
+}
+
+#[tauri::command]
+pub fn set_eq_enabled(
+    enabled: bool,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    player.equalizer.set_enabled(enabled);
+    save_settings(&player, &settings)
+}
+
+/// Saves the current band gains under `name`, replacing any preset of that name.
+#[tauri::command]
+pub fn save_eq_preset(
+    name: String,
+    app: AppHandle,
+    player: State<'_, PlayerState>,
+) -> Result<(), PlayerError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("preset name is empty".into());
+    }
+    let mut presets = read_presets(&app)?;
+    presets.insert(name.to_string(), player.equalizer.bands());
+    let json = serde_json::to_string_pretty(&presets).map_err(|e| e.to_string())?;
+    write_atomic(&presets_path(&app)?, json.as_bytes())
+}
+
+/// Applies the preset `name` and returns its band gains.
+#[tauri::command]
+pub fn load_eq_preset(
+    name: String,
+    app: AppHandle,
+    player: State<'_, PlayerState>,
+    settings: State<'_, SettingsStore>,
+) -> Result<[f32; BAND_COUNT], PlayerError> {
+    let bands = *read_presets(&app)?
+        .get(name.trim())
+        .ok_or_else(|| format!("no EQ preset named {name}"))?;
+    player.equalizer.set_bands(bands);
+    save_settings(&player, &settings)?;
+    Ok(player.equalizer.bands())
+}
+
+#[tauri::command]
+pub fn list_eq_presets(app: AppHandle) -> Result<Vec<String>, PlayerError> {
+    Ok(read_presets(&app)?.into_keys().collect())
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// Emitted with a [`PlayerError`] for failures outside any command call,
+/// e.g. the output device vanishing or a queued track failing to open.
+pub const ERROR_EVENT: &str = "error";
+
+/// Error returned by every command. Serializes as an object with a `kind`
+/// tag, a human-readable `message`, and the variant's context, so the UI can
+/// tell a missing file from a broken one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlayerError {
+    FileNotFound {
+        path: String,
+        message: String,
+    },
+    /// The container or codec isn't one we can play.
+    UnsupportedFormat {
+        message: String,
+    },
+    /// The file opened but its audio is corrupt.
+    DecodeError {
+        message: String,
+    },
+    /// No output device, or the chosen one is gone.
+    DeviceUnavailable {
+        device: Option<String>,
+        message: String,
+    },
+    IoError {
+        path: Option<String>,
+        message: String,
+    },
+    /// Anything the other kinds don't cover.
+    Other {
+        message: String,
+    },
+}
+
+impl PlayerError {
+    /// Failure to `action` `path`, e.g. `PlayerError::io("open", path, e)`.
+    /// A missing file becomes [`PlayerError::FileNotFound`].
+    pub fn io(action: &str, path: &Path, error: io::Error) -> Self {
+        let message = format!("failed to {action} {}: {error}", path.display());
+        let path = path.to_string_lossy().into_owned();
+        if error.kind() == io::ErrorKind::NotFound {
+            PlayerError::FileNotFound { path, message }
+        } else {
+            PlayerError::IoError {
+                path: Some(path),
+                message,
+            }
+        }
+    }
+
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        PlayerError::UnsupportedFormat {
+            message: message.into(),
+        }
+    }
+
+    pub fn decode(message: impl Into<String>) -> Self {
+        PlayerError::DecodeError {
+            message: message.into(),
+        }
+    }
+
+    pub fn device(device: Option<String>, message: impl Into<String>) -> Self {
+        PlayerError::DeviceUnavailable {
+            device,
+            message: message.into(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            PlayerError::FileNotFound { message, .. }
+            | PlayerError::UnsupportedFormat { message }
+            | PlayerError::DecodeError { message }
+            | PlayerError::DeviceUnavailable { message, .. }
+            | PlayerError::IoError { message, .. }
+            | PlayerError::Other { message } => message,
+        }
+    }
+
+    /// Reports a background failure through `error`, and logs it.
+    pub fn emit(&self, app: &AppHandle) {
+        log::warn!("{self}");
+        let _ = app.emit(ERROR_EVENT, self);
+    }
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for PlayerError {}
+
+/// Errors from code that only deals in messages.
+impl From<String> for PlayerError {
+    fn from(message: String) -> Self {
+        PlayerError::Other { message }
+    }
+}
+
+impl From<&str> for PlayerError {
+    fn from(message: &str) -> Self {
+        PlayerError::Other {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Lets helpers that still return `Result<_, String>` use `?` on calls that
+/// return a [`PlayerError`].
+impl From<PlayerError> for String {
+    fn from(error: PlayerError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::PlayerError;
+use crate::index::{now_millis, LibraryIndex};
+use crate::settings::write_atomic;
+use crate::silence::SilenceScanner;
+use crate::watcher::{LibraryChange, LIBRARY_CHANGED_EVENT};
+
+/// Bumped whenever [`LibraryExport`] changes incompatibly.
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryExportFormat {
+    /// A [`LibraryExport`] document; the only format [`import_library`] reads.
+    Json,
+    /// One row per track under a header of [`CSV_COLUMNS`], quoted per RFC 4180.
+    Csv,
+}
+
+/// One indexed track with everything the index knows about it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedTrack {
+    pub path: String,
+    pub size: u64,
+    pub extension: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: u32,
+    pub disc_number: u32,
+    pub year: u32,
+    pub genre: String,
+    pub duration_ms: u64,
+    /// Stars, 0 when unrated.
+    pub rating: u8,
+    pub favorite: bool,
+    /// Unix milliseconds the track was first indexed.
+    pub added_at: i64,
+    pub play_count: u64,
+    pub listened_ms: u64,
+    /// Unix milliseconds of the last counted play.
+    pub last_played: Option<i64>,
+}
+
+/// Header of a CSV export, matching the fields of [`ExportedTrack`].
+pub const CSV_COLUMNS: [&str; 18] = [
+    "path",
+    "size",
+    "extension",
+    "title",
+    "artist",
+    "album",
+    "album_artist",
+    "track_number",
+    "disc_number",
+    "year",
+    "genre",
+    "duration_ms",
+    "rating",
+    "favorite",
+    "added_at",
+    "play_count",
+    "listened_ms",
+    "last_played",
+];
+
+impl ExportedTrack {
+    fn csv_fields(&self) -> [String; 18] {
+        [
+            self.path.clone(),
+            self.size.to_string(),
+            self.extension.clone(),
+            self.title.clone(),
+            self.artist.clone(),
+            self.album.clone(),
+            self.album_artist.clone(),
+            self.track_number.to_string(),
+            self.disc_number.to_string(),
+            self.year.to_string(),
+            self.genre.clone(),
+            self.duration_ms.to_string(),
+            self.rating.to_string(),
+            self.favorite.to_string(),
+            self.added_at.to_string(),
+            self.play_count.to_string(),
+            self.listened_ms.to_string(),
+            self.last_played.map_or_else(String::new, |t| t.to_string()),
+        ]
+    }
+}
+
+/// Contents of a JSON export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryExport {
+    pub version: u32,
+    /// Unix milliseconds the export was written.
+    pub exported_at: i64,
+    pub tracks: Vec<ExportedTrack>,
+}
+
+/// What [`import_library`] did with the exported tracks.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportSummary {
+    /// Tracks that weren't indexed yet and were read from disk.
+    pub added: usize,
+    /// Tracks already indexed whose stats were merged.
+    pub merged: usize,
+    /// Tracks whose file isn't on this machine or couldn't be read.
+    pub skipped: Vec<String>,
+}
+
+/// Quotes `field` if it holds a comma, quote, or line break, doubling any quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_row<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f.as_ref())).collect();
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+fn render_csv(tracks: &[ExportedTrack]) -> String {
+    let mut out = String::new();
+    csv_row(&mut out, &CSV_COLUMNS);
+    for track in tracks {
+        csv_row(&mut out, &track.csv_fields());
+    }
+    out
+}
+
+/// Writes every indexed track, with its metadata, rating, play stats, and
+/// date added, to `dest`.
+#[tauri::command]
+pub async fn export_library(
+    dest: String,
+    format: LibraryExportFormat,
+    app: AppHandle,
+) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let tracks = app.state::<LibraryIndex>().export_tracks()?;
+        let contents = match format {
+            LibraryExportFormat::Json => serde_json::to_string_pretty(&LibraryExport {
+                version: EXPORT_VERSION,
+                exported_at: now_millis(),
+                tracks,
+            })
+            .map_err(|e| e.to_string())?,
+            LibraryExportFormat::Csv => render_csv(&tracks),
+        };
+        write_atomic(Path::new(&dest), contents.as_bytes())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Merges a JSON export into the index, matching tracks by path. Tracks not
+/// indexed yet are read from disk if their file exists here; for the rest the
+/// higher of the two play counts wins. Emits `library-changed`.
+#[tauri::command]
+pub async fn import_library(src: String, app: AppHandle) -> Result<ImportSummary, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let json =
+            fs::read_to_string(&src).map_err(|e| PlayerError::io("read", Path::new(&src), e))?;
+        let export: LibraryExport = serde_json::from_str(&json)
+            .map_err(|e| format!("{src} isn't a library export: {e}"))?;
+        if export.version != EXPORT_VERSION {
+            return Err(format!("unsupported library export version {}", export.version).into());
+        }
+        let index = app.state::<LibraryIndex>();
+        let mut summary = ImportSummary::default();
+        let mut change = LibraryChange::default();
+        for track in export.tracks {
+            let indexed = index.track(&track.path)?.is_some();
+            if !indexed && index.index_file(Path::new(&track.path)).is_err() {
+                summary.skipped.push(track.path);
+                continue;
+            }
+            if !index.merge_exported(&track)? {
+                summary.skipped.push(track.path);
+                continue;
+            }
+            if indexed {
+                summary.merged += 1;
+            } else {
+                summary.added += 1;
+                if let Some(scanner) = app.try_state::<SilenceScanner>() {
+                    scanner.analyze(&track.path);
+                }
+            }
+            change.indexed.push(track.path);
+        }
+        if !change.indexed.is_empty() {
+            let _ = app.emit(LIBRARY_CHANGED_EVENT, change);
+        }
+        Ok(summary)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str, title: &str) -> ExportedTrack {
+        ExportedTrack {
+            path: path.into(),
+            size: 1024,
+            extension: "flac".into(),
+            title: title.into(),
+            artist: "Artist".into(),
+            album: "Album".into(),
+            album_artist: "Artist".into(),
+            track_number: 1,
+            disc_number: 1,
+            year: 1999,
+            genre: "Rock".into(),
+            duration_ms: 180_000,
+            rating: 4,
+            favorite: true,
+            added_at: 10,
+            play_count: 3,
+            listened_ms: 540_000,
+            last_played: None,
+        }
+    }
+
+    #[test]
+    fn plain_fields_are_left_alone() {
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("Blue Train"), "Blue Train");
+        assert_eq!(csv_field("it's; fine"), "it's; fine");
+    }
+
+    #[test]
+    fn special_characters_are_quoted() {
+        assert_eq!(
+            csv_field("Crosby, Stills & Nash"),
+            "\"Crosby, Stills & Nash\""
+        );
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(csv_field("carriage\rreturn"), "\"carriage\rreturn\"");
+    }
+
+    #[test]
+    fn quotes_are_doubled() {
+        assert_eq!(csv_field("12\" Mix"), "\"12\"\" Mix\"");
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+    }
+
+    #[test]
+    fn rows_end_in_crlf() {
+        let mut out = String::new();
+        csv_row(&mut out, &["a", "b,c", ""]);
+        csv_row(&mut out, &["d"]);
+        assert_eq!(out, "a,\"b,c\",\r\nd\r\n");
+    }
+
+    #[test]
+    fn renders_a_header_and_a_row_per_track() {
+        let csv = render_csv(&[
+            track("/music/a.flac", "Plain"),
+            track("/music/b.flac", "Say \"Hi\", Bye"),
+        ]);
+        let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            "/music/a.flac,1024,flac,Plain,Artist,Album,Artist,1,1,1999,Rock,180000,4,true,10,3,540000,"
+        );
+        assert!(lines[2].contains(",\"Say \"\"Hi\"\", Bye\","));
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Serialize;
+use tauri::{AppHandle, Listener, Manager, State};
+
+use crate::audio::{PlaybackStatus, PlayerState, PLAYBACK_STATE_EVENT};
+use crate::error::PlayerError;
+use crate::index::{now_millis, LibraryIndex};
+use crate::queue::Queue;
+use crate::settings::SettingsStore;
+use crate::sync::MutexExt;
+
+/// Default for [`crate::settings::Settings::history_limit`].
+pub const DEFAULT_HISTORY_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub path: String,
+    /// Unix time in milliseconds the track started.
+    pub played_at: i64,
+}
+
+/// Adds each track to the history as it starts playing.
+pub fn start(app: &AppHandle) {
+    let handle = app.clone();
+    app.listen(PLAYBACK_STATE_EVENT, move |event| {
+        let Ok(status) = serde_json::from_str::<PlaybackStatus>(event.payload()) else {
+            return;
+        };
+        let (Some(path), Some(index), Some(settings)) = (
+            status.path,
+            handle.try_state::<LibraryIndex>(),
+            handle.try_state::<SettingsStore>(),
+        ) else {
+            return;
+        };
+        // Pausing and resuming re-send the same path, which dedupes away.
+        let _ = index.record_history(&path, now_millis(), settings.get().history_limit);
+    });
+}
+
+/// Recently played tracks, newest first.
+#[tauri::command]
+pub fn get_history(
+    limit: usize,
+    index: State<'_, LibraryIndex>,
+) -> Result<Vec<HistoryEntry>, PlayerError> {
+    index.history(limit)
+}
+
+#[tauri::command]
+pub fn clear_history(index: State<'_, LibraryIndex>) -> Result<(), PlayerError> {
+    index.clear_history()
+}
+
+/// Queues the entry at `index` in `get_history`'s order (0 is the newest).
+#[tauri::command]
+pub fn requeue_from_history(
+    index: usize,
+    history: State<'_, LibraryIndex>,
+    player: State<'_, PlayerState>,
+) -> Result<Queue, PlayerError> {
+    let path = history
+        .history_path(index)?
+        .ok_or_else(|| format!("no history entry at {index}"))?;
+    player.discard_preloaded();
+    let mut queue = player.queue.locked();
+    queue.add(vec![path]);
+    Ok(queue.clone())
+}
+
+/// Keeps at most `limit` entries, dropping the oldest beyond it now.
+#[tauri::command]
+pub fn set_history_limit(
+    limit: usize,
+    index: State<'_, LibraryIndex>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    settings.update(|s| s.history_limit = limit)?;
+    index.trim_history(limit)
+}
@@ -0,0 +1,984 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use tauri::{AppHandle, Manager};
+
+use crate::bookmarks::Bookmark;
+use crate::cue;
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::export::ExportedTrack;
+use crate::history::HistoryEntry;
+use crate::library::{audio_extension, TrackInfo};
+use crate::metadata::{self, TrackMetadata};
+use crate::rating::Rating;
+use crate::replaygain::ReplayGain;
+use crate::silence::{SilenceScanner, Trim};
+use crate::sort::natural_cmp;
+use crate::stats::TrackStats;
+use crate::sync::MutexExt;
+
+pub const DATABASE_FILE: &str = "library.sqlite3";
+
+/// Schema migrations, applied in order and tracked through `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE tracks (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        size INTEGER NOT NULL,
+        extension TEXT NOT NULL,
+        mtime INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        artist TEXT NOT NULL,
+        album TEXT NOT NULL,
+        album_artist TEXT NOT NULL,
+        track_number INTEGER NOT NULL,
+        disc_number INTEGER NOT NULL,
+        year INTEGER NOT NULL,
+        genre TEXT NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        added_at INTEGER NOT NULL
+    );",
+    // External-content FTS5 table kept in sync with `tracks` through triggers.
+    "CREATE VIRTUAL TABLE tracks_fts USING fts5(
+        title, artist, album,
+        content = 'tracks', content_rowid = 'id',
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER tracks_fts_insert AFTER INSERT ON tracks BEGIN
+        INSERT INTO tracks_fts (rowid, title, artist, album)
+        VALUES (new.id, new.title, new.artist, new.album);
+    END;
+    CREATE TRIGGER tracks_fts_delete AFTER DELETE ON tracks BEGIN
+        INSERT INTO tracks_fts (tracks_fts, rowid, title, artist, album)
+        VALUES ('delete', old.id, old.title, old.artist, old.album);
+    END;
+    CREATE TRIGGER tracks_fts_update AFTER UPDATE ON tracks BEGIN
+        INSERT INTO tracks_fts (tracks_fts, rowid, title, artist, album)
+        VALUES ('delete', old.id, old.title, old.artist, old.album);
+        INSERT INTO tracks_fts (rowid, title, artist, album)
+        VALUES (new.id, new.title, new.artist, new.album);
+    END;
+    INSERT INTO tracks_fts (tracks_fts) VALUES ('rebuild');",
+    // Peaks are little-endian f32 pairs, valid while the file's mtime matches.
+    "CREATE TABLE waveforms (
+        path TEXT NOT NULL,
+        buckets INTEGER NOT NULL,
+        mtime INTEGER NOT NULL,
+        peaks BLOB NOT NULL,
+        PRIMARY KEY (path, buckets)
+    );",
+    // One row per counted play; `listened_ms` grows until the track is left.
+    "CREATE TABLE plays (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        played_at INTEGER NOT NULL,
+        listened_ms INTEGER NOT NULL
+    );
+    CREATE INDEX plays_path ON plays (path);
+    CREATE INDEX plays_played_at ON plays (played_at);",
+    "CREATE TABLE history (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        played_at INTEGER NOT NULL
+    );",
+    // Stars from 1 to 5; 0 means unrated.
+    "ALTER TABLE tracks ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE tracks ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX tracks_favorite ON tracks (favorite) WHERE favorite = 1;",
+    // Keyed by path rather than track id, so they outlive rescans and removals.
+    "CREATE TABLE bookmarks (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        position_ms INTEGER NOT NULL,
+        label TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX bookmarks_path ON bookmarks (path);",
+    // Where to start and stop a track to skip its silence, as detected at
+    // `threshold_db`. `end_ms` is null when the track plays to its end.
+    "CREATE TABLE silence (
+        path TEXT PRIMARY KEY,
+        threshold_db REAL NOT NULL,
+        start_ms INTEGER NOT NULL,
+        end_ms INTEGER
+    );",
+    // Integrated loudness found by analysis, with the gains derived from it.
+    // Album values are set when the track was analyzed as part of an album.
+    "CREATE TABLE loudness (
+        path TEXT PRIMARY KEY,
+        lufs REAL NOT NULL,
+        gain_db REAL NOT NULL,
+        peak REAL NOT NULL,
+        album_gain_db REAL,
+        album_peak REAL
+    );",
+];
+
+/// Columns selected by [`track_from_row`], in order.
+pub const TRACK_COLUMNS: &str = "tracks.path, tracks.size, tracks.extension, tracks.title, \
+    tracks.artist, tracks.album, tracks.album_artist, tracks.track_number, tracks.disc_number, \
+    tracks.year, tracks.genre, tracks.duration_ms, tracks.rating, tracks.favorite";
+
+/// Library order: artists, their albums, then each album's running order.
+/// Text sorts through the `NATURAL_ORDER` collation, so "Vol. 2" precedes
+/// "Vol. 10". (`NATURAL` alone is a join keyword and doesn't parse here.)
+pub const LIBRARY_ORDER: &str =
+    "tracks.artist COLLATE NATURAL_ORDER, tracks.album COLLATE NATURAL_ORDER, \
+    tracks.disc_number, tracks.track_number, tracks.path COLLATE NATURAL_ORDER";
+
+/// Persistent SQLite index of scanned tracks, stored in the app data dir.
+pub struct LibraryIndex {
+    conn: Mutex<Connection>,
+}
+
+impl LibraryIndex {
+    pub fn open(path: &Path) -> Result<Self, PlayerError> {
+        let mut conn = Connection::open(path).map_err(db_error)?;
+        conn.create_collation("NATURAL_ORDER", natural_cmp)
+            .map_err(db_error)?;
+        migrate(&mut conn).map_err(|e| format!("failed to migrate library index: {e}"))?;
+        Ok(LibraryIndex {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Opens the index under `app_data_dir`, creating the directory if needed.
+    pub fn open_in_app_dir(app: &AppHandle) -> Result<Self, PlayerError> {
+        let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| PlayerError::io("create", &dir, e))?;
+        Self::open(&dir.join(DATABASE_FILE))
+    }
+
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.conn.locked()
+    }
+
+    /// Reads tags for `path` and stores them, unless the stored row is
+    /// already up to date with the file's mtime. Returns whether the row changed.
+    ///
+    /// A CUE sheet's virtual track (`album.cue#3`) is stored under the sheet's
+    /// absolute path, with the size and format of the file backing it and
+    /// the later of the two files' mtimes.
+    pub fn index_file(&self, path: &Path) -> Result<bool, PlayerError> {
+        let (key, file, sheet, sheet_mtime) = match path.to_str().and_then(cue::split_virtual) {
+            Some((sheet_path, number)) => {
+                let sheet_path = absolute(sheet_path)?;
+                let sheet_meta = fs::metadata(&sheet_path)
+                    .map_err(|e| PlayerError::io("read", &sheet_path, e))?;
+                let (sheet, i) = cue::find(&sheet_path, number)?;
+                let file = sheet.tracks[i].file.clone();
+                let key = cue::virtual_path(&sheet_path, number);
+                (key, file, Some((sheet, i)), mtime_millis(&sheet_meta))
+            }
+            None => {
+                let path = absolute(path)?;
+                (path.to_string_lossy().into_owned(), path, None, 0)
+            }
+        };
+        let extension = audio_extension(&file).ok_or_else(|| {
+            PlayerError::unsupported(format!("{} is not a supported audio file", file.display()))
+        })?;
+        let file_meta = fs::metadata(&file).map_err(|e| PlayerError::io("read", &file, e))?;
+        let mtime = mtime_millis(&file_meta).max(sheet_mtime);
+
+        let stored: Option<i64> = self
+            .connection()
+            .query_row("SELECT mtime FROM tracks WHERE path = ?1", [&key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(db_error)?;
+        if stored == Some(mtime) {
+            return Ok(false);
+        }
+
+        let tags = match sheet {
+            Some((sheet, i)) => cue::track_metadata(&sheet, i, metadata::read(&file)?.duration_ms),
+            None => metadata::read(&file)?,
+        };
+        self.connection()
+            .execute(
+                "INSERT INTO tracks (path, size, extension, mtime, title, artist, album,
+                    album_artist, track_number, disc_number, year, genre, duration_ms, added_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                 ON CONFLICT(path) DO UPDATE SET
+                    size = excluded.size, extension = excluded.extension, mtime = excluded.mtime,
+                    title = excluded.title, artist = excluded.artist, album = excluded.album,
+                    album_artist = excluded.album_artist, track_number = excluded.track_number,
+                    disc_number = excluded.disc_number, year = excluded.year,
+                    genre = excluded.genre, duration_ms = excluded.duration_ms",
+                params![
+                    key,
+                    file_meta.len() as i64,
+                    extension,
+                    mtime,
+                    tags.title,
+                    tags.artist,
+                    tags.album,
+                    tags.album_artist,
+                    tags.track_number,
+                    tags.disc_number,
+                    tags.year,
+                    tags.genre,
+                    tags.duration_ms as i64,
+                    now_millis(),
+                ],
+            )
+            .map_err(db_error)?;
+        Ok(true)
+    }
+
+    pub fn all_tracks(&self) -> Result<Vec<TrackInfo>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {TRACK_COLUMNS} FROM tracks ORDER BY {LIBRARY_ORDER}"
+            ))
+            .map_err(db_error)?;
+        let tracks = stmt
+            .query_map([], track_from_row)
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        tracks
+    }
+
+    /// Tracks matching `filter`, a `WHERE` clause over `tracks` whose values
+    /// are all bound through `?` placeholders, in library order.
+    pub fn select_tracks(
+        &self,
+        filter: &str,
+        params: Vec<rusqlite::types::Value>,
+        limit: Option<usize>,
+    ) -> Result<Vec<TrackInfo>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {TRACK_COLUMNS} FROM tracks WHERE {filter}
+                 ORDER BY {LIBRARY_ORDER}
+                 LIMIT ?"
+            ))
+            .map_err(db_error)?;
+        let limit = limit.map_or(-1, |limit| limit as i64);
+        let params = params.into_iter().chain([limit.into()]);
+        let tracks = stmt
+            .query_map(rusqlite::params_from_iter(params), track_from_row)
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        tracks
+    }
+
+    /// The indexed row for `path`, if there is one.
+    pub fn track(&self, path: &str) -> Result<Option<TrackInfo>, PlayerError> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {TRACK_COLUMNS} FROM tracks WHERE path = ?1"),
+                [path],
+                track_from_row,
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    /// Sets `path`'s star rating. Returns the updated track, or `None` if it
+    /// isn't indexed.
+    pub fn set_rating(&self, path: &str, stars: u8) -> Result<Option<TrackInfo>, PlayerError> {
+        self.connection()
+            .execute(
+                "UPDATE tracks SET rating = ?2 WHERE path = ?1",
+                params![path, stars],
+            )
+            .map_err(db_error)?;
+        self.track(path)
+    }
+
+    /// Flips `path`'s favorite flag. Returns the updated track, or `None` if
+    /// it isn't indexed.
+    pub fn toggle_favorite(&self, path: &str) -> Result<Option<TrackInfo>, PlayerError> {
+        self.connection()
+            .execute(
+                "UPDATE tracks SET favorite = NOT favorite WHERE path = ?1",
+                [path],
+            )
+            .map_err(db_error)?;
+        self.track(path)
+    }
+
+    /// Full-text search over title, artist and album, best matches first.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<TrackInfo>, PlayerError> {
+        let Some(pattern) = fts_prefix_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {TRACK_COLUMNS} FROM tracks_fts
+                 JOIN tracks ON tracks.id = tracks_fts.rowid
+                 WHERE tracks_fts MATCH ?1
+                 ORDER BY tracks_fts.rank, tracks.title COLLATE NATURAL_ORDER
+                 LIMIT ?2"
+            ))
+            .map_err(db_error)?;
+        let tracks = stmt
+            .query_map(params![pattern, limit as i64], track_from_row)
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        tracks
+    }
+
+    /// Cached waveform for `path`, if one was stored at this `mtime`.
+    pub fn cached_waveform(
+        &self,
+        path: &str,
+        buckets: usize,
+        mtime: i64,
+    ) -> Result<Option<Vec<f32>>, PlayerError> {
+        let peaks: Option<Vec<u8>> = self
+            .connection()
+            .query_row(
+                "SELECT peaks FROM waveforms WHERE path = ?1 AND buckets = ?2 AND mtime = ?3",
+                params![path, buckets as i64, mtime],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(peaks.map(|bytes| {
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        }))
+    }
+
+    pub fn store_waveform(
+        &self,
+        path: &str,
+        buckets: usize,
+        mtime: i64,
+        peaks: &[f32],
+    ) -> Result<(), PlayerError> {
+        let bytes: Vec<u8> = peaks.iter().flat_map(|p| p.to_le_bytes()).collect();
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO waveforms (path, buckets, mtime, peaks)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![path, buckets as i64, mtime, bytes],
+            )
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    /// Drops the row for `path`, or for every track under it if it was a
+    /// directory or a CUE sheet. Returns the paths removed.
+    pub fn remove_under(&self, path: &Path) -> Result<Vec<String>, PlayerError> {
+        let key = path.to_string_lossy().into_owned();
+        let prefix = format!("{key}{}", std::path::MAIN_SEPARATOR);
+        let tracks = format!("{key}#");
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(
+                "DELETE FROM tracks
+                 WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2
+                    OR substr(path, 1, length(?3)) = ?3
+                 RETURNING path",
+            )
+            .map_err(db_error)?;
+        let removed = stmt
+            .query_map(params![key, prefix, tracks], |row| row.get(0))
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        removed
+    }
+
+    /// Paths of every track indexed under the directory `root`.
+    pub fn paths_under(&self, root: &Path) -> Result<Vec<String>, PlayerError> {
+        let prefix = format!("{}{}", root.to_string_lossy(), std::path::MAIN_SEPARATOR);
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare("SELECT path FROM tracks WHERE substr(path, 1, length(?1)) = ?1")
+            .map_err(db_error)?;
+        let paths = stmt
+            .query_map(params![prefix], |row| row.get(0))
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        paths
+    }
+
+    /// Records a play of `path` that started at `played_at`, returning its id
+    /// for [`LibraryIndex::update_play`]. Plays are keyed like tracks, so
+    /// they join against `tracks.path`.
+    pub fn record_play(
+        &self,
+        path: &str,
+        played_at: i64,
+        listened_ms: u64,
+    ) -> Result<i64, PlayerError> {
+        let conn = self.connection();
+        conn.execute(
+            "INSERT INTO plays (path, played_at, listened_ms) VALUES (?1, ?2, ?3)",
+            params![play_key(path), played_at, listened_ms as i64],
+        )
+        .map_err(db_error)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn update_play(&self, id: i64, listened_ms: u64) -> Result<(), PlayerError> {
+        self.connection()
+            .execute(
+                "UPDATE plays SET listened_ms = ?2 WHERE id = ?1",
+                params![id, listened_ms as i64],
+            )
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    pub fn track_stats(&self, path: &str) -> Result<TrackStats, PlayerError> {
+        self.connection()
+            .query_row(
+                "SELECT ?1, COUNT(*), COALESCE(SUM(listened_ms), 0), MAX(played_at)
+                 FROM plays WHERE path = ?1",
+                [play_key(path)],
+                stats_from_row,
+            )
+            .map_err(db_error)
+    }
+
+    /// Most played tracks first; ties go to the one played most recently.
+    pub fn top_tracks(&self, limit: usize) -> Result<Vec<TrackStats>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(
+                "SELECT path, COUNT(*) AS count, SUM(listened_ms), MAX(played_at) AS last
+                 FROM plays GROUP BY path
+                 ORDER BY count DESC, last DESC
+                 LIMIT ?1",
+            )
+            .map_err(db_error)?;
+        let stats = stmt
+            .query_map([limit as i64], stats_from_row)
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        stats
+    }
+
+    /// Total time listened across plays starting at or after `since`.
+    pub fn listening_time(&self, since: Option<i64>) -> Result<u64, PlayerError> {
+        self.connection()
+            .query_row(
+                "SELECT COALESCE(SUM(listened_ms), 0) FROM plays WHERE played_at >= ?1",
+                [since.unwrap_or(i64::MIN)],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|ms| ms as u64)
+            .map_err(db_error)
+    }
+
+    /// Appends `path` to the history unless it's already the latest entry,
+    /// then trims the history to its newest `limit` entries.
+    pub fn record_history(
+        &self,
+        path: &str,
+        played_at: i64,
+        limit: usize,
+    ) -> Result<(), PlayerError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction().map_err(db_error)?;
+        let latest: Option<String> = tx
+            .query_row(
+                "SELECT path FROM history ORDER BY id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        if latest.as_deref() != Some(path) {
+            tx.execute(
+                "INSERT INTO history (path, played_at) VALUES (?1, ?2)",
+                params![path, played_at],
+            )
+            .map_err(db_error)?;
+        }
+        trim_history(&tx, limit).map_err(db_error)?;
+        tx.commit().map_err(db_error)
+    }
+
+    /// Newest entries first.
+    pub fn history(&self, limit: usize) -> Result<Vec<HistoryEntry>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare("SELECT path, played_at FROM history ORDER BY id DESC LIMIT ?1")
+            .map_err(db_error)?;
+        let entries = stmt
+            .query_map([limit as i64], |row| {
+                Ok(HistoryEntry {
+                    path: row.get(0)?,
+                    played_at: row.get(1)?,
+                })
+            })
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        entries
+    }
+
+    /// Path of the history entry `index` places back from the newest.
+    pub fn history_path(&self, index: usize) -> Result<Option<String>, PlayerError> {
+        self.connection()
+            .query_row(
+                "SELECT path FROM history ORDER BY id DESC LIMIT 1 OFFSET ?1",
+                [index as i64],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    pub fn trim_history(&self, limit: usize) -> Result<(), PlayerError> {
+        trim_history(&self.connection(), limit).map_err(db_error)
+    }
+
+    pub fn clear_history(&self) -> Result<(), PlayerError> {
+        self.connection()
+            .execute("DELETE FROM history", [])
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    pub fn add_bookmark(
+        &self,
+        path: &str,
+        position_ms: u64,
+        label: &str,
+    ) -> Result<Bookmark, PlayerError> {
+        let conn = self.connection();
+        let created_at = now_millis();
+        conn.execute(
+            "INSERT INTO bookmarks (path, position_ms, label, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![path, position_ms as i64, label, created_at],
+        )
+        .map_err(db_error)?;
+        Ok(Bookmark {
+            id: conn.last_insert_rowid(),
+            path: path.to_string(),
+            position_ms,
+            label: label.to_string(),
+            created_at,
+        })
+    }
+
+    /// Deletes bookmark `id`, returning it if it existed.
+    pub fn remove_bookmark(&self, id: i64) -> Result<Option<Bookmark>, PlayerError> {
+        self.connection()
+            .query_row(
+                "DELETE FROM bookmarks WHERE id = ?1
+                 RETURNING id, path, position_ms, label, created_at",
+                [id],
+                bookmark_from_row,
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    pub fn bookmark(&self, id: i64) -> Result<Option<Bookmark>, PlayerError> {
+        self.connection()
+            .query_row(
+                "SELECT id, path, position_ms, label, created_at FROM bookmarks WHERE id = ?1",
+                [id],
+                bookmark_from_row,
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    /// Bookmarks in `path`, in playback order.
+    pub fn bookmarks(&self, path: &str) -> Result<Vec<Bookmark>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, path, position_ms, label, created_at FROM bookmarks
+                 WHERE path = ?1 ORDER BY position_ms, id",
+            )
+            .map_err(db_error)?;
+        let bookmarks = stmt
+            .query_map([path], bookmark_from_row)
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        bookmarks
+    }
+
+    /// Stored trim for `path`, if it was analyzed at `threshold_db`.
+    pub fn silence(&self, path: &str, threshold_db: f32) -> Result<Option<Trim>, PlayerError> {
+        self.connection()
+            .query_row(
+                "SELECT start_ms, end_ms FROM silence WHERE path = ?1 AND threshold_db = ?2",
+                params![path, threshold_db],
+                |row| {
+                    Ok(Trim {
+                        start: Duration::from_millis(row.get::<_, i64>(0)? as u64),
+                        end: row
+                            .get::<_, Option<i64>>(1)?
+                            .map(|ms| Duration::from_millis(ms as u64)),
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    pub fn store_silence(
+        &self,
+        path: &str,
+        threshold_db: f32,
+        trim: Trim,
+    ) -> Result<(), PlayerError> {
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO silence (path, threshold_db, start_ms, end_ms)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    path,
+                    threshold_db,
+                    trim.start.as_millis() as i64,
+                    trim.end.map(|end| end.as_millis() as i64),
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    /// Indexed tracks with no trim stored for `threshold_db`.
+    pub fn unanalyzed_for_silence(&self, threshold_db: f32) -> Result<Vec<String>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(
+                "SELECT path FROM tracks WHERE path NOT IN
+                    (SELECT path FROM silence WHERE threshold_db = ?1)",
+            )
+            .map_err(db_error)?;
+        let paths = stmt
+            .query_map([threshold_db], |row| row.get(0))
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        paths
+    }
+
+    /// Every indexed track with its rating, play stats, and date added, in
+    /// library order.
+    pub fn export_tracks(&self) -> Result<Vec<ExportedTrack>, PlayerError> {
+        let conn = self.connection();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {TRACK_COLUMNS}, tracks.added_at, COUNT(plays.id),
+                    COALESCE(SUM(plays.listened_ms), 0), MAX(plays.played_at)
+                 FROM tracks LEFT JOIN plays ON plays.path = tracks.path
+                 GROUP BY tracks.id
+                 ORDER BY {LIBRARY_ORDER}"
+            ))
+            .map_err(db_error)?;
+        let tracks = stmt
+            .query_map([], |row| {
+                Ok(ExportedTrack {
+                    path: row.get(0)?,
+                    size: row.get::<_, i64>(1)? as u64,
+                    extension: row.get(2)?,
+                    title: row.get(3)?,
+                    artist: row.get(4)?,
+                    album: row.get(5)?,
+                    album_artist: row.get(6)?,
+                    track_number: row.get(7)?,
+                    disc_number: row.get(8)?,
+                    year: row.get(9)?,
+                    genre: row.get(10)?,
+                    duration_ms: row.get::<_, i64>(11)? as u64,
+                    rating: row.get(12)?,
+                    favorite: row.get(13)?,
+                    added_at: row.get(14)?,
+                    play_count: row.get::<_, i64>(15)? as u64,
+                    listened_ms: row.get::<_, i64>(16)? as u64,
+                    last_played: row.get(17)?,
+                })
+            })
+            .and_then(Iterator::collect)
+            .map_err(db_error);
+        tracks
+    }
+
+    /// Folds an exported track's stats into its indexed row: keeps the
+    /// earlier date added, a rating where there's none, and the favorite
+    /// flag if either side set it. When the export counted more plays, the
+    /// difference is recorded as plays at its last play time. Returns `false`
+    /// if the track isn't indexed.
+    pub fn merge_exported(&self, track: &ExportedTrack) -> Result<bool, PlayerError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction().map_err(db_error)?;
+        let updated = tx
+            .execute(
+                "UPDATE tracks SET
+                    added_at = MIN(added_at, ?2),
+                    rating = CASE WHEN rating = 0 THEN ?3 ELSE rating END,
+                    favorite = favorite OR ?4
+                 WHERE path = ?1",
+                params![track.path, track.added_at, track.rating, track.favorite],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Ok(false);
+        }
+        let (count, listened): (i64, i64) = tx
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(listened_ms), 0) FROM plays WHERE path = ?1",
+                [&track.path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(db_error)?;
+        let missing = track.play_count.saturating_sub(count as u64);
+        if missing > 0 {
+            let played_at = track.last_played.unwrap_or(track.added_at);
+            let listened = track.listened_ms.saturating_sub(listened as u64);
+            for i in 0..missing {
+                // Spread the missing listening time over the added plays.
+                let share = listened / missing + u64::from(i < listened % missing);
+                tx.execute(
+                    "INSERT INTO plays (path, played_at, listened_ms) VALUES (?1, ?2, ?3)",
+                    params![track.path, played_at, share as i64],
+                )
+                .map_err(db_error)?;
+            }
+        }
+        tx.commit().map_err(db_error)?;
+        Ok(true)
+    }
+
+    /// Measured gains for `path`, in the shape of its ReplayGain tags.
+    pub fn loudness(&self, path: &str) -> Result<Option<ReplayGain>, PlayerError> {
+        self.connection()
+            .query_row(
+                "SELECT gain_db, peak, album_gain_db, album_peak FROM loudness WHERE path = ?1",
+                [path],
+                |row| {
+                    Ok(ReplayGain {
+                        track_gain: Some(row.get(0)?),
+                        track_peak: Some(row.get(1)?),
+                        album_gain: row.get(2)?,
+                        album_peak: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    /// Stores a track's measured loudness, keeping any album gain it has.
+    pub fn store_loudness(
+        &self,
+        path: &str,
+        lufs: f32,
+        gain_db: f32,
+        peak: f32,
+    ) -> Result<(), PlayerError> {
+        self.connection()
+            .execute(
+                "INSERT INTO loudness (path, lufs, gain_db, peak) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(path) DO UPDATE SET
+                    lufs = excluded.lufs, gain_db = excluded.gain_db, peak = excluded.peak",
+                params![path, lufs, gain_db, peak],
+            )
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    /// Sets the album gain and peak of already measured `paths`.
+    pub fn store_album_loudness(
+        &self,
+        paths: &[&str],
+        gain_db: f32,
+        peak: f32,
+    ) -> Result<(), PlayerError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction().map_err(db_error)?;
+        for path in paths {
+            tx.execute(
+                "UPDATE loudness SET album_gain_db = ?2, album_peak = ?3 WHERE path = ?1",
+                params![path, gain_db, peak],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)
+    }
+
+    pub fn clear(&self) -> Result<(), PlayerError> {
+        self.connection()
+            .execute("DELETE FROM tracks", [])
+            .map(|_| ())
+            .map_err(db_error)
+    }
+}
+
+/// A failed query, reported as [`PlayerError::Other`] with SQLite's message.
+fn db_error(error: rusqlite::Error) -> PlayerError {
+    error.to_string().into()
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn trim_history(conn: &Connection, limit: usize) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM history WHERE id NOT IN
+            (SELECT id FROM history ORDER BY id DESC LIMIT ?1)",
+        [limit as i64],
+    )
+    .map(|_| ())
+}
+
+fn bookmark_from_row(row: &Row<'_>) -> rusqlite::Result<Bookmark> {
+    Ok(Bookmark {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        position_ms: row.get::<_, i64>(2)? as u64,
+        label: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn stats_from_row(row: &Row<'_>) -> rusqlite::Result<TrackStats> {
+    Ok(TrackStats {
+        path: row.get(0)?,
+        play_count: row.get::<_, i64>(1)? as u64,
+        listened_ms: row.get::<_, i64>(2)? as u64,
+        last_played: row.get(3)?,
+    })
+}
+
+/// Builds a [`TrackInfo`] from a row selected with [`TRACK_COLUMNS`].
+pub fn track_from_row(row: &Row<'_>) -> rusqlite::Result<TrackInfo> {
+    Ok(TrackInfo {
+        path: row.get(0)?,
+        size: row.get::<_, i64>(1)? as u64,
+        extension: row.get(2)?,
+        metadata: Some(TrackMetadata {
+            title: row.get(3)?,
+            artist: row.get(4)?,
+            album: row.get(5)?,
+            album_artist: row.get(6)?,
+            track_number: row.get(7)?,
+            disc_number: row.get(8)?,
+            year: row.get(9)?,
+            genre: row.get(10)?,
+            duration_ms: row.get::<_, i64>(11)? as u64,
+        }),
+        rating: Some(Rating {
+            stars: row.get(12)?,
+            favorite: row.get(13)?,
+        }),
+    })
+}
+
+/// Turns free text into an FTS5 query matching every word as a prefix, so
+/// `beat` finds "Beatles". Words are quoted to neutralise FTS syntax.
+fn fts_prefix_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Index rows are keyed by absolute path so the same file can't appear twice.
+pub fn absolute(path: &Path) -> Result<PathBuf, PlayerError> {
+    fs::canonicalize(path).map_err(|e| PlayerError::io("resolve", path, e))
+}
+
+/// The key plays of `path` are stored under: its [`index_key`], or the path
+/// as given for streams and files that can't be resolved.
+fn play_key(path: &str) -> String {
+    index_key(Path::new(path)).unwrap_or_else(|_| path.to_string())
+}
+
+/// The key [`LibraryIndex::index_file`] stores `path` under: its absolute
+/// path, or for a CUE sheet's virtual track, the sheet's plus the number.
+pub fn index_key(path: &Path) -> Result<String, PlayerError> {
+    match path.to_str().and_then(cue::split_virtual) {
+        Some((sheet, number)) => Ok(cue::virtual_path(&absolute(sheet)?, number)),
+        None => Ok(absolute(path)?.to_string_lossy().into_owned()),
+    }
+}
+
+/// Latest mtime of the files `path` is read from: the file itself, or both a
+/// CUE sheet and the image its virtual track is cut from.
+pub fn source_mtime(path: &Path) -> Result<i64, PlayerError> {
+    let (file, _) = decoder::locate(path)?;
+    let sheet = path
+        .to_str()
+        .and_then(cue::split_virtual)
+        .map(|(sheet, _)| sheet);
+    let latest = sheet
+        .into_iter()
+        .chain([file.as_path()])
+        .try_fold(0, |latest, source| {
+            let meta = fs::metadata(source).map_err(|e| PlayerError::io("read", source, e))?;
+            Ok(latest.max(mtime_millis(&meta)))
+        });
+    latest
+}
+
+pub fn mtime_millis(meta: &fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// Returns `true` if the track was (re)indexed, `false` if it was unchanged.
+/// Reindexed tracks are queued for silence detection.
+#[tauri::command]
+pub async fn index_track(path: String, app: AppHandle) -> Result<bool, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let changed = app.state::<LibraryIndex>().index_file(Path::new(&path))?;
+        if let (true, Some(scanner)) = (changed, app.try_state::<SilenceScanner>()) {
+            scanner.analyze(&path);
+        }
+        Ok(changed)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_all_tracks(app: AppHandle) -> Result<Vec<TrackInfo>, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<LibraryIndex>().all_tracks())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn search_tracks(
+    query: String,
+    limit: usize,
+    app: AppHandle,
+) -> Result<Vec<TrackInfo>, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<LibraryIndex>().search(&query, limit))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn clear_index(index: tauri::State<'_, LibraryIndex>) -> Result<(), PlayerError> {
+    index.clear()
+}
@@ -1,3 +1,49 @@
+use tauri::Manager;
+
+mod artwork;
+mod audio;
+mod bookmarks;
+mod browse;
+mod channels;
+mod cue;
+mod decoder;
+mod dsp;
+mod duplicates;
+mod equalizer;
+mod error;
+mod export;
+mod history;
+mod index;
+mod library;
+mod logging;
+mod loudness;
+mod media;
+mod metadata;
+mod output;
+mod playlist;
+mod properties;
+mod queue;
+mod rating;
+mod remote;
+mod replaygain;
+mod scrobble;
+mod session;
+mod settings;
+mod shortcuts;
+mod silence;
+mod sleep;
+mod smart_playlist;
+mod sort;
+mod stats;
+mod stretch;
+mod sync;
+mod thumbnail;
+mod tray;
+mod visualizer;
+mod watcher;
+mod waveform;
+mod window_state;
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -10,7 +56,155 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(shortcuts::on_shortcut)
+                .build(),
+        )
+        .manage(audio::PlayerState::default())
+        .on_window_event(tray::on_window_event)
+        .on_window_event(window_state::on_window_event)
+        .setup(|app| {
+            logging::init(app.handle())?;
+            let settings = settings::SettingsStore::load(app.handle())?;
+            let player = app.state::<audio::PlayerState>();
+            let saved = settings.get();
+            player.set_volume_level(saved.volume);
+            player.set_fade(std::time::Duration::from_millis(saved.fade_ms));
+            player.equalizer.set_bands(saved.eq_bands);
+            player.equalizer.set_enabled(saved.eq_enabled);
+            player.channels.set_mono(saved.downmix_mono);
+            player.channels.set_balance(saved.balance);
+            app.manage(shortcuts::ShortcutBindings::register(
+                app.handle(),
+                &saved.shortcuts,
+            ));
+            app.manage(settings);
+            // Playback looks up trims and measured gains in these, so they have
+            // to be managed before anything can start a track.
+            app.manage(index::LibraryIndex::open_in_app_dir(app.handle())?);
+            app.manage(silence::SilenceScanner::start(app.handle())?);
+            player.start_monitor(app.handle().clone())?;
+            app.manage(window_state::WindowStateStore::start(app.handle())?);
+            let scrobbler = scrobble::Scrobbler::new(app.handle(), saved.scrobbling)?;
+            scrobbler.start(app.handle())?;
+            app.manage(scrobbler);
+            let session = session::SessionStore::new(app.handle())?;
+            session.restore(&player, app.handle());
+            session.start_autosave(app.handle().clone())?;
+            app.manage(session);
+            bookmarks::start(app.handle());
+            history::start(app.handle());
+            app.manage(watcher::LibraryWatcher::start(
+                app.handle(),
+                &saved.watched_folders,
+            )?);
+            // Not every desktop has a tray; the window still works without one.
+            let _ = tray::start(app.handle());
+            // Media keys are a nicety; without a session bus (say) carry on without them.
+            let _ = media::start(app.handle());
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            artwork::get_album_art,
+            audio::play,
+            audio::pause,
+            audio::resume,
+            audio::stop,
+            audio::seek,
+            audio::set_loop_region,
+            audio::clear_loop_region,
+            audio::set_gapless,
+            audio::set_crossfade,
+            audio::set_fade_duration,
+            audio::set_volume,
+            audio::toggle_mute,
+            bookmarks::add_bookmark,
+            bookmarks::remove_bookmark,
+            bookmarks::list_bookmarks,
+            bookmarks::jump_to_bookmark,
+            browse::list_folder,
+            channels::set_downmix_mono,
+            channels::set_balance,
+            duplicates::find_duplicates,
+            equalizer::set_eq_band,
+            equalizer::set_eq_enabled,
+            equalizer::save_eq_preset,
+            equalizer::load_eq_preset,
+            equalizer::list_eq_presets,
+            export::export_library,
+            export::import_library,
+            history::get_history,
+            history::clear_history,
+            history::requeue_from_history,
+            history::set_history_limit,
+            index::index_track,
+            index::get_all_tracks,
+            index::search_tracks,
+            index::clear_index,
+            library::scan_directory,
+            logging::get_log_path,
+            logging::open_log_folder,
+            loudness::analyze_loudness,
+            loudness::analyze_loudness_batch,
+            metadata::read_metadata,
+            metadata::write_metadata,
+            metadata::write_metadata_batch,
+            output::list_output_devices,
+            output::set_output_device,
+            playlist::import_playlist,
+            playlist::export_playlist,
+            properties::get_audio_properties,
+            queue::queue_add,
+            queue::queue_remove,
+            queue::queue_move,
+            queue::queue_clear,
+            queue::queue_get,
+            queue::next_track,
+            queue::previous_track,
+            queue::set_shuffle,
+            queue::set_repeat,
+            rating::set_rating,
+            rating::toggle_favorite,
+            rating::get_favorites,
+            remote::play_url,
+            replaygain::set_normalization,
+            scrobble::lastfm_authenticate,
+            scrobble::set_scrobbling,
+            session::clear_session,
+            shortcuts::get_global_shortcuts,
+            shortcuts::set_global_shortcut,
+            silence::set_skip_silence,
+            silence::set_silence_threshold,
+            sleep::set_sleep_timer,
+            sleep::set_sleep_timer_end_of_track,
+            sleep::cancel_sleep_timer,
+            smart_playlist::evaluate_smart_playlist,
+            smart_playlist::save_smart_playlist,
+            smart_playlist::load_smart_playlist,
+            smart_playlist::list_smart_playlists,
+            sort::sort_tracks,
+            stats::get_track_stats,
+            stats::get_top_tracks,
+            stats::get_listening_time,
+            stretch::set_playback_speed,
+            thumbnail::get_thumbnail,
+            thumbnail::clear_thumbnail_cache,
+            tray::set_close_to_tray,
+            visualizer::set_visualizer_enabled,
+            watcher::add_watched_folder,
+            watcher::remove_watched_folder,
+            watcher::list_watched_folders,
+            waveform::generate_waveform
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let player = app.state::<audio::PlayerState>();
+                app.state::<session::SessionStore>().shutdown(&player);
+                player.shutdown();
+            }
+        });
 }
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use walkdir::WalkDir;
+
+use crate::cue::{self, CUE_SHEET_INVALID_EVENT};
+use crate::error::PlayerError;
+use crate::metadata::TrackMetadata;
+use crate::rating::Rating;
+use crate::sort::natural_cmp;
+
+/// File extensions treated as playable audio, lowercase.
+pub const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a", "ogg", "wav", "opus"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub path: String,
+    pub size: u64,
+    pub extension: String,
+    /// Tag data, present for tracks that come from the library index.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<TrackMetadata>,
+    /// Stars and favorite flag, present for tracks that come from the library index.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<Rating>,
+}
+
+/// Lowercased extension of `path` if it is one of [`AUDIO_EXTENSIONS`].
+pub fn audio_extension(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    AUDIO_EXTENSIONS.contains(&ext.as_str()).then_some(ext)
+}
+
+/// Recursively collects audio files under `root` in natural name order,
+/// skipping entries that can't be read. A `root` that is a file lists itself.
+pub fn scan(root: &Path) -> Vec<TrackInfo> {
+    collect(WalkDir::new(root))
+}
+
+/// Like [`scan`], but only the files directly inside `dir`.
+pub fn scan_shallow(dir: &Path) -> Vec<TrackInfo> {
+    collect(WalkDir::new(dir).max_depth(1))
+}
+
+fn collect(walk: WalkDir) -> Vec<TrackInfo> {
+    walk.sort_by(|a, b| {
+        natural_cmp(
+            &a.file_name().to_string_lossy(),
+            &b.file_name().to_string_lossy(),
+        )
+    })
+    .into_iter()
+    .filter_map(Result::ok)
+    .filter(|entry| entry.file_type().is_file())
+    .filter_map(|entry| {
+        let extension = audio_extension(entry.path())?;
+        let size = entry.metadata().ok()?.len();
+        Some(TrackInfo {
+            path: entry.path().to_string_lossy().into_owned(),
+            size,
+            extension,
+            metadata: None,
+            rating: None,
+        })
+    })
+    .collect()
+}
+
+/// Lists the audio files under `root`, with files that a CUE sheet splits up
+/// replaced by its tracks. Sheets that can't be used are reported through
+/// `cue-sheet-invalid` and their files listed whole.
+#[tauri::command]
+pub async fn scan_directory(root: String, app: AppHandle) -> Result<Vec<TrackInfo>, PlayerError> {
+    let root_path = Path::new(&root);
+    if !root_path.is_dir() {
+        return Err(format!("{root} is not a directory").into());
+    }
+    // Walking a large library is blocking I/O; keep it off the async runtime threads.
+    tauri::async_runtime::spawn_blocking(move || {
+        let (tracks, errors) = cue::expand(scan(Path::new(&root)));
+        for error in errors {
+            let _ = app.emit(CUE_SHEET_INVALID_EVENT, error);
+        }
+        tracks
+    })
+    .await
+    .map_err(|e| e.to_string().into())
+}
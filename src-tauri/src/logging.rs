@@ -0,0 +1,166 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::error::PlayerError;
+use crate::index::now_millis;
+use crate::sync::MutexExt;
+
+pub const LOG_FILE: &str = "memory-player.log";
+
+/// The log is rotated once it grows past this many bytes.
+pub const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Rotated logs kept next to the current one, as `memory-player.1.log` and up.
+pub const KEEP_ROTATED: usize = 3;
+
+/// Writes records to [`LOG_FILE`] in the app log dir in release builds, and
+/// to stderr in debug builds.
+struct Logger {
+    file: Option<Mutex<LogFile>>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(LogFile {
+            path,
+            file,
+            written,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.written + line.len() as u64 > MAX_LOG_BYTES && self.written > 0 {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts each log up one place, dropping the oldest, and starts afresh.
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..KEEP_ROTATED).rev() {
+            let _ = fs::rename(rotated(&self.path, i), rotated(&self.path, i + 1));
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))?;
+        *self = LogFile::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+/// `memory-player.log` becomes `memory-player.<n>.log`.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{n}.log"))
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} [{}] {}\n",
+            now_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        match &self.file {
+            Some(file) => {
+                let _ = file.locked().write(&line);
+            }
+            None => eprint!("{line}"),
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.locked().file.flush();
+        }
+    }
+}
+
+fn log_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_log_dir().map_err(|e| e.to_string())?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join(LOG_FILE))
+}
+
+/// Installs the logger and a panic hook that logs panics, with the thread
+/// they happened on, before the default hook runs. Called first thing in `setup`.
+pub fn init(app: &AppHandle) -> Result<(), String> {
+    let file = if cfg!(debug_assertions) {
+        None
+    } else {
+        let path = log_path(app)?;
+        Some(Mutex::new(LogFile::open(path).map_err(|e| e.to_string())?))
+    };
+    log::set_boxed_logger(Box::new(Logger { file })).map_err(|e| e.to_string())?;
+    log::set_max_level(if cfg!(debug_assertions) {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let thread = thread::current();
+        log::error!(
+            "thread '{}' panicked: {info}",
+            thread.name().unwrap_or("<unnamed>")
+        );
+        log::logger().flush();
+        default_hook(info);
+    }));
+    log::info!(
+        "{} {} started",
+        app.package_info().name,
+        app.package_info().version
+    );
+    Ok(())
+}
+
+/// Runs one pass of a background loop, so a panic in it is logged by the
+/// panic hook and the thread carries on with the next pass instead of dying.
+/// Locks it held are recovered by [`MutexExt::locked`] rather than left
+/// poisoned for the next pass.
+pub fn guarded(pass: impl FnOnce()) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(pass));
+}
+
+/// Path of the current log file, for attaching to bug reports.
+#[tauri::command]
+pub fn get_log_path(app: AppHandle) -> Result<String, PlayerError> {
+    Ok(log_path(&app)?.to_string_lossy().into_owned())
+}
+
+/// Opens the folder holding the log files in the system file manager.
+#[tauri::command]
+pub fn open_log_folder(app: AppHandle) -> Result<(), PlayerError> {
+    let path = log_path(&app)?;
+    let dir = path.parent().unwrap_or(&path);
+    app.opener()
+        .open_path(dir.to_string_lossy(), None::<&str>)
+        .map_err(|e| e.to_string().into())
+}
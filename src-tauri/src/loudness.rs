@@ -0,0 +1,366 @@
+use std::f64::consts::PI;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::index::LibraryIndex;
+use crate::logging;
+use crate::replaygain::ReplayGain;
+use crate::sync::MutexExt;
+
+/// Loudness measured gains aim for, as in ReplayGain 2.0.
+pub const REFERENCE_LUFS: f64 = -18.0;
+
+/// Blocks quieter than this never count towards the integrated loudness.
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Blocks more than this far below the ungated loudness are dropped too.
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Gating blocks are 400 ms long and start every 100 ms, overlapping by 75%.
+const STEPS_PER_BLOCK: usize = 4;
+const STEPS_PER_SECOND: u32 = 10;
+
+/// Most tracks of a batch analyzed at once.
+const MAX_WORKERS: usize = 8;
+
+/// Biquad coefficients, normalized so `a0 == 1`.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    /// The two stages of the BS.1770 K-weighting filter at `rate`: a high
+    /// shelf modelling the head, then a high-pass cutting the lowest bass.
+    fn k_weighting(rate: u32) -> [Biquad; 2] {
+        let rate = rate.max(1) as f64;
+
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+        [shelf, high_pass]
+    }
+}
+
+/// Direct form II transposed state of one biquad on one channel.
+#[derive(Debug, Default, Clone, Copy)]
+struct BiquadState {
+    z: [f64; 2],
+}
+
+impl BiquadState {
+    fn process(&mut self, filter: &Biquad, x: f64) -> f64 {
+        let y = filter.b[0] * x + self.z[0];
+        self.z[0] = filter.b[1] * x - filter.a[0] * y + self.z[1];
+        self.z[1] = filter.b[2] * x - filter.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 weight of channel `index` out of `channels`: surrounds count
+/// 1.41 times, the LFE of a 5.1 layout not at all.
+fn channel_weight(index: usize, channels: usize) -> f64 {
+    match (channels, index) {
+        (6, 3) => 0.0,
+        (_, 0..=2) => 1.0,
+        _ => 1.41,
+    }
+}
+
+/// Mean square loudness of a 400 ms block, in LUFS.
+fn block_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Integrated loudness of gating blocks (mean squares) per EBU R128, or
+/// `None` if every block is below the absolute gate.
+pub fn integrated(blocks: &[f64]) -> Option<f64> {
+    let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+        let (sum, count) = blocks.fold((0.0, 0usize), |(s, n), p| (s + p, n + 1));
+        (count > 0).then(|| sum / count as f64)
+    };
+    let audible = |p: &&f64| block_lufs(**p) > ABSOLUTE_GATE_LUFS;
+    let ungated = mean(&mut blocks.iter().filter(audible).copied())?;
+    let threshold = block_lufs(ungated) + RELATIVE_GATE_LU;
+    let gated = mean(
+        &mut blocks
+            .iter()
+            .filter(audible)
+            .filter(|p| block_lufs(**p) > threshold)
+            .copied(),
+    )?;
+    Some(block_lufs(gated))
+}
+
+/// Gain in dB bringing `lufs` to [`REFERENCE_LUFS`].
+pub fn gain_for(lufs: f64) -> f32 {
+    (REFERENCE_LUFS - lufs) as f32
+}
+
+/// Result of measuring one track.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub lufs: f64,
+    /// Highest sample magnitude, full scale being 1.0.
+    pub peak: f32,
+    /// Mean square of every gating block, kept for pooling into an album.
+    pub blocks: Vec<f64>,
+}
+
+/// Decodes `path` and measures its integrated loudness.
+pub fn measure(path: &Path) -> Result<Measurement, PlayerError> {
+    let mut filters = Biquad::k_weighting(0);
+    let mut states: Vec<[BiquadState; 2]> = Vec::new();
+    let mut filter_rate = 0;
+    let mut step_frames = 1;
+    let (mut step_power, mut step_filled) = (0.0f64, 0usize);
+    let mut steps: Vec<f64> = Vec::new();
+    let mut blocks = Vec::new();
+    let mut peak = 0.0f32;
+    decoder::decode_interleaved(path, |samples, channels, rate| {
+        if rate != filter_rate || states.len() != channels {
+            filter_rate = rate;
+            filters = Biquad::k_weighting(rate);
+            states = vec![Default::default(); channels];
+            step_frames = (rate / STEPS_PER_SECOND).max(1) as usize;
+        }
+        for frame in samples.chunks_exact(channels) {
+            for (i, (&sample, [shelf, high_pass])) in frame.iter().zip(&mut states).enumerate() {
+                peak = peak.max(sample.abs());
+                let shelved = shelf.process(&filters[0], sample as f64);
+                let weighted = high_pass.process(&filters[1], shelved);
+                step_power += channel_weight(i, channels) * weighted * weighted;
+            }
+            step_filled += 1;
+            if step_filled == step_frames {
+                steps.push(step_power / step_frames as f64);
+                (step_power, step_filled) = (0.0, 0);
+                if steps.len() >= STEPS_PER_BLOCK {
+                    let last = &steps[steps.len() - STEPS_PER_BLOCK..];
+                    blocks.push(last.iter().sum::<f64>() / STEPS_PER_BLOCK as f64);
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+    let lufs = integrated(&blocks)
+        .ok_or_else(|| PlayerError::decode(format!("{} is silent", path.display())))?;
+    Ok(Measurement { lufs, peak, blocks })
+}
+
+/// Gains stored for `path` by loudness analysis, for files without
+/// ReplayGain tags of their own.
+pub fn measured(app: &AppHandle, path: &str) -> ReplayGain {
+    app.try_state::<LibraryIndex>()
+        .and_then(|index| index.loudness(path).ok()?)
+        .unwrap_or_default()
+}
+
+/// One track of [`AlbumLoudness`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackLoudness {
+    pub path: String,
+    pub lufs: f32,
+    pub gain_db: f32,
+}
+
+/// Payload returned by [`analyze_loudness_batch`].
+#[derive(Debug, Clone, Serialize)]
+pub struct AlbumLoudness {
+    pub tracks: Vec<TrackLoudness>,
+    /// Tracks that couldn't be decoded or are silent.
+    pub failed: Vec<String>,
+    /// Loudness of the tracks played back to back, `None` if none could be measured.
+    pub album_lufs: Option<f32>,
+    pub album_gain_db: Option<f32>,
+}
+
+/// Measures `paths` on a pool of worker threads, in the order given.
+fn measure_all(paths: &[String]) -> Vec<Result<Measurement, PlayerError>> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_WORKERS)
+        .min(paths.len())
+        .max(1);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(paths.len()));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(i) else {
+                    break;
+                };
+                let mut result = Err(PlayerError::decode(format!("analyzing {path} failed")));
+                logging::guarded(|| result = measure(Path::new(path)));
+                results.locked().push((i, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Measures the integrated loudness of `path` per EBU R128 and stores it, with
+/// the gain reaching [`REFERENCE_LUFS`], for normalizing untagged files.
+/// Returns the loudness in LUFS.
+#[tauri::command]
+pub async fn analyze_loudness(path: String, app: AppHandle) -> Result<f32, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let measurement = measure(Path::new(&path))?;
+        app.state::<LibraryIndex>().store_loudness(
+            &path,
+            measurement.lufs as f32,
+            gain_for(measurement.lufs),
+            measurement.peak,
+        )?;
+        Ok(measurement.lufs as f32)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Measures every track of an album like [`analyze_loudness`], in parallel,
+/// then the album as a whole, storing an album gain shared by its tracks.
+#[tauri::command]
+pub async fn analyze_loudness_batch(
+    paths: Vec<String>,
+    app: AppHandle,
+) -> Result<AlbumLoudness, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let index = app.state::<LibraryIndex>();
+        let mut album = AlbumLoudness {
+            tracks: Vec::new(),
+            failed: Vec::new(),
+            album_lufs: None,
+            album_gain_db: None,
+        };
+        let mut blocks = Vec::new();
+        let mut peak = 0.0f32;
+        for (path, result) in paths.iter().zip(measure_all(&paths)) {
+            let Ok(measurement) = result else {
+                album.failed.push(path.clone());
+                continue;
+            };
+            let (lufs, gain_db) = (measurement.lufs as f32, gain_for(measurement.lufs));
+            index.store_loudness(path, lufs, gain_db, measurement.peak)?;
+            album.tracks.push(TrackLoudness {
+                path: path.clone(),
+                lufs,
+                gain_db,
+            });
+            blocks.extend(measurement.blocks);
+            peak = peak.max(measurement.peak);
+        }
+        if let Some(lufs) = integrated(&blocks) {
+            let gain_db = gain_for(lufs);
+            let measured: Vec<&str> = album.tracks.iter().map(|t| t.path.as_str()).collect();
+            index.store_album_loudness(&measured, gain_db, peak)?;
+            album.album_lufs = Some(lufs as f32);
+            album.album_gain_db = Some(gain_db);
+        }
+        Ok(album)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn nothing_audible_has_no_loudness() {
+        assert_eq!(integrated(&[]), None);
+        assert_eq!(integrated(&[0.0; 8]), None);
+        assert_eq!(integrated(&[1e-9, 1e-8]), None);
+    }
+
+    #[test]
+    fn constant_power_gives_its_block_loudness() {
+        for power in [1.0, 0.1, 1e-3] {
+            let lufs = integrated(&[power; 20]).unwrap();
+            assert_close(lufs, -0.691 + 10.0 * power.log10());
+        }
+    }
+
+    #[test]
+    fn absolute_gate_ignores_silence() {
+        let mut blocks = vec![0.1; 10];
+        blocks.extend([0.0, 1e-9, 1e-8]);
+        assert_close(integrated(&blocks).unwrap(), block_lufs(0.1));
+    }
+
+    #[test]
+    fn relative_gate_drops_quiet_passages() {
+        // -40.7 LUFS is audible but more than 10 LU below the loud blocks.
+        let mut blocks = vec![0.1; 10];
+        blocks.extend([1e-4; 10]);
+        assert_close(integrated(&blocks).unwrap(), block_lufs(0.1));
+
+        // Blocks only 3 LU quieter stay in.
+        let blocks = [0.1, 0.05];
+        assert_close(integrated(&blocks).unwrap(), block_lufs(0.075));
+    }
+
+    #[test]
+    fn gain_reaches_the_reference() {
+        assert_eq!(gain_for(-18.0), 0.0);
+        assert_eq!(gain_for(-8.0), -10.0);
+        assert_eq!(gain_for(-23.0), 5.0);
+    }
+
+    #[test]
+    fn full_scale_sine_measures_minus_three_lufs() {
+        // BS.1770's reference: a 997 Hz sine at 0 dBFS on one channel.
+        let rate = 48_000;
+        let [shelf, high_pass] = Biquad::k_weighting(rate);
+        let (mut shelved, mut filtered) = (BiquadState::default(), BiquadState::default());
+        let weighted: Vec<f64> = (0..rate * 3)
+            .map(|n| {
+                let x = (2.0 * PI * 997.0 * n as f64 / rate as f64).sin();
+                filtered.process(&high_pass, shelved.process(&shelf, x))
+            })
+            .collect();
+        // Skip the first second while the filters settle.
+        let settled = &weighted[rate as usize..];
+        let power = settled.iter().map(|y| y * y).sum::<f64>() / settled.len() as f64;
+        assert!(
+            (block_lufs(power) + 3.01).abs() < 0.05,
+            "{}",
+            block_lufs(power)
+        );
+    }
+}
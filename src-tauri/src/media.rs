@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use souvlaki::{
+    MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig,
+    SeekDirection,
+};
+use tauri::{AppHandle, Listener, Manager};
+
+use crate::artwork::{self, MAX_ART_DIMENSION};
+use crate::audio::{self, PlaybackState, PlaybackStatus, PlayerState, PLAYBACK_STATE_EVENT};
+use crate::metadata;
+use crate::queue;
+
+/// Bus name suffix under `org.mpris.MediaPlayer2` on Linux.
+pub const DBUS_NAME: &str = "memory_player";
+
+/// Cover art is written here (in the app cache dir) because the OS controls
+/// only accept it by URL.
+pub const COVER_FILE: &str = "now-playing-cover";
+
+/// How far the "seek" media keys jump when the OS doesn't say.
+pub const SEEK_STEP: Duration = Duration::from_secs(10);
+
+/// Registers with the OS now-playing UI, mirrors `playback-state-changed`
+/// to it, and routes media keys back into the player.
+///
+/// `MediaControls` isn't `Send` on every platform, so it lives on its own
+/// thread and is fed statuses through a channel.
+pub fn start(app: &AppHandle) -> Result<(), String> {
+    // SMTC attaches to a window; the other platforms ignore this.
+    #[cfg(target_os = "windows")]
+    let hwnd = app
+        .get_webview_window("main")
+        .and_then(|window| window.hwnd().ok())
+        .map(|hwnd| hwnd.0 as usize);
+    #[cfg(not(target_os = "windows"))]
+    let hwnd: Option<usize> = None;
+
+    let (updates, rx) = mpsc::channel();
+    let (ready, ready_rx) = mpsc::channel();
+    let handle = app.clone();
+    thread::Builder::new()
+        .name("media-controls".into())
+        .spawn(move || {
+            let config = PlatformConfig {
+                display_name: &handle.package_info().name,
+                dbus_name: DBUS_NAME,
+                hwnd: hwnd.map(|h| h as *mut std::ffi::c_void),
+            };
+            let controls = MediaControls::new(config).and_then(|mut controls| {
+                let app = handle.clone();
+                controls.attach(move |event| on_event(&app, event))?;
+                Ok(controls)
+            });
+            match controls {
+                Ok(controls) => {
+                    let _ = ready.send(Ok(()));
+                    run(controls, rx, &handle);
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(format!("failed to register media controls: {e:?}")));
+                }
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    ready_rx.recv().map_err(|e| e.to_string())??;
+
+    app.listen(PLAYBACK_STATE_EVENT, move |event| {
+        if let Ok(status) = serde_json::from_str(event.payload()) {
+            let _ = updates.send(status);
+        }
+    });
+    Ok(())
+}
+
+/// Applies statuses until every sender is gone.
+fn run(mut controls: MediaControls, updates: Receiver<PlaybackStatus>, app: &AppHandle) {
+    let mut shown: Option<String> = None;
+    for status in updates {
+        if status.path != shown {
+            shown = status.path.clone();
+            let _ = match shown.as_deref() {
+                Some(path) => show_track(&mut controls, Path::new(path), app),
+                None => controls.set_metadata(MediaMetadata::default()),
+            };
+        }
+        let progress = app.state::<PlayerState>().position().map(MediaPosition);
+        let _ = controls.set_playback(match status.state {
+            PlaybackState::Playing => MediaPlayback::Playing { progress },
+            PlaybackState::Paused => MediaPlayback::Paused { progress },
+            PlaybackState::Stopped => MediaPlayback::Stopped,
+        });
+    }
+}
+
+fn show_track(
+    controls: &mut MediaControls,
+    path: &Path,
+    app: &AppHandle,
+) -> Result<(), souvlaki::Error> {
+    let tags = metadata::read(path).ok();
+    let cover = write_cover(path, app).map(|file| format!("file://{}", file.display()));
+    controls.set_metadata(MediaMetadata {
+        title: tags.as_ref().map(|t| t.title.as_str()),
+        album: tags.as_ref().map(|t| t.album.as_str()),
+        artist: tags.as_ref().map(|t| t.artist.as_str()),
+        cover_url: cover.as_deref(),
+        duration: tags.as_ref().map(|t| Duration::from_millis(t.duration_ms)),
+    })
+}
+
+/// Extracts the embedded cover of `path` to [`COVER_FILE`], if it has one.
+fn write_cover(path: &Path, app: &AppHandle) -> Option<PathBuf> {
+    let art = artwork::embedded(path).ok()??;
+    let art = artwork::limit_size(art, MAX_ART_DIMENSION).ok()?;
+    let extension = art.mime.strip_prefix("image/").unwrap_or("jpg");
+    let dir = app.path().app_cache_dir().ok()?;
+    fs::create_dir_all(&dir).ok()?;
+    let file = dir.join(format!("{COVER_FILE}.{extension}"));
+    fs::write(&file, &art.data).ok()?;
+    Some(file)
+}
+
+/// Routes an OS media event to the matching command.
+fn on_event(app: &AppHandle, event: MediaControlEvent) {
+    let player = || app.state::<PlayerState>();
+    let seek_by = |forward: bool, step: Duration| {
+        let Some(position) = player().position() else {
+            return;
+        };
+        let target = if forward {
+            position + step
+        } else {
+            position.saturating_sub(step)
+        };
+        audio::in_background(app, move |player, _| player.seek_to(target));
+    };
+    let playing = player().status().state == PlaybackState::Playing;
+    match event {
+        MediaControlEvent::Pause => audio::pause(app.clone(), player()),
+        MediaControlEvent::Toggle if playing => audio::pause(app.clone(), player()),
+        MediaControlEvent::Play | MediaControlEvent::Toggle => audio::resume(app.clone(), player()),
+        MediaControlEvent::Stop => audio::stop(app.clone(), player()),
+        MediaControlEvent::Next => audio::in_background(app, queue::skip_forward),
+        MediaControlEvent::Previous => audio::in_background(app, queue::skip_back),
+        MediaControlEvent::Seek(direction) => {
+            seek_by(direction == SeekDirection::Forward, SEEK_STEP)
+        }
+        MediaControlEvent::SeekBy(direction, step) => {
+            seek_by(direction == SeekDirection::Forward, step)
+        }
+        MediaControlEvent::SetPosition(MediaPosition(position)) => {
+            audio::in_background(app, move |player, _| player.seek_to(position))
+        }
+        _ => {}
+    }
+}
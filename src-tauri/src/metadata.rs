@@ -0,0 +1,317 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lofty::config::WriteOptions;
+use lofty::error::{ErrorKind, LoftyError};
+use lofty::id3::v2::PopularimeterFrame;
+use lofty::prelude::*;
+use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::cue;
+use crate::error::PlayerError;
+use crate::index::LibraryIndex;
+
+pub const UNKNOWN_ARTIST: &str = "Unknown Artist";
+pub const UNKNOWN_ALBUM: &str = "Unknown Album";
+pub const UNKNOWN_GENRE: &str = "Unknown Genre";
+
+/// Rater identity on POPM frames written by `write_rating`.
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+
+/// Tag data for a single file. Missing fields are filled with defaults rather
+/// than left empty so the UI never has to special-case `null`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackMetadata {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: u32,
+    pub disc_number: u32,
+    pub year: u32,
+    pub genre: String,
+    pub duration_ms: u64,
+}
+
+/// The tag lofty considers primary for the file type (e.g. ID3v2 over APE in
+/// an MP3), falling back to whichever tag is present.
+pub fn preferred_tag(file: &lofty::file::TaggedFile) -> Option<&Tag> {
+    file.primary_tag().or_else(|| file.first_tag())
+}
+
+/// Classifies a lofty failure to `action` `path`, e.g.
+/// `tag_error("read tags from", path, e)`: a missing file becomes
+/// [`PlayerError::FileNotFound`], a file lofty can't parse
+/// [`PlayerError::UnsupportedFormat`].
+pub fn tag_error(action: &str, path: &Path, error: LoftyError) -> PlayerError {
+    let message = format!("failed to {action} {}: {error}", path.display());
+    let path = path.to_string_lossy().into_owned();
+    match error.kind() {
+        ErrorKind::Io(e) if e.kind() == io::ErrorKind::NotFound => {
+            PlayerError::FileNotFound { path, message }
+        }
+        ErrorKind::Io(_) => PlayerError::IoError {
+            path: Some(path),
+            message,
+        },
+        _ => PlayerError::unsupported(message),
+    }
+}
+
+/// Tags of `path`, which may also be a CUE sheet's virtual track.
+pub fn read(path: &Path) -> Result<TrackMetadata, PlayerError> {
+    if let Some(found) = path.to_str().and_then(cue::read_metadata) {
+        return Ok(found?);
+    }
+    let file = lofty::read_from_path(path).map_err(|e| tag_error("read tags from", path, e))?;
+    let tag = preferred_tag(&file);
+    let text = |value: Option<String>, fallback: &str| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| fallback.to_string())
+    };
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let artist = text(tag.and_then(|t| t.artist()).map(Into::into), UNKNOWN_ARTIST);
+    let album_artist = text(
+        tag.and_then(|t| t.get_string(&ItemKey::AlbumArtist))
+            .map(Into::into),
+        &artist,
+    );
+
+    Ok(TrackMetadata {
+        title: text(tag.and_then(|t| t.title()).map(Into::into), &stem),
+        album: text(tag.and_then(|t| t.album()).map(Into::into), UNKNOWN_ALBUM),
+        genre: text(tag.and_then(|t| t.genre()).map(Into::into), UNKNOWN_GENRE),
+        track_number: tag.and_then(|t| t.track()).unwrap_or(0),
+        disc_number: tag.and_then(|t| t.disk()).unwrap_or(0),
+        year: tag.and_then(|t| t.year()).unwrap_or(0),
+        duration_ms: file.properties().duration().as_millis() as u64,
+        artist,
+        album_artist,
+    })
+}
+
+#[tauri::command]
+pub async fn read_metadata(path: String) -> Result<TrackMetadata, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || read(Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Stores `value` under `key`, or drops the item when `value` is blank.
+fn set_text(tag: &mut Tag, key: ItemKey, value: &str) {
+    let value = value.trim();
+    if value.is_empty() {
+        tag.remove_key(&key);
+    } else {
+        tag.insert_text(key, value.to_string());
+    }
+}
+
+/// Sibling path the edited copy is written to before it replaces `path`.
+fn staging_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.tagtmp"))
+}
+
+/// A set of tag edits; `None` fields are left as they are. Empty strings and
+/// zero numbers remove the field.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartialMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+}
+
+impl PartialMetadata {
+    /// The fields of `updated` that differ from `current`.
+    fn diff(current: &TrackMetadata, updated: &TrackMetadata) -> Self {
+        fn changed<T: PartialEq + Clone>(current: &T, updated: &T) -> Option<T> {
+            (current != updated).then(|| updated.clone())
+        }
+        PartialMetadata {
+            title: changed(&current.title, &updated.title),
+            artist: changed(&current.artist, &updated.artist),
+            album: changed(&current.album, &updated.album),
+            album_artist: changed(&current.album_artist, &updated.album_artist),
+            track_number: changed(&current.track_number, &updated.track_number),
+            disc_number: changed(&current.disc_number, &updated.disc_number),
+            year: changed(&current.year, &updated.year),
+            genre: changed(&current.genre, &updated.genre),
+        }
+    }
+}
+
+/// Writes the fields of `updated` that differ from what [`read`] reports.
+pub fn write(path: &Path, updated: &TrackMetadata) -> Result<(), PlayerError> {
+    let current = read(path)?;
+    apply(path, &PartialMetadata::diff(&current, updated))
+}
+
+/// Applies `changes`, leaving every other item, picture, and tag in the file
+/// alone. Edits go to the file's existing tag format, or its primary format
+/// if it has no tag.
+///
+/// The edit is made on a copy that then replaces the original, so a crash
+/// mid-write leaves the original intact.
+pub fn apply(path: &Path, changes: &PartialMetadata) -> Result<(), PlayerError> {
+    let mut tag = editable_tag(path)?;
+    if *changes == PartialMetadata::default() {
+        return Ok(());
+    }
+
+    let texts = [
+        (ItemKey::TrackTitle, &changes.title),
+        (ItemKey::TrackArtist, &changes.artist),
+        (ItemKey::AlbumTitle, &changes.album),
+        (ItemKey::AlbumArtist, &changes.album_artist),
+        (ItemKey::Genre, &changes.genre),
+    ];
+    for (key, value) in texts {
+        if let Some(value) = value {
+            set_text(&mut tag, key, value);
+        }
+    }
+    // Zero means "not set", matching what `read` reports for a missing number.
+    match changes.track_number {
+        Some(0) => tag.remove_track(),
+        Some(n) => tag.set_track(n),
+        None => {}
+    }
+    match changes.disc_number {
+        Some(0) => tag.remove_disk(),
+        Some(n) => tag.set_disk(n),
+        None => {}
+    }
+    match changes.year {
+        Some(0) => tag.remove_year(),
+        Some(n) => tag.set_year(n),
+        None => {}
+    }
+    save(path, &tag)
+}
+
+/// Writes a 0-5 star rating into `path`'s tags: a POPM frame for ID3v2, a
+/// 0-100 `RATING` value elsewhere. Zero stars removes it.
+pub fn write_rating(path: &Path, stars: u8) -> Result<(), PlayerError> {
+    let mut tag = editable_tag(path)?;
+    if stars == 0 {
+        tag.remove_key(&ItemKey::Popularimeter);
+    } else if tag.tag_type() == TagType::Id3v2 {
+        // Windows Media Player's star steps, which most other players read too.
+        let byte = [1, 64, 128, 196, 255][usize::from(stars.min(5)) - 1];
+        let frame = PopularimeterFrame::new(POPM_EMAIL.to_string(), byte, 0)
+            .as_bytes()
+            .map_err(|e| tag_error("encode a rating for", path, e))?;
+        tag.insert(TagItem::new(
+            ItemKey::Popularimeter,
+            ItemValue::Binary(frame),
+        ));
+    } else if !tag.insert_text(ItemKey::Popularimeter, (u32::from(stars) * 20).to_string()) {
+        return Err(PlayerError::unsupported(format!(
+            "{} can't store a rating in its tags",
+            path.display()
+        )));
+    }
+    save(path, &tag)
+}
+
+/// The tag an edit to `path` should start from: the one `read` uses, or a
+/// new one of the format's primary type. Fails for read-only files, and for
+/// virtual tracks, whose tags come from their CUE sheet.
+fn editable_tag(path: &Path) -> Result<Tag, PlayerError> {
+    if path.to_str().and_then(cue::split_virtual).is_some() {
+        return Err(PlayerError::unsupported(format!(
+            "{} is a track of a CUE sheet, which has no tags to edit",
+            path.display()
+        )));
+    }
+    let permissions = fs::metadata(path)
+        .map_err(|e| PlayerError::io("read", path, e))?
+        .permissions();
+    if permissions.readonly() {
+        return Err(PlayerError::IoError {
+            path: Some(path.to_string_lossy().into_owned()),
+            message: format!("{} is read-only", path.display()),
+        });
+    }
+    let file = lofty::read_from_path(path).map_err(|e| tag_error("read tags from", path, e))?;
+    Ok(preferred_tag(&file)
+        .cloned()
+        .unwrap_or_else(|| Tag::new(file.primary_tag_type())))
+}
+
+/// Saves `tag` into a copy of `path`, then swaps the copy in, so a failed
+/// write never leaves the original half-rewritten.
+fn save(path: &Path, tag: &Tag) -> Result<(), PlayerError> {
+    let staging = staging_path(path);
+    fs::copy(path, &staging).map_err(|e| PlayerError::io("copy", path, e))?;
+    let written = tag
+        .save_to_path(&staging, WriteOptions::default())
+        .map_err(|e| tag_error("write tags to", path, e))
+        .and_then(|()| fs::rename(&staging, path).map_err(|e| PlayerError::io("replace", path, e)));
+    if written.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    written
+}
+
+/// Updates the tags of `path` in place and refreshes its library index row.
+/// Fields left as [`read_metadata`] returned them are not touched.
+#[tauri::command]
+pub async fn write_metadata(
+    path: String,
+    metadata: TrackMetadata,
+    app: AppHandle,
+) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = Path::new(&path);
+        write(path, &metadata)?;
+        app.state::<LibraryIndex>().index_file(path)?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Applies the same `changes` to every file in `paths`, e.g. stamping a
+/// folder with one album name. Each file is written independently, so one
+/// failure doesn't stop the rest; results are in the order of `paths`.
+#[tauri::command]
+pub async fn write_metadata_batch(
+    paths: Vec<String>,
+    changes: PartialMetadata,
+    app: AppHandle,
+) -> Result<Vec<Result<(), PlayerError>>, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let index = app.state::<LibraryIndex>();
+        paths
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                apply(path, &changes)?;
+                index.index_file(path)?;
+                Ok(())
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| e.to_string().into())
+}
@@ -0,0 +1,120 @@
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use rodio::cpal::traits::HostTrait;
+use rodio::{DeviceTrait, OutputStream, OutputStreamHandle};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::audio::PlayerState;
+use crate::error::PlayerError;
+
+pub const DEVICE_CHANGED_EVENT: &str = "device-changed";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioDevice {
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// Payload of the `device-changed` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceChange {
+    pub name: Option<String>,
+    /// Set when the selected device vanished and playback fell back to the default.
+    pub disconnected: bool,
+}
+
+/// An open output stream. Dropping it closes the stream.
+pub struct Output {
+    pub handle: OutputStreamHandle,
+    /// Device picked by the user, or `None` for the system default.
+    pub device: Option<String>,
+    _release: Sender<()>,
+}
+
+impl Output {
+    /// Opens `device` by name, or the system default device for `None`.
+    ///
+    /// `rodio::OutputStream` is not `Send`, so it lives on its own thread until
+    /// this `Output` is dropped and only the (thread-safe) handle is handed back.
+    pub fn open(device: Option<String>) -> Result<Self, PlayerError> {
+        let (tx, rx) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let name = device.clone();
+        thread::Builder::new()
+            .name("audio-output".into())
+            .spawn(move || {
+                let stream = match name {
+                    Some(name) => find_device(&name)
+                        .ok_or_else(|| format!("output device {name} not found"))
+                        .and_then(|d| OutputStream::try_from_device(&d).map_err(|e| e.to_string())),
+                    None => OutputStream::try_default().map_err(|e| e.to_string()),
+                };
+                match stream {
+                    Ok((_stream, handle)) => {
+                        let _ = tx.send(Ok(handle));
+                        // Blocks until the sender is dropped along with the `Output`.
+                        let _ = released.recv();
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                    }
+                }
+            })
+            .map_err(|e| PlayerError::device(device.clone(), e.to_string()))?;
+        let handle = rx
+            .recv()
+            .map_err(|e| e.to_string())
+            .and_then(|opened| opened)
+            .map_err(|message| PlayerError::device(device.clone(), message))?;
+        Ok(Output {
+            handle,
+            device,
+            _release: release,
+        })
+    }
+}
+
+fn find_device(name: &str) -> Option<rodio::Device> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|d| d.name().is_ok_and(|n| n == name))
+}
+
+pub fn device_exists(name: &str) -> bool {
+    find_device(name).is_some()
+}
+
+pub fn list_devices() -> Vec<AudioDevice> {
+    let host = rodio::cpal::default_host();
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let Ok(devices) = host.output_devices() else {
+        return Vec::new();
+    };
+    devices
+        .filter_map(|d| d.name().ok())
+        .map(|name| AudioDevice {
+            is_default: default.as_deref() == Some(name.as_str()),
+            name,
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn list_output_devices() -> Vec<AudioDevice> {
+    list_devices()
+}
+
+/// Moves playback to `name`, continuing the current track from where it was.
+#[tauri::command]
+pub async fn set_output_device(name: String, app: AppHandle) -> Result<(), PlayerError> {
+    // Reopening the current track can wait on the network for a stream.
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<PlayerState>()
+            .switch_output(Some(name), false, &app)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
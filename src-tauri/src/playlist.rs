@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::error::PlayerError;
+use crate::metadata;
+use crate::remote;
+use crate::settings::write_atomic;
+
+/// Emitted by `import_playlist` when some entries were left out.
+pub const PLAYLIST_SKIPPED_EVENT: &str = "playlist-entries-skipped";
+
+/// Payload of the `playlist-entries-skipped` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntries {
+    pub playlist: String,
+    /// Entries as written in the playlist, after separator normalisation.
+    pub missing: Vec<String>,
+}
+
+/// Result of parsing a playlist: resolved paths that exist and stream URLs,
+/// plus the rest.
+pub struct Parsed {
+    pub tracks: Vec<PathBuf>,
+    pub missing: Vec<String>,
+}
+
+/// Decodes playlist (or CUE sheet) bytes: `.m3u8` and BOM-marked files are
+/// UTF-8, while plain `.m3u` files that aren't valid UTF-8 are read as Latin-1.
+pub(crate) fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Parses extended or plain M3U. Comment and directive lines (`#EXTM3U`,
+/// `#EXTINF`, ...) are ignored; relative entries resolve against `base`, and
+/// `file://` URLs are read as the paths they name.
+pub fn parse(text: &str, base: &Path) -> Parsed {
+    let mut parsed = Parsed {
+        tracks: Vec::new(),
+        missing: Vec::new(),
+    };
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Streams are played directly; whether they're reachable shows at play time.
+        if remote::is_url(line) {
+            parsed.tracks.push(PathBuf::from(line));
+            continue;
+        }
+        let entry = match line.strip_prefix("file://") {
+            Some(url) => file_url_path(url),
+            None => line.to_string(),
+        };
+        // Windows-authored playlists use backslashes, which only Windows treats
+        // as separators; forward slashes work everywhere.
+        let entry = entry.replace('\\', "/");
+        if entry.contains("://") {
+            parsed.missing.push(entry);
+            continue;
+        }
+        let path = base.join(&entry);
+        if path.is_file() {
+            parsed.tracks.push(path);
+        } else {
+            parsed.missing.push(entry);
+        }
+    }
+    parsed
+}
+
+/// The path a `file://` URL names, given what follows the scheme: percent
+/// escapes decoded, a host other than `localhost` kept as a UNC share, and
+/// Windows drives (`/C:/Music`) without their leading slash.
+fn file_url_path(url: &str) -> String {
+    let path = match url.strip_prefix("localhost") {
+        Some(rest) if rest.starts_with('/') => rest.to_string(),
+        _ if url.starts_with('/') => url.to_string(),
+        _ => format!("//{url}"),
+    };
+    let path = percent_decode(&path);
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return path[1..].to_string();
+    }
+    path
+}
+
+/// Decodes `%XX` escapes as UTF-8, leaving malformed ones as written.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Renders `paths` as extended M3U with `#EXTINF` duration and title lines.
+pub fn render(paths: &[String]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for path in paths {
+        let (seconds, label) = match metadata::read(Path::new(path)) {
+            Ok(tags) => (
+                tags.duration_ms.div_ceil(1000) as i64,
+                format!("{} - {}", tags.artist, tags.title),
+            ),
+            // -1 is the conventional "unknown length".
+            Err(_) => (
+                -1,
+                Path::new(path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+        };
+        out.push_str(&format!("#EXTINF:{seconds},{label}\n{path}\n"));
+    }
+    out
+}
+
+/// Reads an M3U/M3U8 playlist and returns the paths of the tracks that still
+/// exist, along with any stream URLs. Entries that don't are reported
+/// through `playlist-entries-skipped`.
+#[tauri::command]
+pub async fn import_playlist(path: String, app: AppHandle) -> Result<Vec<String>, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = Path::new(&path);
+        let bytes = fs::read(file).map_err(|e| PlayerError::io("read", file, e))?;
+        let base = file.parent().unwrap_or(Path::new(""));
+        let parsed = parse(&decode(&bytes), base);
+        if !parsed.missing.is_empty() {
+            let _ = app.emit(
+                PLAYLIST_SKIPPED_EVENT,
+                SkippedEntries {
+                    playlist: path.clone(),
+                    missing: parsed.missing,
+                },
+            );
+        }
+        Ok(parsed
+            .tracks
+            .into_iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Writes `paths` to `dest` as UTF-8 extended M3U.
+#[tauri::command]
+pub async fn export_playlist(paths: Vec<String>, dest: String) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_atomic(Path::new(&dest), render(&paths).as_bytes())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_resolves_entries_and_reports_the_rest() {
+        let dir = std::env::temp_dir().join(format!("playlist-parse-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.flac"), b"").unwrap();
+        fs::write(dir.join("b c.mp3"), b"").unwrap();
+        let url = format!("file://{}/b%20c.mp3", dir.display());
+        let text = format!(
+            "#EXTM3U\n#EXTINF:1,a\nsub\\a.flac\n\n{url}\nhttps://radio.example/live\ngone.ogg\nftp://host/x.mp3\n"
+        );
+        let parsed = parse(&text, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            parsed.tracks,
+            [
+                dir.join("sub/a.flac"),
+                dir.join("b c.mp3"),
+                PathBuf::from("https://radio.example/live"),
+            ]
+        );
+        assert_eq!(parsed.missing, ["gone.ogg", "ftp://host/x.mp3"]);
+    }
+
+    #[test]
+    fn file_urls_name_local_paths() {
+        assert_eq!(file_url_path("/music/caf%C3%A9.flac"), "/music/café.flac");
+        assert_eq!(file_url_path("localhost/music/a.flac"), "/music/a.flac");
+        assert_eq!(file_url_path("/C:/Music/a%20b.flac"), "C:/Music/a b.flac");
+        assert_eq!(file_url_path("nas/share/a.flac"), "//nas/share/a.flac");
+        assert_eq!(file_url_path("/100%/a.flac"), "/100%/a.flac");
+    }
+
+    #[test]
+    fn render_falls_back_to_the_file_name_without_tags() {
+        let paths = ["/missing/Song One.flac".to_string()];
+        assert_eq!(
+            render(&paths),
+            "#EXTM3U\n#EXTINF:-1,Song One\n/missing/Song One.flac\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use serde::Serialize;
+use symphonia::core::codecs::{
+    CodecType, CODEC_TYPE_AAC, CODEC_TYPE_ADPCM_IMA_WAV, CODEC_TYPE_ADPCM_MS, CODEC_TYPE_MP1,
+    CODEC_TYPE_MP2, CODEC_TYPE_MP3, CODEC_TYPE_OPUS, CODEC_TYPE_VORBIS,
+};
+use symphonia::core::errors::Error as SymphoniaError;
+
+use crate::decoder::{self, Stream};
+use crate::error::PlayerError;
+
+/// Packets whose bytes per frame vary by more than this fraction mark a
+/// stream as VBR. MP3 padding alone moves CBR frames by well under 1%.
+const VBR_TOLERANCE: f64 = 0.02;
+
+/// Technical details of a file's audio stream.
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioProperties {
+    /// Short codec name, e.g. `flac` or `mp3`.
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    /// Bits per sample; `None` for lossy codecs, which don't store samples.
+    pub bit_depth: Option<u32>,
+    pub channels: Option<u16>,
+    /// Average over the whole stream, in kbit/s.
+    pub bitrate_kbps: Option<u32>,
+    /// Whether packets are coded at varying bitrates.
+    pub vbr: bool,
+    /// Length in sample frames, encoder delay and padding excluded.
+    pub frames: Option<u64>,
+    pub duration_ms: Option<u64>,
+}
+
+fn is_lossy(codec: CodecType) -> bool {
+    [
+        CODEC_TYPE_MP1,
+        CODEC_TYPE_MP2,
+        CODEC_TYPE_MP3,
+        CODEC_TYPE_AAC,
+        CODEC_TYPE_VORBIS,
+        CODEC_TYPE_OPUS,
+        CODEC_TYPE_ADPCM_MS,
+        CODEC_TYPE_ADPCM_IMA_WAV,
+    ]
+    .contains(&codec)
+}
+
+/// Reads the stream parameters of `path` and walks its packets without
+/// decoding them: their sizes give the bitrate, and their lengths the exact
+/// duration, which the header only estimates for some formats. A CUE
+/// sheet's virtual track counts only the packets of its part of the image.
+pub fn read(path: &Path) -> Result<AudioProperties, PlayerError> {
+    let (file, span) = decoder::locate(path)?;
+    let Stream {
+        mut format,
+        track_id,
+        params,
+        ..
+    } = Stream::open(&file)?;
+    let whole_file = span.offset.is_zero() && span.end.is_none();
+    let start_ts = match span.offset.is_zero() {
+        true => 0,
+        false => decoder::seek_accurate(format.as_mut(), track_id, span.offset)?,
+    };
+    // Timestamps count frames at the stream's rate.
+    let end_ts = span
+        .end
+        .zip(params.sample_rate)
+        .map(|(end, rate)| (end.as_secs_f64() * rate as f64).round() as u64);
+    let mut bytes: u64 = 0;
+    let mut frames: u64 = 0;
+    let (mut densest, mut sparsest) = (0.0f64, f64::MAX);
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::ResetRequired) => continue,
+            Err(_) => break,
+        };
+        if packet.track_id() != track_id || packet.ts() + packet.dur() <= start_ts {
+            continue;
+        }
+        if end_ts.is_some_and(|end| packet.ts() >= end) {
+            break;
+        }
+        let packet_end = (packet.ts() + packet.dur()).min(end_ts.unwrap_or(u64::MAX));
+        bytes += packet.data.len() as u64;
+        frames += packet_end.saturating_sub(packet.ts().max(start_ts));
+        if packet.block_dur() > 0 {
+            let density = packet.data.len() as f64 / packet.block_dur() as f64;
+            densest = densest.max(density);
+            sparsest = sparsest.min(density);
+        }
+    }
+    let frames = Some(frames)
+        .filter(|&f| f > 0)
+        .or(params.n_frames.filter(|_| whole_file));
+    let seconds = frames
+        .zip(params.sample_rate)
+        .map(|(frames, rate)| frames as f64 / rate as f64)
+        .filter(|&s| s > 0.0);
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map_or_else(|| "unknown".to_string(), |c| c.short_name.to_string());
+    Ok(AudioProperties {
+        codec,
+        sample_rate: params.sample_rate,
+        bit_depth: params.bits_per_sample.filter(|_| !is_lossy(params.codec)),
+        channels: params.channels.map(|c| c.count() as u16),
+        bitrate_kbps: seconds.map(|s| (bytes as f64 * 8.0 / s / 1000.0).round() as u32),
+        vbr: sparsest < f64::MAX && densest > sparsest * (1.0 + VBR_TOLERANCE),
+        frames,
+        duration_ms: seconds.map(|s| (s * 1000.0).round() as u64),
+    })
+}
+
+#[tauri::command]
+pub async fn get_audio_properties(path: String) -> Result<AudioProperties, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || read(Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,583 @@
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::audio::PlayerState;
+use crate::error::PlayerError;
+use crate::sync::MutexExt;
+
+/// `previous_track` restarts the current track instead of going back once it
+/// has played for longer than this.
+pub const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    /// Replay the current track when it finishes.
+    One,
+    /// Wrap around to the start of the queue.
+    All,
+}
+
+/// Ordered list of track paths plus the index of the one playing.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Queue {
+    tracks: Vec<String>,
+    current: Option<usize>,
+    /// Play order as indices into `tracks`. The identity unless shuffling, in
+    /// which case entries before the current one have already been played.
+    order: Vec<usize>,
+    shuffle: bool,
+    repeat: RepeatMode,
+}
+
+impl Queue {
+    pub fn current_path(&self) -> Option<&str> {
+        self.current.map(|i| self.tracks[i].as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Whether `current` and `order` agree with `tracks`, as they always do
+    /// unless the queue was deserialized from a damaged file.
+    pub fn is_consistent(&self) -> bool {
+        let mut seen = vec![false; self.tracks.len()];
+        let permutation = self.order.len() == self.tracks.len()
+            && self
+                .order
+                .iter()
+                .all(|&i| i < seen.len() && !std::mem::replace(&mut seen[i], true));
+        permutation && self.current.is_none_or(|c| c < self.tracks.len())
+    }
+
+    /// Position of the current track within `order`.
+    fn cursor(&self) -> Option<usize> {
+        let current = self.current?;
+        self.order.iter().position(|&i| i == current)
+    }
+
+    fn reset_order(&mut self) {
+        self.order = (0..self.tracks.len()).collect();
+    }
+
+    fn insert_tracks(&mut self, at: usize, paths: Vec<String>) {
+        let count = paths.len();
+        self.tracks.splice(at..at, paths);
+        if let Some(current) = self.current.as_mut().filter(|c| **c >= at) {
+            *current += count;
+        }
+        if !self.shuffle {
+            return self.reset_order();
+        }
+        for index in self.order.iter_mut().filter(|i| **i >= at) {
+            *index += count;
+        }
+        // New entries land at random spots among the tracks not yet played.
+        let mut rng = rand::thread_rng();
+        let upcoming = self.cursor().map_or(0, |c| c + 1);
+        for index in at..at + count {
+            let slot = rng.gen_range(upcoming..=self.order.len());
+            self.order.insert(slot, index);
+        }
+    }
+
+    pub fn add(&mut self, paths: Vec<String>) {
+        self.insert_tracks(self.tracks.len(), paths);
+    }
+
+    /// Makes `path` current, inserting it after the current entry if it isn't queued yet.
+    pub fn select_path(&mut self, path: &str) {
+        let index = match self.tracks.iter().position(|p| p == path) {
+            Some(index) => index,
+            None => {
+                let index = self.current.map_or(self.tracks.len(), |i| i + 1);
+                self.insert_tracks(index, vec![path.to_string()]);
+                index
+            }
+        };
+        if self.shuffle && self.current != Some(index) {
+            // Play it next so the played/upcoming split of the shuffle order holds.
+            self.order.retain(|&i| i != index);
+            let slot = self.cursor().map_or(0, |c| c + 1);
+            self.order.insert(slot, index);
+        }
+        self.current = Some(index);
+    }
+
+    /// Removes the entry at `index`. Returns `true` if it was the current track,
+    /// in which case `current` now points at whatever took its place (if anything).
+    pub fn remove(&mut self, index: usize) -> Result<bool, String> {
+        if index >= self.tracks.len() {
+            return Err(format!("queue index {index} out of range"));
+        }
+        let cursor = self.cursor();
+        self.tracks.remove(index);
+        self.order.retain(|&i| i != index);
+        for i in self.order.iter_mut().filter(|i| **i > index) {
+            *i -= 1;
+        }
+        let Some(current) = self.current else {
+            return Ok(false);
+        };
+        if index < current {
+            self.current = Some(current - 1);
+            Ok(false)
+        } else if index == current {
+            self.current = cursor.and_then(|c| self.order.get(c).copied());
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Moves the entry at `from` to `to`, keeping `current` on the same track.
+    pub fn move_item(&mut self, from: usize, to: usize) -> Result<(), String> {
+        let len = self.tracks.len();
+        if from >= len || to >= len {
+            return Err(format!("queue index out of range (len {len})"));
+        }
+        let path = self.tracks.remove(from);
+        self.tracks.insert(to, path);
+
+        let remap = |i: usize| {
+            if i == from {
+                to
+            } else if from < i && i <= to {
+                i - 1
+            } else if to <= i && i < from {
+                i + 1
+            } else {
+                i
+            }
+        };
+        self.current = self.current.map(remap);
+        if self.shuffle {
+            self.order.iter_mut().for_each(|i| *i = remap(*i));
+        } else {
+            self.reset_order();
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.order.clear();
+        self.current = None;
+    }
+
+    /// Steps to the next entry in play order, returning its path, or `None` at
+    /// the end of the queue unless repeating all.
+    pub fn advance(&mut self) -> Option<&str> {
+        let next = self.cursor().map_or(0, |c| c + 1);
+        if next < self.order.len() {
+            self.current = Some(self.order[next]);
+        } else if self.repeat == RepeatMode::All && !self.order.is_empty() {
+            if self.shuffle {
+                let last = self.current;
+                self.order.shuffle(&mut rand::thread_rng());
+                // Don't replay the track that just finished straight away.
+                if self.order.len() > 1 && Some(self.order[0]) == last {
+                    self.order.swap(0, 1);
+                }
+            }
+            self.current = Some(self.order[0]);
+        } else {
+            self.current = None;
+        }
+        self.current_path()
+    }
+
+    /// Like [`Queue::advance`], but stays on the current track under [`RepeatMode::One`].
+    pub fn advance_after_finish(&mut self) -> Option<&str> {
+        if self.repeat == RepeatMode::One && self.current.is_some() {
+            return self.current_path();
+        }
+        self.advance()
+    }
+
+    /// The track [`Queue::advance_after_finish`] would move to, without moving.
+    pub fn peek_after_finish(&self) -> Option<String> {
+        self.clone().advance_after_finish().map(str::to_string)
+    }
+
+    /// Steps to the previous entry in play order, or `None` if already at the start.
+    pub fn previous(&mut self) -> Option<&str> {
+        let cursor = self.cursor()?;
+        self.current = match cursor.checked_sub(1) {
+            Some(previous) => Some(self.order[previous]),
+            None if self.repeat == RepeatMode::All => self.order.last().copied(),
+            None => return None,
+        };
+        self.current_path()
+    }
+
+    /// Enabling shuffle puts the current track first followed by the rest in
+    /// random order; disabling it returns to queue order from the current track.
+    pub fn set_shuffle(&mut self, enabled: bool) {
+        self.shuffle = enabled;
+        self.reset_order();
+        if enabled {
+            let mut rng = rand::thread_rng();
+            self.order.shuffle(&mut rng);
+            if let Some(cursor) = self.cursor() {
+                self.order.swap(0, cursor);
+            }
+        }
+    }
+
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = mode;
+    }
+}
+
+#[tauri::command]
+pub fn queue_add(paths: Vec<String>, player: State<'_, PlayerState>) -> Queue {
+    player.discard_preloaded();
+    let mut queue = player.queue.locked();
+    queue.add(paths);
+    queue.clone()
+}
+
+/// Removing the playing track stops it and starts whatever now occupies its index.
+#[tauri::command]
+pub async fn queue_remove(index: usize, app: AppHandle) -> Result<Queue, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let player = app.state::<PlayerState>();
+        player.discard_preloaded();
+        let (removed_current, next) = {
+            let mut queue = player.queue.locked();
+            let removed_current = queue.remove(index)?;
+            (removed_current, queue.current_path().map(str::to_string))
+        };
+        if removed_current {
+            match next {
+                Some(path) => player.load(&path, &app)?,
+                None => player.stop_playback(&app),
+            }
+        }
+        let queue = player.queue.locked().clone();
+        Ok(queue)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn queue_move(
+    from: usize,
+    to: usize,
+    player: State<'_, PlayerState>,
+) -> Result<Queue, PlayerError> {
+    player.discard_preloaded();
+    let mut queue = player.queue.locked();
+    queue.move_item(from, to)?;
+    Ok(queue.clone())
+}
+
+#[tauri::command]
+pub fn queue_clear(player: State<'_, PlayerState>) {
+    player.discard_preloaded();
+    player.queue.locked().clear();
+}
+
+#[tauri::command]
+pub fn queue_get(player: State<'_, PlayerState>) -> Queue {
+    player.queue.locked().clone()
+}
+
+#[tauri::command]
+pub async fn next_track(app: AppHandle) -> Result<(), PlayerError> {
+    // Opening the next entry can wait on the network for a stream.
+    tauri::async_runtime::spawn_blocking(move || skip_forward(&app.state(), &app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Moves on to the next queue entry, crossfading into it if enabled.
+pub(crate) fn skip_forward(player: &PlayerState, app: &AppHandle) -> Result<(), PlayerError> {
+    let next = player.queue.locked().advance().map(str::to_string);
+    let crossfade = player.crossfade();
+    match next {
+        Some(path) if !crossfade.is_zero() => {
+            // Fade over whatever is left if the track ends sooner than the window.
+            let window = player.remaining().map_or(crossfade, |r| r.min(crossfade));
+            player.crossfade_into(&path, window, app)
+        }
+        Some(path) => player.load(&path, app),
+        None => {
+            player.stop_playback(app);
+            Ok(())
+        }
+    }
+}
+
+/// Restarts the current track if it has played past [`RESTART_THRESHOLD`],
+/// otherwise goes back to the previous queue entry.
+#[tauri::command]
+pub async fn previous_track(app: AppHandle) -> Result<(), PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || skip_back(&app.state(), &app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// What [`previous_track`] does, for callers that are already off the main thread.
+pub(crate) fn skip_back(player: &PlayerState, app: &AppHandle) -> Result<(), PlayerError> {
+    let elapsed = player.position();
+    let previous = if elapsed.is_some_and(|e| e > RESTART_THRESHOLD) {
+        None
+    } else {
+        player.queue.locked().previous().map(str::to_string)
+    };
+    match previous {
+        Some(path) => player.load(&path, app),
+        // Past the threshold, or already at the head of the queue.
+        None if elapsed.is_some() => {
+            player.seek_to(Duration::ZERO)?;
+            player.emit_state(app);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub fn set_shuffle(enabled: bool, player: State<'_, PlayerState>) -> Queue {
+    player.discard_preloaded();
+    let mut queue = player.queue.locked();
+    queue.set_shuffle(enabled);
+    queue.clone()
+}
+
+#[tauri::command]
+pub fn set_repeat(mode: RepeatMode, player: State<'_, PlayerState>) -> Queue {
+    player.discard_preloaded();
+    let mut queue = player.queue.locked();
+    queue.set_repeat(mode);
+    queue.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [RepeatMode; 3] = [RepeatMode::Off, RepeatMode::One, RepeatMode::All];
+
+    fn path(i: usize) -> String {
+        format!("/music/{i:02}.flac")
+    }
+
+    fn queue(len: usize) -> Queue {
+        let mut queue = Queue::default();
+        queue.add((0..len).map(path).collect());
+        queue
+    }
+
+    /// Every combination of shuffle and repeat, playing track 3 of 8 with
+    /// seven tracks still to come.
+    fn setups() -> impl Iterator<Item = Queue> {
+        [false, true].into_iter().flat_map(|shuffle| {
+            MODES.into_iter().map(move |mode| {
+                let mut queue = queue(8);
+                queue.set_shuffle(shuffle);
+                queue.set_repeat(mode);
+                queue.select_path(&path(3));
+                queue
+            })
+        })
+    }
+
+    /// The tracks left to play after the current one, in play order.
+    fn upcoming(queue: &Queue) -> Vec<String> {
+        let cursor = queue.cursor().map_or(0, |c| c + 1);
+        queue.order[cursor..]
+            .iter()
+            .map(|&i| queue.tracks[i].clone())
+            .collect()
+    }
+
+    #[test]
+    fn add_keeps_the_current_track() {
+        for mut queue in setups() {
+            queue.add(vec![path(10), path(11)]);
+            assert!(queue.is_consistent());
+            assert_eq!(queue.current_path(), Some(path(3).as_str()));
+            assert_eq!(queue.len(), 10);
+        }
+    }
+
+    #[test]
+    fn added_tracks_are_still_to_come_when_shuffled() {
+        let mut queue = queue(8);
+        queue.set_shuffle(true);
+        queue.select_path(&path(3));
+        queue.advance();
+        queue.advance();
+        queue.add(vec![path(10), path(11)]);
+        let upcoming = upcoming(&queue);
+        assert!(upcoming.contains(&path(10)));
+        assert!(upcoming.contains(&path(11)));
+    }
+
+    #[test]
+    fn select_path_queues_unknown_paths_after_the_current_track() {
+        for mut queue in setups() {
+            queue.select_path(&path(20));
+            assert!(queue.is_consistent());
+            assert_eq!(queue.current_path(), Some(path(20).as_str()));
+            assert_eq!(queue.tracks[4], path(20));
+        }
+    }
+
+    #[test]
+    fn remove_keeps_the_current_track() {
+        for mut queue in setups() {
+            assert!(!queue.remove(0).unwrap());
+            assert!(!queue.remove(5).unwrap());
+            assert!(queue.is_consistent());
+            assert_eq!(queue.current_path(), Some(path(3).as_str()));
+        }
+    }
+
+    #[test]
+    fn removing_the_current_track_moves_to_the_next_in_play_order() {
+        for mut queue in setups() {
+            let next = upcoming(&queue).first().cloned();
+            let index = queue.current.unwrap();
+            assert!(queue.remove(index).unwrap());
+            assert!(queue.is_consistent());
+            assert_eq!(queue.current_path().map(str::to_string), next);
+        }
+    }
+
+    #[test]
+    fn remove_out_of_range_fails() {
+        let mut queue = queue(3);
+        assert!(queue.remove(3).is_err());
+        assert!(queue.is_consistent());
+    }
+
+    #[test]
+    fn move_item_keeps_the_current_track() {
+        for mut queue in setups() {
+            let shuffled = queue.shuffle;
+            let before = upcoming(&queue);
+            for (from, to) in [(0, 7), (3, 0), (7, 2), (5, 5)] {
+                queue.move_item(from, to).unwrap();
+                assert!(queue.is_consistent());
+                assert_eq!(queue.current_path(), Some(path(3).as_str()));
+            }
+            if shuffled {
+                assert_eq!(upcoming(&queue), before);
+            }
+        }
+        assert!(queue(3).move_item(0, 3).is_err());
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_once() {
+        let mut queue = queue(8);
+        queue.set_shuffle(true);
+        let mut played = Vec::new();
+        while let Some(path) = queue.advance() {
+            played.push(path.to_string());
+        }
+        played.sort();
+        assert_eq!(played, (0..8).map(path).collect::<Vec<_>>());
+        assert!(queue.is_consistent());
+    }
+
+    #[test]
+    fn toggling_shuffle_keeps_the_current_track() {
+        for mut queue in setups() {
+            let shuffled = queue.shuffle;
+            queue.set_shuffle(!shuffled);
+            assert!(queue.is_consistent());
+            assert_eq!(queue.current_path(), Some(path(3).as_str()));
+            if !shuffled {
+                // The current track leads the new shuffle order.
+                assert_eq!(queue.cursor(), Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn advance_after_finish_follows_the_repeat_mode() {
+        for mut queue in setups() {
+            let next = upcoming(&queue).first().cloned();
+            let after = queue.advance_after_finish().map(str::to_string);
+            match queue.repeat {
+                RepeatMode::One => assert_eq!(after, Some(path(3))),
+                _ => assert_eq!(after, next),
+            }
+            assert!(queue.is_consistent());
+        }
+    }
+
+    #[test]
+    fn the_end_of_the_queue_wraps_only_when_repeating_all() {
+        for mode in MODES {
+            let mut queue = queue(3);
+            queue.set_repeat(mode);
+            queue.select_path(&path(2));
+            let wrapped = queue.advance().map(str::to_string);
+            match mode {
+                RepeatMode::All => assert_eq!(wrapped, Some(path(0))),
+                _ => assert_eq!(wrapped, None),
+            }
+        }
+    }
+
+    #[test]
+    fn a_repeating_shuffle_does_not_replay_the_last_track_first() {
+        for _ in 0..50 {
+            let mut queue = queue(2);
+            queue.set_shuffle(true);
+            queue.set_repeat(RepeatMode::All);
+            queue.advance();
+            let last = queue.advance().unwrap().to_string();
+            assert_ne!(queue.advance(), Some(last.as_str()));
+            assert!(queue.is_consistent());
+        }
+    }
+
+    #[test]
+    fn peek_after_finish_does_not_move() {
+        for queue in setups() {
+            let mut advanced = queue.clone();
+            let next = advanced.advance_after_finish().map(str::to_string);
+            assert_eq!(queue.peek_after_finish(), next);
+            assert_eq!(queue.current_path(), Some(path(3).as_str()));
+        }
+    }
+
+    #[test]
+    fn previous_stops_at_the_start_unless_repeating_all() {
+        for mode in MODES {
+            let mut queue = queue(3);
+            queue.set_repeat(mode);
+            queue.select_path(&path(0));
+            let previous = queue.previous().map(str::to_string);
+            match mode {
+                RepeatMode::All => assert_eq!(previous, Some(path(2))),
+                _ => assert_eq!(previous, None),
+            }
+        }
+    }
+
+    #[test]
+    fn damaged_queues_are_inconsistent() {
+        let parse = |json: &str| serde_json::from_str::<Queue>(json).unwrap();
+        let tracks = r#""tracks": ["a", "b"], "shuffle": false, "repeat": "off""#;
+        assert!(parse(&format!(r#"{{{tracks}, "current": 1, "order": [0, 1]}}"#)).is_consistent());
+        assert!(!parse(&format!(r#"{{{tracks}, "current": 2, "order": [0, 1]}}"#)).is_consistent());
+        assert!(!parse(&format!(r#"{{{tracks}, "current": 0, "order": [0, 0]}}"#)).is_consistent());
+        assert!(!parse(&format!(r#"{{{tracks}, "current": 0, "order": [0]}}"#)).is_consistent());
+    }
+}
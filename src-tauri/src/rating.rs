@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::PlayerError;
+use crate::index::LibraryIndex;
+use crate::library::TrackInfo;
+use crate::metadata;
+
+/// Emitted with the updated [`TrackInfo`] when a track's rating or favorite
+/// flag changes.
+pub const TRACK_UPDATED_EVENT: &str = "track-updated";
+
+pub const MAX_STARS: u8 = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rating {
+    /// 1 to 5, or 0 when unrated.
+    pub stars: u8,
+    pub favorite: bool,
+}
+
+fn not_indexed(path: &str) -> String {
+    format!("{path} is not in the library")
+}
+
+/// Rates `path` from 0 (unrated) to 5 stars. The index holds the rating; it
+/// is also written to the file's tags when the file is writable.
+#[tauri::command]
+pub async fn set_rating(path: String, stars: u8, app: AppHandle) -> Result<(), PlayerError> {
+    if stars > MAX_STARS {
+        return Err(format!("rating must be 0 to {MAX_STARS} stars").into());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let track = app
+            .state::<LibraryIndex>()
+            .set_rating(&path, stars)?
+            .ok_or_else(|| not_indexed(&path))?;
+        // Read-only or tagless formats keep the rating in the index only.
+        let _ = metadata::write_rating(Path::new(&path), stars);
+        let _ = app.emit(TRACK_UPDATED_EVENT, track);
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Stars or unstars `path`, returning whether it's now a favorite.
+#[tauri::command]
+pub fn toggle_favorite(
+    path: String,
+    index: State<'_, LibraryIndex>,
+    app: AppHandle,
+) -> Result<bool, PlayerError> {
+    let track = index
+        .toggle_favorite(&path)?
+        .ok_or_else(|| not_indexed(&path))?;
+    let favorite = track.rating.is_some_and(|r| r.favorite);
+    let _ = app.emit(TRACK_UPDATED_EVENT, track);
+    Ok(favorite)
+}
+
+/// Favorite tracks, in library order.
+#[tauri::command]
+pub fn get_favorites(index: State<'_, LibraryIndex>) -> Result<Vec<TrackInfo>, PlayerError> {
+    index.select_tracks("tracks.favorite = 1", Vec::new(), None)
+}
@@ -0,0 +1,524 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use symphonia::core::io::MediaSource;
+use tauri::{AppHandle, Manager};
+
+use crate::audio::PlayerState;
+use crate::error::PlayerError;
+use crate::sync::MutexExt;
+
+/// Emitted when an ICY (Shoutcast/Icecast) stream announces a new title.
+pub const STREAM_TITLE_EVENT: &str = "stream-title-changed";
+
+/// Reconnects attempted after a dropped connection before the stream ends.
+pub const MAX_RECONNECTS: u32 = 3;
+
+/// Wait before the first reconnect; each further attempt waits one step longer.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Most bytes downloaded ahead of playback.
+pub const BUFFER_BYTES: usize = 1024 * 1024;
+
+/// Buffered bytes needed before the next packet is decoded, comfortably more
+/// than the largest packet of a typical stream.
+const READY_BYTES: usize = 32 * 1024;
+
+/// Bytes the download thread reads at a time.
+const CHUNK_BYTES: usize = 16 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A read stalling this long counts as a dropped connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Payload of the `stream-title-changed` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamTitleChange {
+    pub path: String,
+    pub title: String,
+}
+
+/// Whether a track path is a remote URL rather than a local file.
+pub fn is_url(path: &str) -> bool {
+    let lower = path.get(..8).unwrap_or(path).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Latest title from a stream's ICY metadata, shared with the engine.
+#[derive(Debug, Default)]
+pub struct StreamTitle {
+    title: Mutex<Option<String>>,
+    changed: AtomicBool,
+}
+
+impl StreamTitle {
+    fn set(&self, title: String) {
+        let mut current = self.title.locked();
+        if current.as_deref() != Some(title.as_str()) {
+            *current = Some(title);
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The title, if it changed since the last call.
+    pub fn take_changed(&self) -> Option<String> {
+        self.changed
+            .swap(false, Ordering::Relaxed)
+            .then(|| self.title.locked().clone())
+            .flatten()
+    }
+}
+
+/// Position within ICY's interleaving of audio and metadata blocks.
+struct Icy {
+    interval: usize,
+    until_metadata: usize,
+}
+
+/// Why a download stopped.
+enum End {
+    Complete,
+    Failed(String),
+}
+
+/// Bytes downloaded ahead of playback, shared between the download thread
+/// filling it and the [`HttpMedia`] reading from it.
+struct Buffer {
+    state: Mutex<BufferState>,
+    /// Signalled whenever bytes are added or taken, or the download ends.
+    changed: Condvar,
+    /// Reads wait for bytes while the track is being opened; once it's handed
+    /// to the audio thread they return `WouldBlock` instead.
+    blocking: AtomicBool,
+}
+
+struct BufferState {
+    bytes: VecDeque<u8>,
+    /// Stream offset of the first buffered byte.
+    position: u64,
+    /// Offset the download should start over from, after a seek past the
+    /// buffered bytes.
+    restart: Option<u64>,
+    end: Option<End>,
+    /// The reader is gone and the download thread should exit.
+    closed: bool,
+}
+
+impl Buffer {
+    fn lock(&self) -> MutexGuard<'_, BufferState> {
+        self.state.locked()
+    }
+
+    /// Waits for `changed`, keeping the state if a reader panicked meanwhile.
+    fn wait<'a>(&self, state: MutexGuard<'a, BufferState>) -> MutexGuard<'a, BufferState> {
+        self.changed
+            .wait(state)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Handle on a stream's download buffer, for deciding whether a packet can be
+/// decoded without waiting for the network.
+#[derive(Clone)]
+pub struct StreamBuffer(Arc<Buffer>);
+
+impl StreamBuffer {
+    /// Whether enough is buffered to decode a packet, or the download ended.
+    pub fn is_ready(&self) -> bool {
+        let state = self.0.lock();
+        state.end.is_some() || (state.restart.is_none() && state.bytes.len() >= READY_BYTES)
+    }
+
+    /// Makes reads fail with `WouldBlock` rather than wait for bytes. Called
+    /// once the source is about to be played on the audio thread.
+    pub fn stop_blocking(&self) {
+        self.0.blocking.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A `MediaSource` streaming a URL over HTTP. Finite files served with range
+/// support are seekable; live streams aren't.
+///
+/// The network is read on a download thread, which keeps up to
+/// [`BUFFER_BYTES`] ahead of playback and resumes dropped connections up to
+/// [`MAX_RECONNECTS`] times, so reads never wait on a socket.
+pub struct HttpMedia {
+    url: String,
+    buffer: Arc<Buffer>,
+    position: u64,
+    length: Option<u64>,
+    seekable: bool,
+    /// MIME type from the first response, used as a format hint.
+    content_type: Option<String>,
+}
+
+impl HttpMedia {
+    /// Connects to `url` and starts downloading it. Blocks until the server
+    /// responds.
+    pub fn open(url: &str, title: Arc<StreamTitle>) -> Result<Self, String> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .redirects(5)
+            .build();
+        let mut download = Download {
+            url: url.to_string(),
+            agent,
+            reader: Box::new(io::empty()),
+            position: 0,
+            length: None,
+            seekable: false,
+            icy: None,
+            title,
+        };
+        let response = download.request(0)?;
+        let content_type = response
+            .header("Content-Type")
+            .map(|t| t.split(';').next().unwrap_or(t).trim().to_ascii_lowercase());
+        download.icy = response
+            .header("icy-metaint")
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|&interval| interval > 0)
+            .map(|interval| Icy {
+                interval,
+                until_metadata: interval,
+            });
+        let total = response
+            .header("Content-Range")
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|total| total.parse().ok());
+        download.length = total.or_else(|| {
+            response
+                .header("Content-Length")
+                .and_then(|len| len.parse().ok())
+        });
+        // Offsets in an ICY stream would count metadata bytes, so even a
+        // server that claims range support can't be seeked meaningfully.
+        download.seekable = download.icy.is_none()
+            && download.length.is_some()
+            && (response.status() == 206 || response.header("Accept-Ranges") == Some("bytes"));
+        download.reader = response.into_reader();
+
+        let buffer = Arc::new(Buffer {
+            state: Mutex::new(BufferState {
+                bytes: VecDeque::new(),
+                position: 0,
+                restart: None,
+                end: None,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            blocking: AtomicBool::new(true),
+        });
+        let media = HttpMedia {
+            url: url.to_string(),
+            buffer: buffer.clone(),
+            position: 0,
+            length: download.length,
+            seekable: download.seekable,
+            content_type,
+        };
+        thread::Builder::new()
+            .name("stream-download".into())
+            .spawn(move || download.run(&buffer))
+            .map_err(|e| format!("failed to start downloading {url}: {e}"))?;
+        Ok(media)
+    }
+
+    /// Handle on the download buffer, kept by the source decoding this media.
+    pub fn stream_buffer(&self) -> StreamBuffer {
+        StreamBuffer(self.buffer.clone())
+    }
+
+    /// File extension suggested by the response's MIME type or the URL path.
+    pub fn extension_hint(&self) -> Option<String> {
+        let from_type = match self.content_type.as_deref() {
+            Some("audio/mpeg" | "audio/mp3") => Some("mp3"),
+            Some("audio/aac" | "audio/aacp") => Some("aac"),
+            Some("audio/mp4" | "audio/x-m4a") => Some("m4a"),
+            Some("audio/ogg" | "application/ogg") => Some("ogg"),
+            Some("audio/opus") => Some("opus"),
+            Some("audio/flac" | "audio/x-flac") => Some("flac"),
+            Some("audio/wav" | "audio/x-wav" | "audio/wave") => Some("wav"),
+            _ => None,
+        };
+        from_type.map(str::to_string).or_else(|| {
+            let path = self.url.split(['?', '#']).next()?;
+            let name = path.rsplit('/').next()?;
+            let (_, ext) = name.rsplit_once('.')?;
+            Some(ext.to_ascii_lowercase())
+        })
+    }
+}
+
+impl Drop for HttpMedia {
+    fn drop(&mut self) {
+        self.buffer.lock().closed = true;
+        self.buffer.changed.notify_all();
+    }
+}
+
+/// The connection behind an [`HttpMedia`], owned by its download thread.
+struct Download {
+    url: String,
+    agent: ureq::Agent,
+    reader: Box<dyn Read + Send + Sync>,
+    position: u64,
+    length: Option<u64>,
+    seekable: bool,
+    icy: Option<Icy>,
+    title: Arc<StreamTitle>,
+}
+
+impl Download {
+    fn request(&self, offset: u64) -> Result<ureq::Response, String> {
+        let mut request = self.agent.get(&self.url).set("Icy-MetaData", "1");
+        if offset > 0 {
+            request = request.set("Range", &format!("bytes={offset}-"));
+        }
+        request
+            .call()
+            .map_err(|e| format!("failed to fetch {}: {e}", self.url))
+    }
+
+    /// Replaces the connection with one starting at `offset` (live streams
+    /// just pick up wherever the broadcast is).
+    fn reconnect(&mut self, offset: u64) -> io::Result<()> {
+        if !self.seekable && self.length.is_some() {
+            // Starting over would replay the file from the top.
+            return Err(io::Error::other(format!("{} can't be resumed", self.url)));
+        }
+        let offset = if self.seekable { offset } else { 0 };
+        let response = self.request(offset).map_err(io::Error::other)?;
+        if offset > 0 && response.status() != 206 {
+            return Err(io::Error::other(format!(
+                "{} ignored the range request",
+                self.url
+            )));
+        }
+        self.reader = response.into_reader();
+        if let Some(icy) = &mut self.icy {
+            icy.until_metadata = icy.interval;
+        }
+        if self.seekable {
+            self.position = offset;
+        }
+        Ok(())
+    }
+
+    /// Reads audio bytes from the current connection, consuming any ICY
+    /// metadata interleaved with them.
+    fn read_audio(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut want = buf.len();
+        if let Some(icy) = &mut self.icy {
+            if icy.until_metadata == 0 {
+                read_metadata(&mut self.reader, &self.title)?;
+                icy.until_metadata = icy.interval;
+            }
+            want = want.min(icy.until_metadata);
+        }
+        let n = self.reader.read(&mut buf[..want])?;
+        if let Some(icy) = &mut self.icy {
+            icy.until_metadata -= n;
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    /// Whether a clean end of the response means the connection dropped.
+    fn ended_early(&self) -> bool {
+        match self.length {
+            Some(length) => self.position < length,
+            // Chunked files of unknown length end when they end; only a
+            // broadcast is expected to go on forever.
+            None => self.icy.is_some(),
+        }
+    }
+
+    /// Reads the next audio bytes, reconnecting after a dropped connection.
+    /// Returns 0 at the end of the stream.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempts = 0;
+        loop {
+            let error = match self.read_audio(buf) {
+                Ok(0) if !self.ended_early() => return Ok(0),
+                Ok(0) => io::Error::from(io::ErrorKind::UnexpectedEof),
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => e,
+            };
+            // Keep retrying until a connection comes back or attempts run out.
+            loop {
+                if attempts == MAX_RECONNECTS {
+                    return Err(error);
+                }
+                attempts += 1;
+                thread::sleep(RECONNECT_DELAY * attempts);
+                if self.reconnect(self.position).is_ok() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Body of the download thread: keeps `buffer` topped up until the reader
+    /// is dropped, starting over wherever it seeks to.
+    fn run(mut self, buffer: &Buffer) {
+        let mut chunk = vec![0u8; CHUNK_BYTES];
+        loop {
+            let restart = {
+                let mut state = buffer.lock();
+                while !state.closed
+                    && state.restart.is_none()
+                    && (state.end.is_some() || state.bytes.len() >= BUFFER_BYTES)
+                {
+                    state = buffer.wait(state);
+                }
+                if state.closed {
+                    return;
+                }
+                state.restart.take()
+            };
+            if let Some(offset) = restart {
+                if let Err(e) = self.reconnect(offset) {
+                    let mut state = buffer.lock();
+                    if state.restart.is_none() {
+                        state.end = Some(End::Failed(e.to_string()));
+                    }
+                    buffer.changed.notify_all();
+                    continue;
+                }
+            }
+            let read = self.read(&mut chunk);
+            let mut state = buffer.lock();
+            // Bytes read before a seek came in belong to the old position.
+            if state.restart.is_some() {
+                continue;
+            }
+            match read {
+                Ok(0) => state.end = Some(End::Complete),
+                Ok(n) => state.bytes.extend(&chunk[..n]),
+                Err(e) => state.end = Some(End::Failed(e.to_string())),
+            }
+            buffer.changed.notify_all();
+        }
+    }
+}
+
+/// Reads an ICY metadata block and publishes its `StreamTitle`, if any.
+fn read_metadata(reader: &mut impl Read, title: &StreamTitle) -> io::Result<()> {
+    let mut len = [0u8; 1];
+    reader.read_exact(&mut len)?;
+    let mut block = vec![0u8; len[0] as usize * 16];
+    reader.read_exact(&mut block)?;
+    let text = String::from_utf8_lossy(&block);
+    let announced = text
+        .split_once("StreamTitle='")
+        .and_then(|(_, rest)| rest.split_once("';"))
+        .map(|(announced, _)| announced.trim());
+    if let Some(announced) = announced.filter(|t| !t.is_empty()) {
+        title.set(announced.to_string());
+    }
+    Ok(())
+}
+
+impl Read for HttpMedia {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.buffer.lock();
+        loop {
+            if !state.bytes.is_empty() {
+                let n = buf.len().min(state.bytes.len());
+                for (dest, byte) in buf.iter_mut().zip(state.bytes.drain(..n)) {
+                    *dest = byte;
+                }
+                state.position += n as u64;
+                self.position = state.position;
+                self.buffer.changed.notify_all();
+                return Ok(n);
+            }
+            match &state.end {
+                Some(End::Complete) => return Ok(0),
+                Some(End::Failed(message)) => return Err(io::Error::other(message.clone())),
+                None if !self.buffer.blocking.load(Ordering::Relaxed) => {
+                    return Err(io::Error::from(io::ErrorKind::WouldBlock));
+                }
+                None => state = self.buffer.wait(state),
+            }
+        }
+    }
+}
+
+impl Seek for HttpMedia {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => self
+                .length
+                .ok_or_else(|| io::Error::from(io::ErrorKind::Unsupported))?
+                .checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        if target == self.position {
+            return Ok(target);
+        }
+        if !self.seekable {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stream is not seekable",
+            ));
+        }
+        let mut state = self.buffer.lock();
+        let buffered = state.bytes.len() as u64;
+        if target > state.position && target - state.position <= buffered {
+            let skip = (target - state.position) as usize;
+            state.bytes.drain(..skip);
+        } else {
+            state.bytes.clear();
+            state.restart = Some(target);
+            state.end = None;
+        }
+        state.position = target;
+        self.position = target;
+        self.buffer.changed.notify_all();
+        Ok(target)
+    }
+}
+
+impl MediaSource for HttpMedia {
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.length
+    }
+}
+
+/// Plays a remote stream or file from `url`, selecting it in the queue like
+/// `play` does. Playback starts as soon as the first packets arrive; live
+/// streams report titles through `stream-title-changed`.
+#[tauri::command]
+pub async fn play_url(url: String, app: AppHandle) -> Result<(), PlayerError> {
+    if !is_url(&url) {
+        return Err(format!("{url} is not an http(s) URL").into());
+    }
+    // Connecting blocks, so keep it off the async runtime threads.
+    tauri::async_runtime::spawn_blocking(move || {
+        let player = app.state::<PlayerState>();
+        player.load(&url, &app)?;
+        player.queue.locked().select_path(&url);
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
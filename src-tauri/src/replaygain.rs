@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use lofty::config::ParseOptions;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::audio::PlayerState;
+use crate::metadata::preferred_tag;
+
+/// Tagged gains outside this range are treated as bad data and clamped.
+pub const MIN_GAIN_DB: f32 = -24.0;
+pub const MAX_GAIN_DB: f32 = 12.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalizationMode {
+    #[default]
+    Off,
+    Track,
+    /// Album gain, falling back to track gain for files without album tags.
+    Album,
+}
+
+/// ReplayGain values read from a file's tags.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Reads the ReplayGain tags of `path`. Unreadable files yield no gain.
+    pub fn read(path: &Path) -> Self {
+        let file = Probe::open(path)
+            .map(|probe| probe.options(ParseOptions::new().read_properties(false)))
+            .and_then(|probe| probe.read());
+        let Ok(file) = file else {
+            return ReplayGain::default();
+        };
+        let Some(tag) = preferred_tag(&file) else {
+            return ReplayGain::default();
+        };
+        let value = |key: ItemKey| tag.get_string(&key).and_then(parse_value);
+        ReplayGain {
+            track_gain: value(ItemKey::ReplayGainTrackGain),
+            track_peak: value(ItemKey::ReplayGainTrackPeak),
+            album_gain: value(ItemKey::ReplayGainAlbumGain),
+            album_peak: value(ItemKey::ReplayGainAlbumPeak),
+        }
+    }
+
+    /// These tags, or `measured` if the file carries no gain of its own.
+    pub fn or(self, measured: ReplayGain) -> Self {
+        if self.track_gain.is_some() || self.album_gain.is_some() {
+            self
+        } else {
+            measured
+        }
+    }
+
+    /// Gain to apply in dB under `mode`; 0 when the relevant tags are absent.
+    /// The result never pushes the tagged peak above full scale.
+    pub fn gain_db(&self, mode: NormalizationMode) -> f32 {
+        let (gain, peak) = match mode {
+            NormalizationMode::Off => return 0.0,
+            NormalizationMode::Album if self.album_gain.is_some() => {
+                (self.album_gain, self.album_peak)
+            }
+            NormalizationMode::Track | NormalizationMode::Album => {
+                (self.track_gain, self.track_peak)
+            }
+        };
+        let Some(gain) = gain else {
+            return 0.0;
+        };
+        let gain = gain.clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+        match peak.filter(|p| *p > 0.0) {
+            Some(peak) => gain.min(-20.0 * peak.log10()),
+            None => gain,
+        }
+    }
+}
+
+/// Parses tag values like `-6.48 dB` or `0.988547`.
+fn parse_value(raw: &str) -> Option<f32> {
+    let raw = raw.trim();
+    let number = raw
+        .strip_suffix("dB")
+        .or_else(|| raw.strip_suffix("db"))
+        .unwrap_or(raw);
+    number.trim().parse().ok().filter(|v: &f32| v.is_finite())
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Applies to the playing track immediately. The resulting gain is reported
+/// as `gain_db` on `playback-state-changed`.
+#[tauri::command]
+pub fn set_normalization(mode: NormalizationMode, app: AppHandle, player: State<'_, PlayerState>) {
+    player.set_normalization(mode);
+    player.emit_state(&app);
+}
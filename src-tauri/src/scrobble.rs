@@ -0,0 +1,472 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Listener, Manager, State};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::audio::{
+    PlaybackProgress, PlaybackStatus, PLAYBACK_PROGRESS_EVENT, PLAYBACK_STATE_EVENT,
+    PROGRESS_INTERVAL,
+};
+use crate::error::PlayerError;
+use crate::index::{now_millis, LibraryIndex};
+use crate::metadata::{self, UNKNOWN_ALBUM, UNKNOWN_ARTIST};
+use crate::settings::{write_atomic, SettingsStore};
+use crate::sync::MutexExt;
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const AUTH_URL: &str = "https://www.last.fm/api/auth/";
+
+/// Credentials of the registered Last.fm API application, supplied at build time.
+const API_KEY: Option<&str> = option_env!("LASTFM_API_KEY");
+const API_SECRET: Option<&str> = option_env!("LASTFM_API_SECRET");
+
+pub const SESSION_FILE: &str = "lastfm-session.json";
+pub const PENDING_FILE: &str = "lastfm-pending.json";
+
+/// Tracks shorter than this are never scrobbled.
+pub const MIN_TRACK_LENGTH: Duration = Duration::from_secs(30);
+/// A track scrobbles after half its length or this long, whichever comes first.
+pub const MAX_SCROBBLE_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+
+/// How often queued scrobbles are retried while there are any.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Last.fm accepts at most this many scrobbles per request.
+const BATCH_SIZE: usize = 50;
+
+/// How long `lastfm_authenticate` waits for the user to approve access.
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(120);
+const AUTH_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Last.fm error codes worth retrying: service offline, temporarily
+/// unavailable, and rate limited.
+const RETRYABLE_ERRORS: &[i64] = &[11, 16, 29];
+
+/// Last.fm error codes meaning the session or the app's credentials aren't
+/// accepted: authentication failed, invalid session key, invalid API key,
+/// invalid signature, and suspended API key.
+const AUTH_ERRORS: &[i64] = &[4, 9, 10, 13, 26];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LastfmSession {
+    name: String,
+    key: String,
+}
+
+/// A finished listen waiting to be submitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Scrobble {
+    artist: String,
+    title: String,
+    album: String,
+    duration_secs: u64,
+    /// Unix time the track started playing.
+    timestamp: u64,
+}
+
+enum ApiError {
+    /// Offline or Last.fm is having trouble; try again later.
+    Retry(String),
+    /// The session is no longer valid; the user has to sign in again.
+    Unauthorized(String),
+    Rejected(String),
+}
+
+impl ApiError {
+    fn message(self) -> String {
+        match self {
+            ApiError::Retry(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Rejected(message) => message,
+        }
+    }
+}
+
+fn credentials() -> Result<(&'static str, &'static str), String> {
+    API_KEY
+        .zip(API_SECRET)
+        .ok_or_else(|| "this build has no Last.fm API credentials".to_string())
+}
+
+/// Calls `method` with `params`, signing the request as Last.fm requires.
+fn call(method: &str, params: &[(&str, String)]) -> Result<Value, ApiError> {
+    let (key, secret) = credentials().map_err(ApiError::Rejected)?;
+    let mut signed: BTreeMap<&str, String> = params.iter().cloned().collect();
+    signed.insert("method", method.to_string());
+    signed.insert("api_key", key.to_string());
+    let mut base: String = signed.iter().map(|(k, v)| format!("{k}{v}")).collect();
+    base.push_str(secret);
+    signed.insert("api_sig", format!("{:x}", md5::compute(base)));
+    signed.insert("format", "json".to_string());
+
+    let form: Vec<(&str, &str)> = signed.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let body = match ureq::post(API_URL).send_form(&form) {
+        Ok(response) => response.into_string(),
+        // Error details come back as JSON with a 4xx status.
+        Err(ureq::Error::Status(_, response)) => response.into_string(),
+        Err(e) => return Err(ApiError::Retry(e.to_string())),
+    }
+    .map_err(|e| ApiError::Retry(e.to_string()))?;
+    let json: Value = serde_json::from_str(&body).map_err(|e| ApiError::Retry(e.to_string()))?;
+    match json.get("error").and_then(Value::as_i64) {
+        Some(code) => {
+            let message = json["message"].as_str().unwrap_or("unknown error");
+            let message = format!("Last.fm error {code}: {message}");
+            if RETRYABLE_ERRORS.contains(&code) {
+                Err(ApiError::Retry(message))
+            } else if AUTH_ERRORS.contains(&code) {
+                Err(ApiError::Unauthorized(message))
+            } else {
+                Err(ApiError::Rejected(message))
+            }
+        }
+        None => Ok(json),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Played-time bookkeeping for the current track.
+struct Listen {
+    path: String,
+    /// The [`PlaybackStatus::start_id`] of the play this is.
+    start_id: Option<u64>,
+    started_at: u64,
+    started_ms: i64,
+    listened: Duration,
+    last_progress: Option<Instant>,
+    /// Row in the play statistics, once the play has passed the threshold.
+    play: Option<i64>,
+}
+
+enum Job {
+    NowPlaying(String),
+    Scrobble { path: String, timestamp: u64 },
+}
+
+/// Submits now-playing updates and scrobbles in the background, keeping
+/// unsent scrobbles on disk until Last.fm accepts them. Also feeds the play
+/// statistics, whether or not scrobbling is enabled.
+pub struct Scrobbler {
+    dir: PathBuf,
+    enabled: Mutex<bool>,
+    session: Arc<Mutex<Option<LastfmSession>>>,
+    listen: Mutex<Option<Listen>>,
+    jobs: Mutex<Option<Sender<Job>>>,
+}
+
+impl Scrobbler {
+    pub fn new(app: &AppHandle, enabled: bool) -> Result<Self, String> {
+        let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let session = fs::read_to_string(dir.join(SESSION_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        Ok(Scrobbler {
+            dir,
+            enabled: Mutex::new(enabled),
+            session: Arc::new(Mutex::new(session)),
+            listen: Mutex::new(None),
+            jobs: Mutex::new(None),
+        })
+    }
+
+    /// Starts the submission thread and follows playback events.
+    pub fn start(&self, app: &AppHandle) -> std::io::Result<()> {
+        let (jobs, rx) = mpsc::channel();
+        let pending_path = self.dir.join(PENDING_FILE);
+        let session_path = self.dir.join(SESSION_FILE);
+        let session = self.session.clone();
+        let handle = app.clone();
+        thread::Builder::new()
+            .name("scrobbler".into())
+            .spawn(move || {
+                let mut pending: Vec<Scrobble> = fs::read_to_string(&pending_path)
+                    .ok()
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default();
+                loop {
+                    let job = match rx.recv_timeout(RETRY_INTERVAL) {
+                        Ok(job) => Some(job),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    let Some(key) = session.locked().as_ref().map(|s| s.key.clone()) else {
+                        continue;
+                    };
+                    let mut changed = false;
+                    let mut unauthorized = None;
+                    match job {
+                        Some(Job::NowPlaying(path)) => {
+                            if let Some(track) = read_track(&path, 0) {
+                                if let Err(ApiError::Unauthorized(message)) =
+                                    update_now_playing(&key, &track)
+                                {
+                                    unauthorized = Some(message);
+                                }
+                            }
+                        }
+                        Some(Job::Scrobble { path, timestamp }) => {
+                            let track = read_track(&path, timestamp);
+                            changed = track.is_some();
+                            pending.extend(track);
+                        }
+                        None => {}
+                    }
+                    let queued = pending.len();
+                    if unauthorized.is_none() {
+                        if let Err(ApiError::Unauthorized(message)) = flush(&key, &mut pending) {
+                            unauthorized = Some(message);
+                        }
+                    }
+                    if pending.len() != queued || changed {
+                        save_pending(&pending_path, &pending);
+                    }
+                    // Queued scrobbles stay on disk until the user signs in again.
+                    if let Some(message) = unauthorized {
+                        *session.locked() = None;
+                        let _ = fs::remove_file(&session_path);
+                        PlayerError::from(format!("signed out of Last.fm: {message}"))
+                            .emit(&handle);
+                    }
+                }
+            })?;
+        *self.jobs.locked() = Some(jobs);
+
+        let handle = app.clone();
+        app.listen(PLAYBACK_STATE_EVENT, move |event| {
+            let status = serde_json::from_str::<PlaybackStatus>(event.payload());
+            if let (Ok(status), Some(scrobbler)) = (status, handle.try_state::<Scrobbler>()) {
+                scrobbler.on_status(status, &handle);
+            }
+        });
+        let handle = app.clone();
+        app.listen(PLAYBACK_PROGRESS_EVENT, move |event| {
+            let progress = serde_json::from_str::<PlaybackProgress>(event.payload());
+            if let (Ok(progress), Some(scrobbler)) = (progress, handle.try_state::<Scrobbler>()) {
+                scrobbler.on_progress(progress, &handle);
+            }
+        });
+        Ok(())
+    }
+
+    fn send(&self, job: Job) {
+        if !*self.enabled.locked() {
+            return;
+        }
+        if let Some(jobs) = self.jobs.locked().as_ref() {
+            let _ = jobs.send(job);
+        }
+    }
+
+    fn on_status(&self, status: PlaybackStatus, app: &AppHandle) {
+        let mut listen = self.listen.locked();
+        // Pausing, stopping, or moving on all settle the listening time so far.
+        if let Some(previous) = listen.as_ref() {
+            if let (Some(id), Some(index)) = (previous.play, app.try_state::<LibraryIndex>()) {
+                let _ = index.update_play(id, previous.listened.as_millis() as u64);
+            }
+        }
+        // Same start of the same track: a pause, resume, or seek. A replay or
+        // repeat-one gets a new start id and counts as a new listen.
+        let same =
+            |l: &Listen| Some(&l.path) == status.path.as_ref() && l.start_id == status.start_id;
+        if listen.as_ref().is_some_and(same) {
+            return;
+        }
+        *listen = status.path.map(|path| Listen {
+            path,
+            start_id: status.start_id,
+            started_at: unix_now(),
+            started_ms: now_millis(),
+            listened: Duration::ZERO,
+            last_progress: None,
+            play: None,
+        });
+        if let Some(listen) = listen.as_ref() {
+            self.send(Job::NowPlaying(listen.path.clone()));
+        }
+    }
+
+    /// Accumulates time actually heard, so seeking doesn't count as listening.
+    /// Once past the threshold the track is scrobbled and counted as a play,
+    /// once per time it's started.
+    fn on_progress(&self, progress: PlaybackProgress, app: &AppHandle) {
+        let mut listen = self.listen.locked();
+        let Some(listen) = listen.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        // Progress stops while paused; don't count the gap.
+        let step = listen
+            .last_progress
+            .map_or(Duration::ZERO, |last| now - last)
+            .min(PROGRESS_INTERVAL * 2);
+        listen.last_progress = Some(now);
+        listen.listened += step;
+
+        let Some(duration) = progress.duration_ms.map(Duration::from_millis) else {
+            return;
+        };
+        let threshold = (duration / 2).min(MAX_SCROBBLE_THRESHOLD);
+        if listen.play.is_none() && duration >= MIN_TRACK_LENGTH && listen.listened >= threshold {
+            let Some(index) = app.try_state::<LibraryIndex>() else {
+                return;
+            };
+            let listened_ms = listen.listened.as_millis() as u64;
+            // A failed insert is retried on the next progress event.
+            let Ok(id) = index.record_play(&listen.path, listen.started_ms, listened_ms) else {
+                return;
+            };
+            listen.play = Some(id);
+            self.send(Job::Scrobble {
+                path: listen.path.clone(),
+                timestamp: listen.started_at,
+            });
+        }
+    }
+
+    fn save_session(&self, session: LastfmSession) -> Result<(), String> {
+        let path = self.dir.join(SESSION_FILE);
+        let json = serde_json::to_string(&session).map_err(|e| e.to_string())?;
+        write_atomic(&path, json.as_bytes())?;
+        // The session key grants write access to the account; keep it private.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                .map_err(|e| e.to_string())?;
+        }
+        *self.session.locked() = Some(session);
+        Ok(())
+    }
+}
+
+fn read_track(path: &str, timestamp: u64) -> Option<Scrobble> {
+    let tags = metadata::read(Path::new(path)).ok()?;
+    // Last.fm can't match a listen without an artist.
+    if tags.artist == UNKNOWN_ARTIST {
+        return None;
+    }
+    Some(Scrobble {
+        album: if tags.album == UNKNOWN_ALBUM {
+            String::new()
+        } else {
+            tags.album
+        },
+        artist: tags.artist,
+        title: tags.title,
+        duration_secs: tags.duration_ms / 1000,
+        timestamp,
+    })
+}
+
+fn update_now_playing(key: &str, track: &Scrobble) -> Result<Value, ApiError> {
+    call(
+        "track.updateNowPlaying",
+        &[
+            ("sk", key.to_string()),
+            ("artist", track.artist.clone()),
+            ("track", track.title.clone()),
+            ("album", track.album.clone()),
+            ("duration", track.duration_secs.to_string()),
+        ],
+    )
+}
+
+/// Submits `pending` in batches until one fails, which is kept for later
+/// with everything behind it. Returns the failure.
+///
+/// Scrobbles Last.fm accepts the request for are done with, including any
+/// it reports as ignored one by one, since those would be ignored again.
+fn flush(key: &str, pending: &mut Vec<Scrobble>) -> Result<(), ApiError> {
+    while !pending.is_empty() {
+        let batch = &pending[..pending.len().min(BATCH_SIZE)];
+        let fields: Vec<(String, String)> = batch
+            .iter()
+            .enumerate()
+            .flat_map(|(i, track)| {
+                [
+                    (format!("artist[{i}]"), track.artist.clone()),
+                    (format!("track[{i}]"), track.title.clone()),
+                    (format!("album[{i}]"), track.album.clone()),
+                    (format!("duration[{i}]"), track.duration_secs.to_string()),
+                    (format!("timestamp[{i}]"), track.timestamp.to_string()),
+                ]
+            })
+            .collect();
+        let mut params = vec![("sk", key.to_string())];
+        params.extend(fields.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        call("track.scrobble", &params)?;
+        let sent = batch.len();
+        pending.drain(..sent);
+    }
+    Ok(())
+}
+
+fn save_pending(path: &Path, pending: &[Scrobble]) {
+    if let Ok(json) = serde_json::to_string(pending) {
+        let _ = write_atomic(path, json.as_bytes());
+    }
+}
+
+/// Opens the Last.fm authorization page and waits for the user to approve
+/// it. Returns the account name.
+#[tauri::command]
+pub async fn lastfm_authenticate(app: AppHandle) -> Result<String, PlayerError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let (key, _) = credentials()?;
+        let token = call("auth.getToken", &[])
+            .map_err(ApiError::message)?
+            .get("token")
+            .and_then(Value::as_str)
+            .ok_or("Last.fm returned no token")?
+            .to_string();
+        app.opener()
+            .open_url(
+                format!("{AUTH_URL}?api_key={key}&token={token}"),
+                None::<&str>,
+            )
+            .map_err(|e| e.to_string())?;
+
+        let deadline = Instant::now() + AUTH_TIMEOUT;
+        loop {
+            thread::sleep(AUTH_POLL_INTERVAL);
+            // Fails with "unauthorized token" until the user approves.
+            if let Ok(json) = call("auth.getSession", &[("token", token.clone())]) {
+                let session: LastfmSession = serde_json::from_value(json["session"].clone())
+                    .map_err(|e| format!("unexpected Last.fm session: {e}"))?;
+                let name = session.name.clone();
+                app.state::<Scrobbler>().save_session(session)?;
+                return Ok(name);
+            }
+            if Instant::now() >= deadline {
+                return Err("timed out waiting for Last.fm authorization".into());
+            }
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Turns scrobbling on or off and persists the choice. Off by default.
+#[tauri::command]
+pub fn set_scrobbling(
+    enabled: bool,
+    scrobbler: State<'_, Scrobbler>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    *scrobbler.enabled.locked() = enabled;
+    settings.update(|s| s.scrobbling = enabled)
+}
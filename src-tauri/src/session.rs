@@ -0,0 +1,158 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::audio::PlayerState;
+use crate::error::PlayerError;
+use crate::queue::Queue;
+use crate::remote;
+use crate::settings::write_atomic;
+use crate::sync::MutexExt;
+
+pub const SESSION_FILE: &str = "session.json";
+
+/// Bumped whenever [`SessionState`] changes incompatibly; older files are discarded.
+pub const SESSION_VERSION: u32 = 1;
+
+/// How often the session is written while it keeps changing.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What's needed to pick up where the last run left off. Volume lives in
+/// [`crate::settings::Settings`] instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionState {
+    pub version: u32,
+    /// Tracks, current entry, and shuffle/repeat modes.
+    pub queue: Queue,
+    pub position_ms: u64,
+    /// Playback speed; sessions saved before it existed play at normal speed.
+    #[serde(default = "normal_speed")]
+    pub speed: f32,
+}
+
+fn normal_speed() -> f32 {
+    1.0
+}
+
+impl SessionState {
+    fn capture(player: &PlayerState) -> Self {
+        SessionState {
+            version: SESSION_VERSION,
+            queue: player.queue.locked().clone(),
+            position_ms: player.position().map_or(0, |p| p.as_millis() as u64),
+            speed: player.speed.get(),
+        }
+    }
+}
+
+/// Session file in the app data dir, saved periodically and on exit.
+pub struct SessionStore {
+    path: PathBuf,
+    /// Last JSON written, so an unchanged session isn't rewritten.
+    saved: Mutex<Option<String>>,
+    autosave: Mutex<Option<Autosave>>,
+}
+
+struct Autosave {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl SessionStore {
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        Ok(SessionStore {
+            path: dir.join(SESSION_FILE),
+            saved: Mutex::new(None),
+            autosave: Mutex::new(None),
+        })
+    }
+
+    /// The saved session, or `None` if it's missing, corrupt, or from another version.
+    fn read(&self) -> Option<SessionState> {
+        let json = fs::read_to_string(&self.path).ok()?;
+        let session: SessionState = serde_json::from_str(&json).ok()?;
+        (session.version == SESSION_VERSION && session.queue.is_consistent()).then_some(session)
+    }
+
+    /// Restores the saved queue and cues its current track, paused at the saved position.
+    pub fn restore(&self, player: &PlayerState, app: &AppHandle) {
+        let Some(session) = self.read() else {
+            return;
+        };
+        let current = session.queue.current_path().map(str::to_string);
+        player.speed.set(session.speed);
+        *player.queue.locked() = session.queue;
+        // A track that has since moved just leaves the queue stopped, and so
+        // does a remote stream, rather than holding up startup to connect.
+        if let Some(path) = current.filter(|path| !remote::is_url(path)) {
+            let _ = player.cue(&path, Duration::from_millis(session.position_ms), app);
+        }
+    }
+
+    pub fn save(&self, player: &PlayerState) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&SessionState::capture(player))
+            .map_err(|e| e.to_string())?;
+        let mut saved = self.saved.locked();
+        if saved.as_deref() == Some(json.as_str()) {
+            return Ok(());
+        }
+        write_atomic(&self.path, json.as_bytes())?;
+        *saved = Some(json);
+        Ok(())
+    }
+
+    pub fn start_autosave(&self, app: AppHandle) -> std::io::Result<()> {
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let handle = thread::Builder::new()
+            .name("session-autosave".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(AUTOSAVE_INTERVAL) {
+                    let _ = app
+                        .state::<SessionStore>()
+                        .save(&app.state::<PlayerState>());
+                }
+            })?;
+        *self.autosave.locked() = Some(Autosave { stop, handle });
+        Ok(())
+    }
+
+    /// Stops autosaving and writes the final state. Called on `RunEvent::Exit`,
+    /// before the player shuts down.
+    pub fn shutdown(&self, player: &PlayerState) {
+        if let Some(autosave) = self.autosave.locked().take() {
+            let _ = autosave.stop.send(());
+            let _ = autosave.handle.join();
+        }
+        let _ = self.save(player);
+    }
+
+    fn clear(&self) -> Result<(), PlayerError> {
+        *self.saved.locked() = None;
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(PlayerError::io("remove", &self.path, e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stops playback, empties the queue, and deletes the saved session.
+#[tauri::command]
+pub fn clear_session(
+    app: AppHandle,
+    player: State<'_, PlayerState>,
+    session: State<'_, SessionStore>,
+) -> Result<(), PlayerError> {
+    player.stop_playback(&app);
+    player.queue.locked().clear();
+    session.clear()
+}
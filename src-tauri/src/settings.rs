@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::audio::DEFAULT_FADE_MS;
+use crate::equalizer::BAND_COUNT;
+use crate::error::PlayerError;
+use crate::history::DEFAULT_HISTORY_LIMIT;
+use crate::shortcuts::{default_shortcuts, ShortcutAction};
+use crate::silence::DEFAULT_THRESHOLD_DB;
+use crate::sync::MutexExt;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// User preferences persisted across launches. Unknown or missing fields fall
+/// back to their defaults so older files keep loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Slider position in `0.0..=1.0`, before the perceptual curve.
+    pub volume: f32,
+    pub eq_enabled: bool,
+    /// Gain of each equalizer band in dB.
+    pub eq_bands: [f32; BAND_COUNT],
+    /// Global shortcut accelerator per action.
+    pub shortcuts: BTreeMap<ShortcutAction, String>,
+    /// Last.fm scrobbling is opt-in.
+    pub scrobbling: bool,
+    /// Library folders kept in sync with the index, as absolute paths.
+    pub watched_folders: Vec<String>,
+    /// Entries kept in the recently played history.
+    pub history_limit: usize,
+    /// Fade on play, resume, and pause, in milliseconds; 0 disables it.
+    pub fade_ms: u64,
+    /// Closing the main window hides it to the tray instead of quitting.
+    pub close_to_tray: bool,
+    /// Start tracks past leading silence and end them before trailing silence.
+    pub skip_silence: bool,
+    /// Level below which audio counts as silence, in dBFS.
+    pub silence_threshold_db: f32,
+    /// Sum stereo tracks to mono.
+    pub downmix_mono: bool,
+    /// L/R balance from -1.0 (left) to 1.0 (right).
+    pub balance: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            volume: 1.0,
+            eq_enabled: false,
+            eq_bands: [0.0; BAND_COUNT],
+            shortcuts: default_shortcuts(),
+            scrobbling: false,
+            watched_folders: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            fade_ms: DEFAULT_FADE_MS,
+            close_to_tray: true,
+            skip_silence: false,
+            silence_threshold_db: DEFAULT_THRESHOLD_DB,
+            downmix_mono: false,
+            balance: 0.0,
+        }
+    }
+}
+
+/// [`Settings`] backed by a JSON file in the app data dir.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Loads the settings file, starting from defaults if it's missing or unreadable.
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(SETTINGS_FILE);
+        let settings = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Ok(SettingsStore {
+            path,
+            settings: Mutex::new(settings),
+        })
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.locked().clone()
+    }
+
+    /// Applies `change` and writes the result back to disk.
+    pub fn update(&self, change: impl FnOnce(&mut Settings)) -> Result<(), PlayerError> {
+        let mut settings = self.settings.locked();
+        change(&mut settings);
+        let json = serde_json::to_string_pretty(&*settings).map_err(|e| e.to_string())?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+}
+
+/// Writes through a sibling temp file and renames it into place, so a crash
+/// mid-write never leaves a truncated file behind.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), PlayerError> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(|e| PlayerError::io("write", &tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| PlayerError::io("replace", path, e))
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::audio::{self, PlaybackState, PlayerState};
+use crate::error::PlayerError;
+use crate::queue;
+use crate::settings::SettingsStore;
+use crate::sync::MutexExt;
+
+/// Emitted with the new slider level when a shortcut changes the volume.
+pub const VOLUME_CHANGED_EVENT: &str = "volume-changed";
+
+/// Slider step applied by the volume shortcuts.
+pub const VOLUME_STEP: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShortcutAction {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+}
+
+impl ShortcutAction {
+    pub const ALL: [ShortcutAction; 5] = [
+        ShortcutAction::PlayPause,
+        ShortcutAction::Next,
+        ShortcutAction::Previous,
+        ShortcutAction::VolumeUp,
+        ShortcutAction::VolumeDown,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShortcutAction::PlayPause => "play-pause",
+            ShortcutAction::Next => "next",
+            ShortcutAction::Previous => "previous",
+            ShortcutAction::VolumeUp => "volume-up",
+            ShortcutAction::VolumeDown => "volume-down",
+        }
+    }
+
+    pub fn default_accelerator(self) -> &'static str {
+        match self {
+            ShortcutAction::PlayPause => "CommandOrControl+Alt+Space",
+            ShortcutAction::Next => "CommandOrControl+Alt+Right",
+            ShortcutAction::Previous => "CommandOrControl+Alt+Left",
+            ShortcutAction::VolumeUp => "CommandOrControl+Alt+Up",
+            ShortcutAction::VolumeDown => "CommandOrControl+Alt+Down",
+        }
+    }
+}
+
+pub fn default_shortcuts() -> BTreeMap<ShortcutAction, String> {
+    ShortcutAction::ALL
+        .into_iter()
+        .map(|action| (action, action.default_accelerator().to_string()))
+        .collect()
+}
+
+/// An action's accelerator and whether the OS accepted it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcutBinding {
+    pub action: ShortcutAction,
+    pub accelerator: String,
+    /// Why the shortcut isn't active, e.g. another app already owns it.
+    pub error: Option<String>,
+    #[serde(skip)]
+    shortcut: Option<Shortcut>,
+}
+
+/// Current bindings, registered with the global shortcut plugin.
+pub struct ShortcutBindings {
+    bindings: Mutex<BTreeMap<ShortcutAction, ShortcutBinding>>,
+}
+
+impl ShortcutBindings {
+    /// Registers every action, using `saved` accelerators where present.
+    /// Failures are recorded on the binding rather than aborting startup.
+    pub fn register(app: &AppHandle, saved: &BTreeMap<ShortcutAction, String>) -> Self {
+        let mut bindings = BTreeMap::new();
+        for action in ShortcutAction::ALL {
+            let accelerator = saved
+                .get(&action)
+                .cloned()
+                .unwrap_or_else(|| action.default_accelerator().to_string());
+            let registered = parse(&accelerator).and_then(|shortcut| {
+                let taken = bindings
+                    .values()
+                    .find(|b: &&ShortcutBinding| b.shortcut == Some(shortcut));
+                if let Some(other) = taken {
+                    return Err(conflict(&accelerator, other.action));
+                }
+                register(app, shortcut)?;
+                Ok(shortcut)
+            });
+            bindings.insert(
+                action,
+                ShortcutBinding {
+                    action,
+                    accelerator,
+                    error: registered.as_ref().err().cloned(),
+                    shortcut: registered.ok(),
+                },
+            );
+        }
+        ShortcutBindings {
+            bindings: Mutex::new(bindings),
+        }
+    }
+
+    fn action_for(&self, shortcut: &Shortcut) -> Option<ShortcutAction> {
+        self.bindings
+            .locked()
+            .values()
+            .find(|b| b.shortcut.as_ref() == Some(shortcut))
+            .map(|b| b.action)
+    }
+}
+
+fn parse(accelerator: &str) -> Result<Shortcut, String> {
+    accelerator
+        .parse()
+        .map_err(|e| format!("invalid accelerator {accelerator}: {e}"))
+}
+
+fn register(app: &AppHandle, shortcut: Shortcut) -> Result<(), String> {
+    app.global_shortcut()
+        .register(shortcut)
+        .map_err(|e| format!("failed to register {shortcut}: {e}"))
+}
+
+fn conflict(accelerator: &str, other: ShortcutAction) -> String {
+    format!("{accelerator} is already bound to {}", other.as_str())
+}
+
+/// Handler passed to the global shortcut plugin.
+pub fn on_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    let Some(action) = app
+        .try_state::<ShortcutBindings>()
+        .and_then(|bindings| bindings.action_for(shortcut))
+    else {
+        return;
+    };
+    let player = app.state::<PlayerState>();
+    let _ = match action {
+        ShortcutAction::PlayPause => {
+            if player.status().state == PlaybackState::Playing {
+                audio::pause(app.clone(), player);
+            } else {
+                audio::resume(app.clone(), player);
+            }
+            Ok(())
+        }
+        ShortcutAction::Next => {
+            audio::in_background(app, queue::skip_forward);
+            Ok(())
+        }
+        ShortcutAction::Previous => {
+            audio::in_background(app, queue::skip_back);
+            Ok(())
+        }
+        ShortcutAction::VolumeUp | ShortcutAction::VolumeDown => {
+            let step = if action == ShortcutAction::VolumeUp {
+                VOLUME_STEP
+            } else {
+                -VOLUME_STEP
+            };
+            let level = player.volume_level() + step;
+            let result = audio::set_volume(level, player.clone(), app.state());
+            let _ = app.emit(VOLUME_CHANGED_EVENT, player.volume_level());
+            result
+        }
+    };
+}
+
+#[tauri::command]
+pub fn get_global_shortcuts(bindings: State<'_, ShortcutBindings>) -> Vec<ShortcutBinding> {
+    bindings.bindings.locked().values().cloned().collect()
+}
+
+/// Rebinds `action` to `accelerator` (e.g. `CommandOrControl+Shift+P`) and
+/// persists it. The previous shortcut stays active if the new one is invalid,
+/// bound to another action, or refused by the OS.
+#[tauri::command]
+pub fn set_global_shortcut(
+    action: ShortcutAction,
+    accelerator: String,
+    app: AppHandle,
+    bindings: State<'_, ShortcutBindings>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    let shortcut = parse(&accelerator)?;
+    let mut bindings = bindings.bindings.locked();
+    if let Some(other) = bindings
+        .values()
+        .find(|b| b.action != action && b.shortcut == Some(shortcut))
+    {
+        return Err(conflict(&accelerator, other.action).into());
+    }
+    let binding = bindings
+        .get_mut(&action)
+        .expect("every action has a binding");
+    if binding.shortcut != Some(shortcut) {
+        if let Some(previous) = binding.shortcut {
+            let _ = app.global_shortcut().unregister(previous);
+        }
+        if let Err(e) = register(&app, shortcut) {
+            if let Some(previous) = binding.shortcut {
+                let _ = register(&app, previous);
+            }
+            return Err(e.into());
+        }
+        binding.shortcut = Some(shortcut);
+    }
+    binding.accelerator = accelerator.clone();
+    binding.error = None;
+    settings.update(|s| {
+        s.shortcuts.insert(action, accelerator);
+    })
+}
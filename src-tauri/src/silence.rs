@@ -0,0 +1,193 @@
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::index::{index_key, LibraryIndex};
+use crate::logging;
+use crate::replaygain::db_to_gain;
+use crate::settings::SettingsStore;
+
+/// Level below which audio counts as silence, in dBFS. Low enough that the
+/// quiet start of an ambient piece still counts as sound.
+pub const DEFAULT_THRESHOLD_DB: f32 = -60.0;
+pub const MIN_THRESHOLD_DB: f32 = -96.0;
+pub const MAX_THRESHOLD_DB: f32 = -20.0;
+
+/// Silence must last this long to be skipped, so a short pause before the
+/// first note or a slow fade-in is played as recorded.
+pub const MIN_SILENCE: Duration = Duration::from_millis(1500);
+
+/// Kept before the first sound and after the last, so attacks and decays
+/// aren't clipped.
+const PAD: Duration = Duration::from_millis(200);
+
+/// The part of a track worth playing. The default is all of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Trim {
+    /// Where playback starts.
+    pub start: Duration,
+    /// Where the track is cut off, or `None` to play to the end.
+    pub end: Option<Duration>,
+}
+
+/// Finds the leading and trailing silence of `path`: any sample at or above
+/// `threshold_db` counts as sound, and a run of silence only counts if it
+/// lasts [`MIN_SILENCE`]. A track that never gets louder is left whole.
+pub fn detect(path: &Path, threshold_db: f32) -> Result<Trim, String> {
+    let threshold = db_to_gain(threshold_db);
+    let mut frames: u64 = 0;
+    let mut first_sound = None;
+    let mut last_sound = 0;
+    let rate = decoder::decode_mono(path, |sample| {
+        if sample.abs() >= threshold {
+            first_sound.get_or_insert(frames);
+            last_sound = frames;
+        }
+        frames += 1;
+        ControlFlow::Continue(())
+    })?;
+    let Some(first_sound) = first_sound else {
+        return Ok(Trim::default());
+    };
+    let at = |frame: u64| Duration::from_secs_f64(frame as f64 / rate.max(1) as f64);
+    let leading = at(first_sound);
+    let trailing = at(frames - last_sound - 1);
+    Ok(Trim {
+        start: if leading >= MIN_SILENCE {
+            leading - PAD
+        } else {
+            Duration::ZERO
+        },
+        end: (trailing >= MIN_SILENCE).then(|| at(last_sound + 1) + PAD),
+    })
+}
+
+/// How to play `path`: its stored trim while skipping silence is on, and the
+/// whole track otherwise or until it has been analyzed. Offsets are looked
+/// up under the same key analysis stores them under.
+pub fn trim_for(app: &AppHandle, path: &str) -> Trim {
+    let Some(settings) = app.try_state::<SettingsStore>().map(|s| s.get()) else {
+        return Trim::default();
+    };
+    if !settings.skip_silence {
+        return Trim::default();
+    }
+    let Ok(key) = index_key(Path::new(path)) else {
+        return Trim::default();
+    };
+    app.try_state::<LibraryIndex>()
+        .and_then(|index| index.silence(&key, settings.silence_threshold_db).ok()?)
+        .unwrap_or_default()
+}
+
+enum Request {
+    Track(String),
+    /// Every indexed track without offsets for the current threshold.
+    Library,
+}
+
+/// Analyzes tracks for silence on a background thread, since it takes a
+/// full decode. Requests are dropped while skipping silence is off.
+pub struct SilenceScanner {
+    requests: Sender<Request>,
+}
+
+impl SilenceScanner {
+    /// Starts the scanner, catching up on tracks left unanalyzed last run.
+    /// Register with `manage` after the library index.
+    pub fn start(app: &AppHandle) -> Result<Self, String> {
+        let (requests, rx) = mpsc::channel();
+        let handle = app.clone();
+        thread::Builder::new()
+            .name("silence-scan".into())
+            .spawn(move || run(rx, &handle))
+            .map_err(|e| e.to_string())?;
+        let scanner = SilenceScanner { requests };
+        scanner.backfill();
+        Ok(scanner)
+    }
+
+    /// Queues `path` for analysis, e.g. after it was (re)indexed.
+    pub fn analyze(&self, path: &str) {
+        let _ = self.requests.send(Request::Track(path.to_string()));
+    }
+
+    fn backfill(&self) {
+        let _ = self.requests.send(Request::Library);
+    }
+}
+
+fn run(rx: Receiver<Request>, app: &AppHandle) {
+    let enabled_at = |threshold_db: f32| {
+        let settings = app.state::<SettingsStore>().get();
+        settings.skip_silence && settings.silence_threshold_db == threshold_db
+    };
+    for request in rx {
+        let settings = app.state::<SettingsStore>().get();
+        if !settings.skip_silence {
+            continue;
+        }
+        let threshold_db = settings.silence_threshold_db;
+        let index = app.state::<LibraryIndex>();
+        let paths = match request {
+            Request::Track(path) => index_key(Path::new(&path))
+                .map(|key| vec![key])
+                .unwrap_or_default(),
+            Request::Library => index
+                .unanalyzed_for_silence(threshold_db)
+                .unwrap_or_default(),
+        };
+        for path in paths {
+            // Switched off or retuned midway; the request that did so picks up from here.
+            if !enabled_at(threshold_db) {
+                break;
+            }
+            // Unreadable files are retried on the next backfill.
+            logging::guarded(|| {
+                if let Ok(trim) = detect(Path::new(&path), threshold_db) {
+                    let _ = index.store_silence(&path, threshold_db, trim);
+                }
+            });
+        }
+    }
+}
+
+/// Starts tracks past their leading silence and moves on before their
+/// trailing silence, as found when they're indexed. Turning it on analyzes
+/// the tracks already in the library in the background.
+#[tauri::command]
+pub fn set_skip_silence(
+    enabled: bool,
+    settings: State<'_, SettingsStore>,
+    scanner: State<'_, SilenceScanner>,
+) -> Result<(), PlayerError> {
+    settings.update(|s| s.skip_silence = enabled)?;
+    if enabled {
+        scanner.backfill();
+    }
+    Ok(())
+}
+
+/// Sets the level below which audio counts as silence, clamped to
+/// `MIN_THRESHOLD_DB..=MAX_THRESHOLD_DB`. Tracks are analyzed again for the
+/// new level; until then they play whole.
+#[tauri::command]
+pub fn set_silence_threshold(
+    threshold_db: f32,
+    settings: State<'_, SettingsStore>,
+    scanner: State<'_, SilenceScanner>,
+) -> Result<(), PlayerError> {
+    if !threshold_db.is_finite() {
+        return Err("threshold must be a number of dB".into());
+    }
+    let threshold_db = threshold_db.clamp(MIN_THRESHOLD_DB, MAX_THRESHOLD_DB);
+    settings.update(|s| s.silence_threshold_db = threshold_db)?;
+    scanner.backfill();
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::audio::PlayerState;
+use crate::sync::MutexExt;
+
+pub const SLEEP_TIMER_EVENT: &str = "sleep-timer-tick";
+
+/// How long playback fades out before the timer pauses it.
+pub const SLEEP_FADE: Duration = Duration::from_secs(10);
+
+/// How often `sleep-timer-tick` is emitted while a timed countdown runs.
+pub const TICK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Ramp back to full level when a timer is cancelled mid-fade.
+const RESTORE_FADE: Duration = Duration::from_millis(500);
+
+/// Payload of the `sleep-timer-tick` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct SleepTimerStatus {
+    pub active: bool,
+    /// Stopping when the current track ends rather than after a fixed time.
+    pub end_of_track: bool,
+    pub remaining_ms: Option<u64>,
+}
+
+enum Armed {
+    Timed {
+        deadline: Instant,
+        next_tick: Instant,
+    },
+    Fading {
+        until: Instant,
+    },
+    EndOfTrack,
+}
+
+/// Pauses playback after a while or at the end of the current track.
+/// Driven by the playback monitor.
+#[derive(Default)]
+pub struct SleepTimer {
+    armed: Mutex<Option<Armed>>,
+}
+
+impl SleepTimer {
+    /// Whether playback should stop instead of moving on when the track ends.
+    pub fn stops_after_track(&self) -> bool {
+        matches!(*self.armed.locked(), Some(Armed::EndOfTrack))
+    }
+
+    /// Disarms an end-of-track timer, returning whether one was armed.
+    pub fn take_end_of_track(&self) -> bool {
+        let mut armed = self.armed.locked();
+        let was = matches!(*armed, Some(Armed::EndOfTrack));
+        if was {
+            *armed = None;
+        }
+        was
+    }
+
+    fn status(armed: &Option<Armed>) -> SleepTimerStatus {
+        let now = Instant::now();
+        let remaining =
+            |until: Instant| Some(until.saturating_duration_since(now).as_millis() as u64);
+        match armed {
+            None => SleepTimerStatus {
+                active: false,
+                end_of_track: false,
+                remaining_ms: None,
+            },
+            Some(Armed::Timed { deadline, .. }) => SleepTimerStatus {
+                active: true,
+                end_of_track: false,
+                remaining_ms: remaining(*deadline),
+            },
+            Some(Armed::Fading { .. }) => SleepTimerStatus {
+                active: true,
+                end_of_track: false,
+                remaining_ms: Some(0),
+            },
+            Some(Armed::EndOfTrack) => SleepTimerStatus {
+                active: true,
+                end_of_track: true,
+                remaining_ms: None,
+            },
+        }
+    }
+
+    fn arm(&self, armed: Option<Armed>, player: &PlayerState, app: &AppHandle) {
+        let mut slot = self.armed.locked();
+        if matches!(*slot, Some(Armed::Fading { .. })) {
+            player.fade_current(1.0, RESTORE_FADE);
+        }
+        *slot = armed;
+        let _ = app.emit(SLEEP_TIMER_EVENT, Self::status(&slot));
+    }
+
+    /// Advances the countdown; called on every monitor tick.
+    pub fn tick(&self, player: &PlayerState, app: &AppHandle) {
+        let mut armed = self.armed.locked();
+        let now = Instant::now();
+        match armed.as_mut() {
+            Some(Armed::Timed { deadline, .. }) if now >= *deadline => {
+                *armed = Some(Armed::Fading {
+                    until: now + SLEEP_FADE,
+                });
+                player.fade_current(0.0, SLEEP_FADE);
+            }
+            Some(Armed::Timed { next_tick, .. }) => {
+                if now >= *next_tick {
+                    *next_tick += TICK_INTERVAL;
+                    let _ = app.emit(SLEEP_TIMER_EVENT, Self::status(&armed));
+                }
+            }
+            Some(Armed::Fading { until }) if now >= *until => {
+                *armed = None;
+                // Already faded out; pause at once so the gain can be restored.
+                player.pause_after(Duration::ZERO, app);
+                // The fade only touched the track gain, so the volume setting
+                // is untouched; put the gain back for the next manual play.
+                player.fade_current(1.0, Duration::ZERO);
+                let _ = app.emit(SLEEP_TIMER_EVENT, Self::status(&armed));
+            }
+            Some(Armed::Fading { until }) => {
+                // Re-aim at the same end point, which also catches a track
+                // that started mid-fade at full level.
+                player.fade_current(0.0, until.saturating_duration_since(now));
+            }
+            Some(Armed::EndOfTrack) | None => {}
+        }
+    }
+}
+
+/// Fades out and pauses after `minutes`, replacing any running timer.
+#[tauri::command]
+pub fn set_sleep_timer(minutes: u64, app: AppHandle, player: State<'_, PlayerState>) {
+    let now = Instant::now();
+    let armed = Armed::Timed {
+        deadline: now + Duration::from_secs(minutes * 60),
+        next_tick: now + TICK_INTERVAL,
+    };
+    player.sleep.arm(Some(armed), &player, &app);
+}
+
+/// Stops playback once the current track finishes instead of moving on.
+#[tauri::command]
+pub fn set_sleep_timer_end_of_track(app: AppHandle, player: State<'_, PlayerState>) {
+    player.discard_preloaded();
+    player.sleep.arm(Some(Armed::EndOfTrack), &player, &app);
+}
+
+#[tauri::command]
+pub fn cancel_sleep_timer(app: AppHandle, player: State<'_, PlayerState>) {
+    player.sleep.arm(None, &player, &app);
+}
@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+
+use crate::error::PlayerError;
+use crate::index::{now_millis, LibraryIndex};
+use crate::library::TrackInfo;
+use crate::settings::write_atomic;
+
+pub const SMART_PLAYLISTS_FILE: &str = "smart-playlists.json";
+
+/// Deepest nesting of `and`/`or` groups accepted.
+pub const MAX_RULE_DEPTH: usize = 16;
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Year,
+    DurationMs,
+    PlayCount,
+    /// Stars, 0 when unrated.
+    Rating,
+    Favorite,
+    /// Unix milliseconds the track was first indexed.
+    AddedAt,
+    /// Unix milliseconds of the last counted play; unset if never played.
+    LastPlayed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Number,
+    Date,
+    Flag,
+}
+
+impl Field {
+    /// SQL expression for the field, evaluated per row of `tracks`.
+    fn expression(self) -> &'static str {
+        match self {
+            Field::Title => "tracks.title",
+            Field::Artist => "tracks.artist",
+            Field::Album => "tracks.album",
+            Field::AlbumArtist => "tracks.album_artist",
+            Field::Genre => "tracks.genre",
+            Field::Year => "tracks.year",
+            Field::DurationMs => "tracks.duration_ms",
+            Field::PlayCount => "(SELECT COUNT(*) FROM plays WHERE plays.path = tracks.path)",
+            Field::Rating => "tracks.rating",
+            Field::Favorite => "tracks.favorite",
+            Field::AddedAt => "tracks.added_at",
+            Field::LastPlayed => {
+                "(SELECT MAX(played_at) FROM plays WHERE plays.path = tracks.path)"
+            }
+        }
+    }
+
+    fn kind(self) -> Kind {
+        match self {
+            Field::Title | Field::Artist | Field::Album | Field::AlbumArtist | Field::Genre => {
+                Kind::Text
+            }
+            Field::Year | Field::DurationMs | Field::PlayCount | Field::Rating => Kind::Number,
+            Field::AddedAt | Field::LastPlayed => Kind::Date,
+            Field::Favorite => Kind::Flag,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    /// Text (case-insensitive), number, or flag equality.
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    StartsWith,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Dates, compared against Unix milliseconds.
+    Before,
+    After,
+    /// Dates within the last `value` days.
+    InLastDays,
+}
+
+/// A smart playlist's filter: conditions combined with `and`/`or` groups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Rule {
+    And {
+        rules: Vec<Rule>,
+    },
+    Or {
+        rules: Vec<Rule>,
+    },
+    Condition {
+        field: Field,
+        operator: Operator,
+        value: Value,
+    },
+}
+
+/// A saved rule set, re-evaluated against the library every time it's opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+    pub rules: Rule,
+    pub limit: Option<usize>,
+}
+
+/// Builds a `WHERE` clause from fixed SQL fragments and `?` placeholders;
+/// every user-supplied value goes into `params`, never into the SQL text.
+struct Compiler {
+    sql: String,
+    params: Vec<SqlValue>,
+}
+
+impl Compiler {
+    fn rule(&mut self, rule: &Rule, depth: usize) -> Result<(), String> {
+        if depth > MAX_RULE_DEPTH {
+            return Err(format!("rules are nested more than {MAX_RULE_DEPTH} deep"));
+        }
+        match rule {
+            Rule::And { rules } => self.group(rules, " AND ", "1", depth),
+            Rule::Or { rules } => self.group(rules, " OR ", "0", depth),
+            Rule::Condition {
+                field,
+                operator,
+                value,
+            } => self.condition(*field, *operator, value),
+        }
+    }
+
+    fn group(
+        &mut self,
+        rules: &[Rule],
+        joiner: &str,
+        empty: &str,
+        depth: usize,
+    ) -> Result<(), String> {
+        if rules.is_empty() {
+            self.sql.push_str(empty);
+            return Ok(());
+        }
+        self.sql.push('(');
+        for (i, rule) in rules.iter().enumerate() {
+            if i > 0 {
+                self.sql.push_str(joiner);
+            }
+            self.rule(rule, depth + 1)?;
+        }
+        self.sql.push(')');
+        Ok(())
+    }
+
+    fn condition(&mut self, field: Field, operator: Operator, value: &Value) -> Result<(), String> {
+        let column = field.expression();
+        let mismatch = || format!("{operator:?} doesn't apply to {field:?}");
+        let text = || {
+            value
+                .as_str()
+                .map(|s| SqlValue::Text(s.to_string()))
+                .ok_or_else(|| format!("{field:?} needs a text value"))
+        };
+        let number = || match value.as_i64() {
+            Some(n) => Ok(SqlValue::Integer(n)),
+            None => value
+                .as_f64()
+                .map(SqlValue::Real)
+                .ok_or_else(|| format!("{field:?} needs a numeric value")),
+        };
+        let flag = || {
+            value
+                .as_bool()
+                .map(|b| SqlValue::Integer(b.into()))
+                .ok_or_else(|| format!("{field:?} needs true or false"))
+        };
+        let (fragment, params) = match (field.kind(), operator) {
+            (Kind::Text, Operator::Is) => ("{} = ? COLLATE NOCASE", vec![text()?]),
+            (Kind::Text, Operator::IsNot) => ("{} <> ? COLLATE NOCASE", vec![text()?]),
+            (Kind::Text, Operator::Contains) => ("instr(lower({}), lower(?)) > 0", vec![text()?]),
+            (Kind::Text, Operator::NotContains) => {
+                ("instr(lower({}), lower(?)) = 0", vec![text()?])
+            }
+            (Kind::Text, Operator::StartsWith) => (
+                "substr(lower({}), 1, length(?)) = lower(?)",
+                vec![text()?, text()?],
+            ),
+            (Kind::Number, Operator::Is) => ("{} = ?", vec![number()?]),
+            (Kind::Number, Operator::IsNot) => ("{} <> ?", vec![number()?]),
+            (Kind::Number, Operator::Lt) => ("{} < ?", vec![number()?]),
+            (Kind::Number, Operator::Le) => ("{} <= ?", vec![number()?]),
+            (Kind::Number, Operator::Gt) => ("{} > ?", vec![number()?]),
+            (Kind::Number, Operator::Ge) => ("{} >= ?", vec![number()?]),
+            (Kind::Flag, Operator::Is) => ("{} = ?", vec![flag()?]),
+            (Kind::Flag, Operator::IsNot) => ("{} <> ?", vec![flag()?]),
+            (Kind::Date, Operator::Before) => ("{} < ?", vec![number()?]),
+            (Kind::Date, Operator::After) => ("{} > ?", vec![number()?]),
+            (Kind::Date, Operator::InLastDays) => {
+                let days = value
+                    .as_f64()
+                    .filter(|days| *days > 0.0)
+                    .ok_or_else(|| format!("{field:?} needs a positive number of days"))?;
+                // Ranges longer than the clock goes back just match everything.
+                let since = now_millis().saturating_sub((days * DAY_MS as f64) as i64);
+                ("{} >= ?", vec![SqlValue::Integer(since)])
+            }
+            _ => return Err(mismatch()),
+        };
+        self.sql.push_str(&fragment.replace("{}", column));
+        self.params.extend(params);
+        Ok(())
+    }
+}
+
+/// Compiles `rules` into a `WHERE` clause over `tracks` and its parameters.
+pub fn compile(rules: &Rule) -> Result<(String, Vec<SqlValue>), String> {
+    let mut compiler = Compiler {
+        sql: String::new(),
+        params: Vec::new(),
+    };
+    compiler.rule(rules, 0)?;
+    Ok((compiler.sql, compiler.params))
+}
+
+fn playlists_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join(SMART_PLAYLISTS_FILE))
+}
+
+/// Reads saved smart playlists, treating a missing or unreadable file as empty.
+fn read_playlists(app: &AppHandle) -> Result<BTreeMap<String, SmartPlaylist>, String> {
+    let path = playlists_path(app)?;
+    Ok(fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+/// Indexed tracks matching `rules`, in library order.
+#[tauri::command]
+pub async fn evaluate_smart_playlist(
+    rules: Rule,
+    limit: Option<usize>,
+    app: AppHandle,
+) -> Result<Vec<TrackInfo>, PlayerError> {
+    let (filter, params) = compile(&rules)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<LibraryIndex>()
+            .select_tracks(&filter, params, limit)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Saves `playlist` under `name`, replacing any smart playlist of that name.
+#[tauri::command]
+pub fn save_smart_playlist(
+    name: String,
+    playlist: SmartPlaylist,
+    app: AppHandle,
+) -> Result<(), PlayerError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("playlist name is empty".into());
+    }
+    // Refuse rules that could never be evaluated.
+    compile(&playlist.rules)?;
+    let mut playlists = read_playlists(&app)?;
+    playlists.insert(name.to_string(), playlist);
+    let json = serde_json::to_string_pretty(&playlists).map_err(|e| e.to_string())?;
+    write_atomic(&playlists_path(&app)?, json.as_bytes())
+}
+
+#[tauri::command]
+pub fn load_smart_playlist(name: String, app: AppHandle) -> Result<SmartPlaylist, PlayerError> {
+    read_playlists(&app)?
+        .remove(name.trim())
+        .ok_or_else(|| format!("no smart playlist named {name}").into())
+}
+
+#[tauri::command]
+pub fn list_smart_playlists(app: AppHandle) -> Result<Vec<String>, PlayerError> {
+    Ok(read_playlists(&app)?.into_keys().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn condition(field: Field, operator: Operator, value: Value) -> Rule {
+        Rule::Condition {
+            field,
+            operator,
+            value,
+        }
+    }
+
+    fn compile_json(rules: Value) -> Result<(String, Vec<SqlValue>), String> {
+        compile(&serde_json::from_value(rules).unwrap())
+    }
+
+    #[test]
+    fn groups_nest_in_parentheses() {
+        let (sql, params) = compile_json(json!({
+            "type": "and",
+            "rules": [
+                { "type": "condition", "field": "genre", "operator": "is", "value": "Jazz" },
+                { "type": "or", "rules": [
+                    { "type": "condition", "field": "year", "operator": "lt", "value": 1960 },
+                    { "type": "condition", "field": "favorite", "operator": "is", "value": true },
+                ]},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            sql,
+            "(tracks.genre = ? COLLATE NOCASE AND (tracks.year < ? OR tracks.favorite = ?))"
+        );
+        assert_eq!(
+            params,
+            [
+                SqlValue::Text("Jazz".into()),
+                SqlValue::Integer(1960),
+                SqlValue::Integer(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_groups_match_everything_or_nothing() {
+        assert_eq!(compile(&Rule::And { rules: vec![] }).unwrap().0, "1");
+        assert_eq!(compile(&Rule::Or { rules: vec![] }).unwrap().0, "0");
+    }
+
+    #[test]
+    fn values_never_reach_the_sql() {
+        let value = "'); DROP TABLE tracks; --";
+        let (sql, params) =
+            compile(&condition(Field::Title, Operator::Contains, json!(value))).unwrap();
+        assert!(!sql.contains(value));
+        assert_eq!(params, [SqlValue::Text(value.into())]);
+        let (sql, params) = compile(&condition(
+            Field::Artist,
+            Operator::StartsWith,
+            json!("The"),
+        ))
+        .unwrap();
+        assert_eq!(sql.matches('?').count(), params.len());
+    }
+
+    #[test]
+    fn numbers_keep_their_type() {
+        let (_, params) =
+            compile(&condition(Field::DurationMs, Operator::Ge, json!(90.5))).unwrap();
+        assert_eq!(params, [SqlValue::Real(90.5)]);
+        let (_, params) = compile(&condition(
+            Field::AddedAt,
+            Operator::Before,
+            json!(1_700_000_000_000i64),
+        ))
+        .unwrap();
+        assert_eq!(params, [SqlValue::Integer(1_700_000_000_000)]);
+    }
+
+    #[test]
+    fn in_last_days_counts_back_from_now() {
+        let before = now_millis();
+        let (sql, params) = compile(&condition(
+            Field::LastPlayed,
+            Operator::InLastDays,
+            json!(7),
+        ))
+        .unwrap();
+        assert!(sql.ends_with(" >= ?"));
+        let [SqlValue::Integer(since)] = params[..] else {
+            panic!("expected one integer, got {params:?}");
+        };
+        assert!((before - 7 * DAY_MS..=now_millis() - 7 * DAY_MS).contains(&since));
+
+        let forever = condition(Field::LastPlayed, Operator::InLastDays, json!(1e300));
+        assert!(compile(&forever).is_ok());
+    }
+
+    #[test]
+    fn rejects_operators_and_values_of_the_wrong_kind() {
+        let cases = [
+            condition(Field::Year, Operator::Contains, json!("19")),
+            condition(Field::Title, Operator::Lt, json!("M")),
+            condition(Field::AddedAt, Operator::Is, json!(0)),
+            condition(Field::Favorite, Operator::Gt, json!(true)),
+            condition(Field::Title, Operator::Is, json!(3)),
+            condition(Field::Rating, Operator::Ge, json!("four")),
+            condition(Field::Favorite, Operator::Is, json!(1)),
+            condition(Field::LastPlayed, Operator::InLastDays, json!("week")),
+            condition(Field::LastPlayed, Operator::InLastDays, json!(0)),
+            condition(Field::LastPlayed, Operator::InLastDays, json!(-3)),
+        ];
+        for rule in cases {
+            assert!(compile(&rule).is_err(), "{rule:?}");
+        }
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| {
+            (0..depth).fold(
+                condition(Field::Rating, Operator::Ge, json!(4)),
+                |rule, _| Rule::And { rules: vec![rule] },
+            )
+        };
+        assert!(compile(&nested(MAX_RULE_DEPTH)).is_ok());
+        assert!(compile(&nested(MAX_RULE_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn compiled_rules_select_matching_tracks() {
+        let index = LibraryIndex::open(Path::new(":memory:")).unwrap();
+        {
+            let conn = index.connection();
+            let tracks = [
+                ("/a.flac", "Blue Train", "John Coltrane", "Jazz", 1957, 4, 1),
+                ("/b.flac", "So What", "Miles Davis", "jazz", 1959, 5, 0),
+                ("/c.flac", "Teen Spirit", "Nirvana", "Grunge", 1991, 2, 1),
+            ];
+            for (path, title, artist, genre, year, rating, favorite) in tracks {
+                conn.execute(
+                    "INSERT INTO tracks (path, size, extension, mtime, title, artist, album,
+                        album_artist, track_number, disc_number, year, genre, duration_ms,
+                        added_at, rating, favorite)
+                     VALUES (?1, 0, 'flac', 0, ?2, ?3, '', ?3, 1, 1, ?4, ?5, 300000, 0, ?6, ?7)",
+                    rusqlite::params![path, title, artist, year, genre, rating, favorite],
+                )
+                .unwrap();
+            }
+            conn.execute(
+                "INSERT INTO plays (path, played_at, listened_ms) VALUES ('/b.flac', 1, 1), ('/b.flac', 2, 1)",
+                [],
+            )
+            .unwrap();
+        }
+        let select = |rules: Value| {
+            let (sql, params) = compile_json(rules).unwrap();
+            let tracks = index.select_tracks(&sql, params, None).unwrap();
+            tracks.into_iter().map(|t| t.path).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            select(
+                json!({ "type": "condition", "field": "genre", "operator": "is", "value": "JAZZ" })
+            ),
+            ["/a.flac", "/b.flac"]
+        );
+        assert_eq!(
+            select(
+                json!({ "type": "condition", "field": "title", "operator": "starts_with", "value": "so" })
+            ),
+            ["/b.flac"]
+        );
+        assert_eq!(
+            select(
+                json!({ "type": "condition", "field": "play_count", "operator": "ge", "value": 2 })
+            ),
+            ["/b.flac"]
+        );
+        assert_eq!(
+            select(json!({ "type": "or", "rules": [
+                { "type": "condition", "field": "rating", "operator": "le", "value": 2 },
+                { "type": "and", "rules": [
+                    { "type": "condition", "field": "favorite", "operator": "is", "value": true },
+                    { "type": "condition", "field": "year", "operator": "lt", "value": 1958 },
+                ]},
+            ]})),
+            ["/a.flac", "/c.flac"]
+        );
+        assert!(select(json!({ "type": "or", "rules": [] })).is_empty());
+    }
+}
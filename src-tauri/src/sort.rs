@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+use serde::Deserialize;
+
+use crate::library::TrackInfo;
+use crate::metadata::TrackMetadata;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Year,
+    /// Disc, then track number.
+    TrackNumber,
+    DurationMs,
+    Rating,
+    FileName,
+    Path,
+}
+
+/// Value of `c` as a decimal digit, for ASCII digits and the other digit
+/// sets common in file names (fullwidth, Arabic-Indic, Devanagari).
+fn digit(c: char) -> Option<u32> {
+    let zero = match c {
+        '0'..='9' => '0',
+        '\u{ff10}'..='\u{ff19}' => '\u{ff10}',
+        '\u{0660}'..='\u{0669}' => '\u{0660}',
+        '\u{06f0}'..='\u{06f9}' => '\u{06f0}',
+        '\u{0966}'..='\u{096f}' => '\u{0966}',
+        _ => return None,
+    };
+    Some(c as u32 - zero as u32)
+}
+
+/// Takes a run of digits, returning their values without leading zeros and
+/// how many zeros were dropped.
+fn number(chars: &mut Peekable<Chars<'_>>) -> (Vec<u32>, usize) {
+    let mut digits = Vec::new();
+    let mut zeros = 0;
+    while let Some(d) = chars.peek().copied().and_then(digit) {
+        chars.next();
+        if d == 0 && digits.is_empty() {
+            zeros += 1;
+        } else {
+            digits.push(d);
+        }
+    }
+    (digits, zeros)
+}
+
+/// Compares strings the way a person reads them: "Track 2" before
+/// "Track 10", case ignored. Numbers of equal value order by fewer leading
+/// zeros, and strings that only differ in case fall back to plain ordering,
+/// so the order is total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+    let mut zeros = Ordering::Equal;
+    loop {
+        let (l, r) = match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) => (l, r),
+        };
+        if digit(l).is_some() && digit(r).is_some() {
+            let (l_digits, l_zeros) = number(&mut left);
+            let (r_digits, r_zeros) = number(&mut right);
+            let by_value = l_digits
+                .len()
+                .cmp(&r_digits.len())
+                .then_with(|| l_digits.cmp(&r_digits));
+            if by_value != Ordering::Equal {
+                return by_value;
+            }
+            zeros = zeros.then(l_zeros.cmp(&r_zeros));
+            continue;
+        }
+        let by_char = l.to_lowercase().cmp(r.to_lowercase());
+        if by_char != Ordering::Equal {
+            return by_char;
+        }
+        left.next();
+        right.next();
+    }
+    zeros.then_with(|| a.cmp(b))
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+/// The tagged title, or the file name for a track without tags.
+fn title(track: &TrackInfo) -> &str {
+    match &track.metadata {
+        Some(metadata) => &metadata.title,
+        None => file_name(&track.path),
+    }
+}
+
+/// Compares tagged tracks with `cmp`; tracks without tags sort after them.
+fn by_tags(
+    a: &TrackInfo,
+    b: &TrackInfo,
+    cmp: impl Fn(&TrackMetadata, &TrackMetadata) -> Ordering,
+) -> Ordering {
+    match (&a.metadata, &b.metadata) {
+        (Some(x), Some(y)) => cmp(x, y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Orders two tracks by `field`. Text compares naturally or, with `natural`
+/// off, by plain code point order. Tracks without tags sort by file name in
+/// place of a title, and after tagged tracks for the other tag fields.
+pub fn compare(a: &TrackInfo, b: &TrackInfo, field: SortField, natural: bool) -> Ordering {
+    let text = |x: &str, y: &str| {
+        if natural {
+            natural_cmp(x, y)
+        } else {
+            x.cmp(y)
+        }
+    };
+    match field {
+        SortField::Title => text(title(a), title(b)),
+        SortField::Artist => by_tags(a, b, |x, y| text(&x.artist, &y.artist)),
+        SortField::Album => by_tags(a, b, |x, y| text(&x.album, &y.album)),
+        SortField::AlbumArtist => by_tags(a, b, |x, y| text(&x.album_artist, &y.album_artist)),
+        SortField::Genre => by_tags(a, b, |x, y| text(&x.genre, &y.genre)),
+        SortField::Year => by_tags(a, b, |x, y| x.year.cmp(&y.year)),
+        SortField::TrackNumber => by_tags(a, b, |x, y| {
+            (x.disc_number, x.track_number).cmp(&(y.disc_number, y.track_number))
+        }),
+        SortField::DurationMs => by_tags(a, b, |x, y| x.duration_ms.cmp(&y.duration_ms)),
+        SortField::Rating => {
+            let stars = |t: &TrackInfo| t.rating.map_or(0, |r| r.stars);
+            stars(a).cmp(&stars(b))
+        }
+        SortField::FileName => text(file_name(&a.path), file_name(&b.path)),
+        SortField::Path => text(&a.path, &b.path),
+    }
+}
+
+/// Sorts `tracks` by `field`, ascending; ties keep their incoming order.
+/// `natural` compares text the way a person reads it ("Track 2" before
+/// "Track 10") rather than character by character.
+#[tauri::command]
+pub fn sort_tracks(mut tracks: Vec<TrackInfo>, field: SortField, natural: bool) -> Vec<TrackInfo> {
+    tracks.sort_by(|a, b| compare(a, b, field, natural));
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|s| s.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(
+            sorted(&["Track 10", "Track 2", "Track 1", "Track 21"]),
+            ["Track 1", "Track 2", "Track 10", "Track 21"]
+        );
+        assert_eq!(
+            sorted(&["v1.10", "v1.9", "v1.9.1"]),
+            ["v1.9", "v1.9.1", "v1.10"]
+        );
+        // Longer than any integer type.
+        assert_eq!(
+            natural_cmp("id 99999999999999999999999", "id 100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn case_is_ignored_until_nothing_else_differs() {
+        assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+        assert_eq!(natural_cmp("Apple", "apple"), "Apple".cmp("apple"));
+        assert_eq!(natural_cmp("ÉCOLE", "école"), "ÉCOLE".cmp("école"));
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural_cmp("01", "2"), Ordering::Less);
+        assert_eq!(natural_cmp("track 007", "track 7"), Ordering::Greater);
+        assert_eq!(natural_cmp("a01b2", "a1b02"), Ordering::Greater);
+        assert_eq!(natural_cmp("0", "00"), Ordering::Less);
+    }
+
+    #[test]
+    fn other_digit_sets_count_as_numbers() {
+        // Fullwidth, Arabic-Indic, and Devanagari "10" against ASCII "9".
+        for ten in ["\u{ff11}\u{ff10}", "\u{0661}\u{0660}", "\u{0967}\u{0966}"] {
+            assert_eq!(
+                natural_cmp(&format!("Disc {ten}"), "Disc 9"),
+                Ordering::Greater
+            );
+        }
+    }
+
+    #[test]
+    fn prefixes_sort_first() {
+        assert_eq!(natural_cmp("Track", "Track 1"), Ordering::Less);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+        assert_eq!(natural_cmp("", ""), Ordering::Equal);
+    }
+
+    #[test]
+    fn the_order_is_total() {
+        let names = [
+            "",
+            "a",
+            "A",
+            "a1",
+            "a01",
+            "A1",
+            "a2",
+            "a10",
+            "a 2",
+            "b",
+            "B01",
+            "b1",
+            "ß",
+            "é",
+            "E",
+            "10",
+            "9",
+            "09",
+            "x\u{ff19}",
+            "x9",
+        ];
+        for a in names {
+            assert_eq!(natural_cmp(a, a), Ordering::Equal);
+            for b in names {
+                assert_eq!(
+                    natural_cmp(a, b),
+                    natural_cmp(b, a).reverse(),
+                    "{a:?} {b:?}"
+                );
+                if a != b {
+                    assert_ne!(natural_cmp(a, b), Ordering::Equal, "{a:?} {b:?}");
+                }
+                for c in names {
+                    if natural_cmp(a, b).is_le() && natural_cmp(b, c).is_le() {
+                        assert!(natural_cmp(a, c).is_le(), "{a:?} {b:?} {c:?}");
+                    }
+                }
+            }
+        }
+    }
+}
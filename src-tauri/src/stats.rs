@@ -0,0 +1,42 @@
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::PlayerError;
+use crate::index::LibraryIndex;
+
+/// Plays and listening time for one track. A play counts once the track has
+/// been heard past the scrobble threshold; see [`crate::scrobble`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackStats {
+    pub path: String,
+    pub play_count: u64,
+    pub listened_ms: u64,
+    /// Unix time in milliseconds the last counted play started.
+    pub last_played: Option<i64>,
+}
+
+#[tauri::command]
+pub fn get_track_stats(
+    path: String,
+    index: State<'_, LibraryIndex>,
+) -> Result<TrackStats, PlayerError> {
+    index.track_stats(&path)
+}
+
+#[tauri::command]
+pub fn get_top_tracks(
+    limit: usize,
+    index: State<'_, LibraryIndex>,
+) -> Result<Vec<TrackStats>, PlayerError> {
+    index.top_tracks(limit)
+}
+
+/// Milliseconds listened in plays started since `since` (Unix milliseconds),
+/// or ever.
+#[tauri::command]
+pub fn get_listening_time(
+    since: Option<i64>,
+    index: State<'_, LibraryIndex>,
+) -> Result<u64, PlayerError> {
+    index.listening_time(since)
+}
@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+use tauri::State;
+
+use crate::audio::PlayerState;
+use crate::dsp::AtomicGain;
+use crate::error::PlayerError;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// Length of each overlap-added segment. Long enough to hold a couple of
+/// pitch periods of a low voice, short enough not to smear transients.
+const WINDOW: Duration = Duration::from_millis(40);
+
+/// How far a segment may shift from its nominal position to line up with
+/// the waveform already written.
+const TOLERANCE: Duration = Duration::from_millis(8);
+
+/// Step between correlation samples; the alignment doesn't need every one.
+const CORRELATION_STRIDE: usize = 2;
+
+/// Playback speed shared by every track's [`Stretched`] stage.
+#[derive(Debug)]
+pub struct SpeedControl(AtomicGain);
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        SpeedControl(AtomicGain::new(1.0))
+    }
+}
+
+impl SpeedControl {
+    pub fn get(&self) -> f32 {
+        self.0.get()
+    }
+
+    /// Sets the speed, clamped to `MIN_SPEED..=MAX_SPEED`.
+    pub fn set(&self, speed: f32) {
+        self.0.set(speed.clamp(MIN_SPEED, MAX_SPEED));
+    }
+}
+
+/// Changes tempo without changing pitch (WSOLA: waveform-similarity overlap-add).
+///
+/// Segments of the input are cut at a hop scaled by the speed, nudged within
+/// [`TOLERANCE`] to where they best continue the output, and cross-faded
+/// with a Hann window at a fixed output hop. At exactly 1.0 the source passes
+/// straight through.
+pub struct Stretched<S> {
+    inner: S,
+    speed: Arc<SpeedControl>,
+    channels: usize,
+    window: usize,
+    hop: usize,
+    tolerance: usize,
+    hann: Vec<f32>,
+    /// Interleaved input not yet consumed.
+    input: Vec<f32>,
+    /// Nominal position of the next segment in `input`, in frames.
+    position: f64,
+    /// Where the previous segment started in `input`, in frames.
+    previous: Option<usize>,
+    /// Output being overlap-added, `window` frames long.
+    overlap: Vec<f32>,
+    ready: VecDeque<f32>,
+    /// Sample index within the current frame, so the mode only switches
+    /// between frames.
+    in_frame: usize,
+    stretching: bool,
+    exhausted: bool,
+}
+
+impl<S: Source<Item = f32>> Stretched<S> {
+    pub fn new(inner: S, speed: Arc<SpeedControl>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let rate = inner.sample_rate() as f32;
+        let frames = |d: Duration| (d.as_secs_f32() * rate) as usize;
+        // An even window keeps the 50% hop exact, which makes the periodic
+        // Hann windows sum to one.
+        let window = (frames(WINDOW) / 2 * 2).max(2);
+        let hann = (0..window)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / window as f32).cos())
+            .collect();
+        Stretched {
+            inner,
+            speed,
+            channels,
+            window,
+            hop: window / 2,
+            tolerance: frames(TOLERANCE),
+            hann,
+            input: Vec::new(),
+            position: 0.0,
+            previous: None,
+            overlap: vec![0.0; window * channels],
+            ready: VecDeque::new(),
+            in_frame: 0,
+            stretching: false,
+            exhausted: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.previous = None;
+        self.overlap.iter_mut().for_each(|s| *s = 0.0);
+        self.ready.clear();
+        self.exhausted = false;
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// Mono sample `frame` of `input`.
+    fn mono(&self, frame: usize) -> f32 {
+        let start = frame * self.channels;
+        self.input[start..start + self.channels].iter().sum()
+    }
+
+    /// Reads input until `frames` are buffered or the source ends.
+    fn fill(&mut self, frames: usize) {
+        while !self.exhausted && self.frames() < frames {
+            let mut frame = Vec::with_capacity(self.channels);
+            for _ in 0..self.channels {
+                match self.inner.next() {
+                    Some(sample) => frame.push(sample),
+                    None => break,
+                }
+            }
+            if frame.len() < self.channels {
+                self.exhausted = true;
+            } else {
+                self.input.extend(frame);
+            }
+        }
+    }
+
+    /// Offset within `start..=start + 2 * tolerance` whose segment best
+    /// continues the one after `previous`.
+    fn best_offset(&self, start: usize, previous: usize) -> usize {
+        let natural = previous + self.hop;
+        let mut best = (self.tolerance, f32::MIN);
+        for offset in 0..=2 * self.tolerance {
+            let candidate = start + offset;
+            let mut score = 0.0;
+            for i in (0..self.hop).step_by(CORRELATION_STRIDE) {
+                score += self.mono(natural + i) * self.mono(candidate + i);
+            }
+            if score > best.1 {
+                best = (offset, score);
+            }
+        }
+        best.0
+    }
+
+    /// Adds one segment to the output, making `hop` frames ready. Returns
+    /// `false` once the input has run out.
+    fn step(&mut self, speed: f32) -> bool {
+        let nominal = self.position.round() as usize;
+        let start = nominal.saturating_sub(self.tolerance);
+        let needed = (start + 2 * self.tolerance + self.window)
+            .max(self.previous.map_or(0, |p| p + self.hop + self.window));
+        self.fill(needed);
+        if self.frames() < needed {
+            // Flush what's been overlap-added and end with the source.
+            if self.previous.take().is_some() {
+                self.ready
+                    .extend(self.overlap.drain(..self.hop * self.channels));
+            }
+            return false;
+        }
+
+        let chosen = match self.previous {
+            Some(previous) => start + self.best_offset(start, previous),
+            None => nominal,
+        };
+        for i in 0..self.window {
+            let gain = self.hann[i];
+            for c in 0..self.channels {
+                self.overlap[i * self.channels + c] +=
+                    self.input[(chosen + i) * self.channels + c] * gain;
+            }
+        }
+        self.ready
+            .extend(self.overlap.drain(..self.hop * self.channels));
+        self.overlap
+            .extend(std::iter::repeat_n(0.0, self.hop * self.channels));
+        self.previous = Some(chosen);
+        self.position += self.hop as f64 * speed as f64;
+
+        // Drop input no later segment can reach.
+        let keep_from = chosen.min((self.position as usize).saturating_sub(self.tolerance));
+        if keep_from > 0 {
+            self.input.drain(..keep_from * self.channels);
+            self.position -= keep_from as f64;
+            self.previous = Some(chosen - keep_from);
+        }
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Stretched<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.in_frame == 0 {
+            let speed = self.speed.get();
+            let stretch = speed != 1.0;
+            if stretch != self.stretching && self.ready.is_empty() {
+                // Input buffered for the old mode is dropped; a speed change
+                // skips at most a window's worth of audio.
+                self.reset();
+                self.stretching = stretch;
+            }
+            if self.stretching && self.ready.is_empty() && !self.step(speed) {
+                self.stretching = false;
+                self.input.clear();
+                if self.ready.is_empty() {
+                    return None;
+                }
+            }
+        }
+        let sample = if self.stretching || !self.ready.is_empty() {
+            self.ready.pop_front()?
+        } else {
+            self.inner.next()?
+        };
+        self.in_frame = (self.in_frame + 1) % self.channels;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Stretched<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.stretching {
+            // Output isn't aligned with the inner frames while stretching.
+            None
+        } else {
+            self.inner.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let speed = self.speed.get();
+        self.inner.total_duration().map(|d| d.div_f32(speed))
+    }
+}
+
+/// Plays faster or slower without changing pitch, e.g. `1.5` for a podcast.
+/// `rate` is clamped to `0.5..=3.0` and applies to the playing track at once.
+#[tauri::command]
+pub fn set_playback_speed(rate: f32, player: State<'_, PlayerState>) -> Result<(), PlayerError> {
+    if !rate.is_finite() {
+        return Err(format!("invalid playback speed {rate}").into());
+    }
+    player.speed.set(rate);
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Locking that survives a panic in another thread.
+///
+/// Background loops run each pass under [`crate::logging::guarded`], so a
+/// panic while holding a lock would otherwise poison it and make every later
+/// `lock().unwrap()` panic too, killing the engine one thread at a time.
+/// Instead the data is taken as the panicking thread left it: at worst a
+/// half-made change, such as a queue edit applied to only some of its
+/// fields, which the next command or tick overwrites.
+pub trait MutexExt<T> {
+    /// Locks the mutex, recovering it if a panic poisoned it.
+    fn locked(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn locked(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
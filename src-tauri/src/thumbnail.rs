@@ -0,0 +1,126 @@
+use std::fs::{self, File};
+use std::io::{Cursor, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use image::imageops::FilterType;
+use image::ImageFormat;
+use tauri::{AppHandle, Manager};
+
+use crate::artwork::{self, Artwork};
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::index::{absolute, mtime_millis};
+use crate::settings::write_atomic;
+
+pub const THUMBNAIL_DIR: &str = "thumbnails";
+
+pub const MAX_THUMBNAIL_SIZE: u32 = 1000;
+
+/// Once the cache grows past this, the least recently used thumbnails go.
+pub const MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+fn cache_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_cache_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join(THUMBNAIL_DIR))
+}
+
+/// Crops `art` to a centered square and scales it to `size` pixels a side.
+fn render(art: &Artwork, size: u32) -> Result<Artwork, String> {
+    let thumbnail = image::load_from_memory(&art.data)
+        .map_err(|e| format!("failed to decode album art: {e}"))?
+        .resize_to_fill(size, size, FilterType::Lanczos3);
+    let mut data = Vec::new();
+    thumbnail
+        .to_rgb8()
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .map_err(|e| format!("failed to encode thumbnail: {e}"))?;
+    Ok(Artwork {
+        mime: ImageFormat::Jpeg.to_mime_type().to_string(),
+        data,
+    })
+}
+
+/// Deletes the least recently used files in `dir` until it fits in
+/// [`MAX_CACHE_BYTES`]. Cache hits refresh a file's mtime, so that serves as
+/// its last use.
+fn evict(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let used = meta.modified().ok()?;
+            meta.is_file().then(|| (used, meta.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if total <= MAX_CACHE_BYTES {
+        return;
+    }
+    files.sort();
+    for (_, len, file) in files {
+        if total <= MAX_CACHE_BYTES {
+            break;
+        }
+        if fs::remove_file(&file).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+/// A `size`-pixel square JPEG of `path`'s cover as a data URI, from its
+/// embedded art or else a cover image in its folder. Thumbnails are cached
+/// on disk until the track changes.
+#[tauri::command]
+pub async fn get_thumbnail(path: String, size: u32, app: AppHandle) -> Result<String, PlayerError> {
+    if size == 0 || size > MAX_THUMBNAIL_SIZE {
+        return Err(format!("size must be between 1 and {MAX_THUMBNAIL_SIZE}").into());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        // A CUE sheet's tracks share the cover of the image they're cut from.
+        let track = absolute(&decoder::locate(Path::new(&path))?.0)?;
+        let mtime =
+            mtime_millis(&fs::metadata(&track).map_err(|e| PlayerError::io("read", &track, e))?);
+        let key = format!("{}\0{mtime}\0{size}", track.to_string_lossy());
+        let dir = cache_dir(&app)?;
+        let cached = dir.join(format!("{:x}.jpg", md5::compute(key)));
+        if let Ok(data) = fs::read(&cached) {
+            if let Ok(file) = File::options().append(true).open(&cached) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            let art = Artwork {
+                mime: ImageFormat::Jpeg.to_mime_type().to_string(),
+                data,
+            };
+            return Ok(art.to_data_uri());
+        }
+
+        // Files whose tags can't be read may still sit next to a cover image.
+        let art = artwork::embedded(&track)
+            .ok()
+            .flatten()
+            .or_else(|| artwork::from_folder(&track))
+            .ok_or_else(|| format!("no album art for {path}"))?;
+        let thumbnail = render(&art, size)?;
+        fs::create_dir_all(&dir).map_err(|e| PlayerError::io("create", &dir, e))?;
+        // A thumbnail that can't be cached is still worth returning.
+        if write_atomic(&cached, &thumbnail.data).is_ok() {
+            evict(&dir);
+        }
+        Ok(thumbnail.to_data_uri())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn clear_thumbnail_cache(app: AppHandle) -> Result<(), PlayerError> {
+    let dir = cache_dir(&app)?;
+    match fs::remove_dir_all(&dir) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(PlayerError::io("remove", &dir, e)),
+        _ => Ok(()),
+    }
+}
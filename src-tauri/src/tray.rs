@@ -0,0 +1,144 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Listener, Manager, State, Window, WindowEvent};
+
+use crate::audio::{self, PlaybackState, PlaybackStatus, PlayerState, PLAYBACK_STATE_EVENT};
+use crate::error::PlayerError;
+use crate::metadata;
+use crate::queue;
+use crate::settings::SettingsStore;
+use crate::sync::MutexExt;
+
+pub const TRAY_ID: &str = "main";
+
+const PLAY_PAUSE_ITEM: &str = "play-pause";
+const NEXT_ITEM: &str = "next";
+const PREVIOUS_ITEM: &str = "previous";
+const QUIT_ITEM: &str = "quit";
+
+/// Adds the tray icon and its playback menu, headed by the current track and
+/// kept in step with `playback-state-changed`. Clicking the icon brings the
+/// main window back.
+pub fn start(app: &AppHandle) -> Result<(), String> {
+    build(app).map_err(|e| format!("failed to create tray icon: {e}"))
+}
+
+fn build(app: &AppHandle) -> tauri::Result<()> {
+    let now_playing = MenuItem::with_id(app, "now-playing", "Not playing", false, None::<&str>)?;
+    let play_pause = MenuItem::with_id(app, PLAY_PAUSE_ITEM, "Play", true, None::<&str>)?;
+    let menu = Menu::with_items(
+        app,
+        &[
+            &now_playing,
+            &PredefinedMenuItem::separator(app)?,
+            &play_pause,
+            &MenuItem::with_id(app, NEXT_ITEM, "Next", true, None::<&str>)?,
+            &MenuItem::with_id(app, PREVIOUS_ITEM, "Previous", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, QUIT_ITEM, "Quit", true, None::<&str>)?,
+        ],
+    )?;
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(&app.package_info().name)
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+
+    let shown = Mutex::new(None::<String>);
+    app.listen(PLAYBACK_STATE_EVENT, move |event| {
+        let Ok(status) = serde_json::from_str::<PlaybackStatus>(event.payload()) else {
+            return;
+        };
+        let label = match status.state {
+            PlaybackState::Playing => "Pause",
+            PlaybackState::Paused | PlaybackState::Stopped => "Play",
+        };
+        let _ = play_pause.set_text(label);
+        let mut shown = shown.locked();
+        if status.path != *shown {
+            let _ = now_playing.set_text(track_title(status.path.as_deref()));
+            *shown = status.path;
+        }
+    });
+    Ok(())
+}
+
+/// "Title — Artist" for `path`, falling back to its file name.
+fn track_title(path: Option<&str>) -> String {
+    let Some(path) = path else {
+        return "Not playing".into();
+    };
+    match metadata::read(Path::new(path)) {
+        Ok(tags) => format!("{} — {}", tags.title, tags.artist),
+        Err(_) => Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned()),
+    }
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    let player = app.state::<PlayerState>();
+    match event.id().as_ref() {
+        PLAY_PAUSE_ITEM => {
+            if player.status().state == PlaybackState::Playing {
+                audio::pause(app.clone(), player);
+            } else {
+                audio::resume(app.clone(), player);
+            }
+        }
+        NEXT_ITEM => audio::in_background(app, queue::skip_forward),
+        PREVIOUS_ITEM => audio::in_background(app, queue::skip_back),
+        QUIT_ITEM => app.exit(0),
+        _ => {}
+    }
+}
+
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Hides the main window instead of closing it while close-to-tray is on,
+/// so playback carries on; quitting goes through the tray menu. Without a
+/// tray icon to bring it back, the window closes as usual.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    let WindowEvent::CloseRequested { api, .. } = event else {
+        return;
+    };
+    let close_to_tray = window
+        .try_state::<SettingsStore>()
+        .is_some_and(|settings| settings.get().close_to_tray);
+    let has_tray = window.app_handle().tray_by_id(TRAY_ID).is_some();
+    if close_to_tray && has_tray && window.label() == "main" {
+        api.prevent_close();
+        let _ = window.hide();
+    }
+}
+
+#[tauri::command]
+pub fn set_close_to_tray(
+    enabled: bool,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    settings.update(|s| s.close_to_tray = enabled)
+}
@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rodio::Source;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audio::PlayerState;
+use crate::error::PlayerError;
+use crate::logging;
+use crate::sync::MutexExt;
+
+pub const SPECTRUM_EVENT: &str = "spectrum-data";
+
+/// Samples per FFT; about 46 ms at 44.1 kHz.
+pub const FFT_SIZE: usize = 2048;
+
+pub const DEFAULT_BINS: usize = 64;
+/// One per FFT frequency; more would only repeat them.
+pub const MAX_BINS: usize = FFT_SIZE / 2;
+pub const DEFAULT_RATE_HZ: u32 = 30;
+pub const MAX_RATE_HZ: u32 = 120;
+
+/// Lowest frequency covered by the first bin, in Hz.
+const MIN_FREQUENCY: f32 = 20.0;
+const MAX_FREQUENCY: f32 = 20_000.0;
+
+/// Samples are handed to the analyzer in chunks of this many frames, so the
+/// audio thread only touches the shared buffer now and then.
+const CHUNK_FRAMES: usize = 512;
+
+/// Recent mono samples of one track, filled by its [`Tapped`] stage.
+#[derive(Debug)]
+pub struct SampleTap {
+    enabled: Arc<AtomicBool>,
+    recent: Mutex<VecDeque<f32>>,
+    sample_rate: AtomicU32,
+    /// Total frames pushed, so the analyzer can tell when playback stalls.
+    written: AtomicU64,
+}
+
+impl SampleTap {
+    fn push(&self, chunk: &[f32], sample_rate: u32) {
+        // Never block the audio thread; dropping a chunk only skips a frame.
+        let Ok(mut recent) = self.recent.try_lock() else {
+            return;
+        };
+        recent.extend(chunk);
+        let excess = recent.len().saturating_sub(FFT_SIZE);
+        recent.drain(..excess);
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.written
+            .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+
+    /// The latest [`FFT_SIZE`] samples, or `None` until that many have arrived.
+    fn latest(&self) -> Option<(Vec<f32>, u32)> {
+        let recent = self.recent.locked();
+        if recent.len() < FFT_SIZE {
+            return None;
+        }
+        let rate = self.sample_rate.load(Ordering::Relaxed);
+        Some((recent.iter().copied().collect(), rate))
+    }
+}
+
+/// Copies a mono mix of the samples passing through into a [`SampleTap`].
+/// Costs one atomic load per frame while the visualizer is off.
+pub struct Tapped<S> {
+    inner: S,
+    tap: Arc<SampleTap>,
+    active: bool,
+    chunk: Vec<f32>,
+    frame_sum: f32,
+    channel: u16,
+}
+
+impl<S: Source<Item = f32>> Tapped<S> {
+    pub fn new(inner: S, tap: Arc<SampleTap>) -> Self {
+        Tapped {
+            inner,
+            tap,
+            active: false,
+            chunk: Vec::new(),
+            frame_sum: 0.0,
+            channel: 0,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Tapped<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let channels = self.inner.channels().max(1);
+        if self.channel == 0 {
+            self.active = self.tap.enabled.load(Ordering::Relaxed);
+        }
+        let sample = self.inner.next()?;
+        self.channel = (self.channel + 1) % channels;
+        if !self.active {
+            return Some(sample);
+        }
+        self.frame_sum += sample;
+        if self.channel == 0 {
+            self.chunk.push(self.frame_sum / channels as f32);
+            self.frame_sum = 0.0;
+            if self.chunk.len() >= CHUNK_FRAMES {
+                self.tap.push(&self.chunk, self.inner.sample_rate());
+                self.chunk.clear();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Tapped<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Owns the analyzer thread and the flag every [`Tapped`] stage checks.
+#[derive(Default)]
+pub struct Visualizer {
+    enabled: Arc<AtomicBool>,
+    analyzer: Mutex<Option<Analyzer>>,
+}
+
+struct Analyzer {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Visualizer {
+    /// A fresh tap for a newly opened track.
+    pub fn tap(&self) -> Arc<SampleTap> {
+        Arc::new(SampleTap {
+            enabled: self.enabled.clone(),
+            recent: Mutex::new(VecDeque::with_capacity(FFT_SIZE + CHUNK_FRAMES)),
+            sample_rate: AtomicU32::new(0),
+            written: AtomicU64::new(0),
+        })
+    }
+
+    /// (Re)starts the analyzer, emitting `bins` magnitudes `rate_hz` times a second.
+    pub fn start(&self, bins: usize, rate_hz: u32, app: AppHandle) -> std::io::Result<()> {
+        self.stop();
+        let interval = Duration::from_secs(1) / rate_hz.clamp(1, MAX_RATE_HZ);
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let handle = thread::Builder::new()
+            .name("spectrum-analyzer".into())
+            .spawn(move || {
+                let mut spectrum = Spectrum::new(bins.clamp(1, MAX_BINS));
+                let mut last_written = None;
+                while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                    logging::guarded(|| {
+                        let tap = app.state::<PlayerState>().current_tap();
+                        let written = tap.as_ref().map(|t| t.written.load(Ordering::Relaxed));
+                        if written == last_written {
+                            return;
+                        }
+                        last_written = written;
+                        let magnitudes = match tap.as_ref().and_then(|t| t.latest()) {
+                            Some((samples, rate)) => spectrum.analyze(&samples, rate),
+                            None => vec![0.0; spectrum.bins],
+                        };
+                        let _ = app.emit(SPECTRUM_EVENT, magnitudes);
+                    });
+                }
+            })?;
+        *self.analyzer.locked() = Some(Analyzer { stop, handle });
+        self.enabled.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        self.enabled.store(false, Ordering::Relaxed);
+        if let Some(analyzer) = self.analyzer.locked().take() {
+            let _ = analyzer.stop.send(());
+            let _ = analyzer.handle.join();
+        }
+    }
+}
+
+/// FFT state reused between frames.
+struct Spectrum {
+    bins: usize,
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl Spectrum {
+    fn new(bins: usize) -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        Spectrum {
+            bins,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            buffer: vec![Complex::default(); FFT_SIZE],
+        }
+    }
+
+    /// Peak magnitude in each of `bins` log-spaced bands, scaled so a
+    /// full-scale sine reads about 1.0.
+    fn analyze(&mut self, samples: &[f32], sample_rate: u32) -> Vec<f32> {
+        for ((slot, sample), w) in self.buffer.iter_mut().zip(samples).zip(&self.window) {
+            *slot = Complex::new(sample * w, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        // A Hann window halves the amplitude; the one-sided spectrum halves it again.
+        let scale = 4.0 / FFT_SIZE as f32;
+        let resolution = sample_rate.max(1) as f32 / FFT_SIZE as f32;
+        let top = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let ratio = (top / MIN_FREQUENCY).max(1.0);
+        let half = FFT_SIZE / 2;
+        (0..self.bins)
+            .map(|bin| {
+                let low = MIN_FREQUENCY * ratio.powf(bin as f32 / self.bins as f32);
+                let high = MIN_FREQUENCY * ratio.powf((bin + 1) as f32 / self.bins as f32);
+                let start = ((low / resolution) as usize).clamp(1, half - 1);
+                let end = ((high / resolution).ceil() as usize).clamp(start + 1, half);
+                self.buffer[start..end]
+                    .iter()
+                    .map(|c| c.norm() * scale)
+                    .fold(0.0, f32::max)
+            })
+            .collect()
+    }
+}
+
+/// Starts or stops `spectrum-data` events. `bins` and `rate_hz` default to
+/// [`DEFAULT_BINS`] and [`DEFAULT_RATE_HZ`], and are capped at [`MAX_BINS`]
+/// and [`MAX_RATE_HZ`].
+#[tauri::command]
+pub fn set_visualizer_enabled(
+    enabled: bool,
+    bins: Option<usize>,
+    rate_hz: Option<u32>,
+    app: AppHandle,
+    player: State<'_, PlayerState>,
+) -> Result<(), PlayerError> {
+    if !enabled {
+        player.visualizer.stop();
+        return Ok(());
+    }
+    player
+        .visualizer
+        .start(
+            bins.unwrap_or(DEFAULT_BINS),
+            rate_hz.unwrap_or(DEFAULT_RATE_HZ),
+            app,
+        )
+        .map_err(|e| e.to_string().into())
+}
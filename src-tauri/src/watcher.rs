@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::event::{CreateKind, Event, EventKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::cue;
+use crate::error::PlayerError;
+use crate::index::{absolute, LibraryIndex};
+use crate::library;
+use crate::settings::SettingsStore;
+use crate::silence::SilenceScanner;
+use crate::sync::MutexExt;
+
+pub const LIBRARY_CHANGED_EVENT: &str = "library-changed";
+
+/// Quiet period after the last filesystem event before changes are indexed.
+pub const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Longest changes wait during a steady stream of events, e.g. a long copy.
+pub const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Payload of the `library-changed` event.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryChange {
+    /// Tracks added or re-read because they changed.
+    pub indexed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Watches the library folders and keeps the index in step with them.
+pub struct LibraryWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    /// Feeds synthetic events to the indexing thread, e.g. for a newly added folder.
+    events: Sender<notify::Result<Event>>,
+    folders: Mutex<Vec<String>>,
+}
+
+impl LibraryWatcher {
+    /// Starts watching `folders` and catches up on anything that changed in
+    /// them while the app was closed, including removals. Folders that no longer exist stay in
+    /// the list but aren't watched.
+    pub fn start(app: &AppHandle, folders: &[String]) -> Result<Self, String> {
+        let (events, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(events.clone()).map_err(|e| e.to_string())?;
+        let app = app.clone();
+        thread::Builder::new()
+            .name("library-watcher".into())
+            .spawn(move || run(rx, &app))
+            .map_err(|e| e.to_string())?;
+        for folder in folders {
+            if watcher
+                .watch(Path::new(folder), RecursiveMode::Recursive)
+                .is_ok()
+            {
+                let _ = events.send(Ok(rescan(PathBuf::from(folder))));
+            }
+        }
+        Ok(LibraryWatcher {
+            watcher: Mutex::new(watcher),
+            events,
+            folders: Mutex::new(folders.to_vec()),
+        })
+    }
+}
+
+/// An event that makes the indexing thread walk `folder`.
+fn rescan(folder: PathBuf) -> Event {
+    Event::new(EventKind::Create(CreateKind::Folder)).add_path(folder)
+}
+
+/// Collects changed paths until events pause, then indexes them in one pass.
+fn run(rx: Receiver<notify::Result<Event>>, app: &AppHandle) {
+    let mut pending: HashSet<PathBuf> = HashSet::new();
+    let mut first = Instant::now();
+    let mut last = Instant::now();
+    loop {
+        match rx.recv_timeout(DEBOUNCE) {
+            Ok(Ok(event)) if !matches!(event.kind, EventKind::Access(_)) => {
+                if pending.is_empty() {
+                    first = Instant::now();
+                }
+                last = Instant::now();
+                pending.extend(event.paths);
+            }
+            // Watcher errors (e.g. a dropped event queue) carry nothing to index.
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if !pending.is_empty() && (last.elapsed() >= DEBOUNCE || first.elapsed() >= MAX_DELAY) {
+            let change = apply(&app.state::<LibraryIndex>(), pending.drain());
+            if let Some(scanner) = app.try_state::<SilenceScanner>() {
+                change.indexed.iter().for_each(|path| scanner.analyze(path));
+            }
+            if !change.indexed.is_empty() || !change.removed.is_empty() {
+                let _ = app.emit(LIBRARY_CHANGED_EVENT, change);
+            }
+        }
+    }
+}
+
+/// Brings the index up to date with `paths`. A rename shows up as its old
+/// path, now missing, and its new one, so both halves are handled here.
+/// A directory also drops tracks indexed under it that are gone, such as
+/// ones deleted while the app was closed.
+///
+/// Files a CUE sheet splits up are indexed as its tracks, as `scan_directory`
+/// lists them, so a sheet appearing, changing, or going away relists the
+/// files next to it.
+fn apply(index: &LibraryIndex, paths: impl Iterator<Item = PathBuf>) -> LibraryChange {
+    let mut change = LibraryChange::default();
+    let mut files = Vec::new();
+    let mut paths: Vec<PathBuf> = paths.collect();
+    let gone: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| path.is_dir())
+        .flat_map(|dir| missing_under(index, dir))
+        .collect();
+    paths.extend(gone);
+    for path in paths {
+        if !path.exists() {
+            if let Ok(removed) = index.remove_under(&path) {
+                change.removed.extend(removed);
+            }
+        }
+        if cue::is_sheet(&path) {
+            if let Some(dir) = path.parent() {
+                files.extend(library::scan_shallow(dir));
+            }
+        } else if path.exists() {
+            files.extend(library::scan(&path));
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.path.clone()));
+
+    let (tracks, _) = cue::expand(files.clone());
+    let listed: HashSet<&str> = tracks.iter().map(|t| t.path.as_str()).collect();
+    for file in &files {
+        // Now listed through a sheet's tracks rather than whole.
+        if !listed.contains(file.path.as_str()) {
+            if let Ok(removed) = index.remove_under(Path::new(&file.path)) {
+                change.removed.extend(removed);
+            }
+        }
+    }
+    for track in &tracks {
+        // Files still being copied fail to parse; their next write event retries.
+        if let Ok(true) = index.index_file(Path::new(&track.path)) {
+            change.indexed.push(track.path.clone());
+        }
+    }
+    change
+}
+
+/// Indexed files under `dir` that no longer exist. A CUE sheet's tracks are
+/// checked through the sheet, so it shows up once if it's gone.
+fn missing_under(index: &LibraryIndex, dir: &Path) -> Vec<PathBuf> {
+    let Ok(paths) = absolute(dir).and_then(|root| index.paths_under(&root)) else {
+        return Vec::new();
+    };
+    let files: HashSet<PathBuf> = paths
+        .iter()
+        .map(|path| match cue::split_virtual(path) {
+            Some((sheet, _)) => sheet.to_path_buf(),
+            None => PathBuf::from(path),
+        })
+        .collect();
+    files.into_iter().filter(|file| !file.exists()).collect()
+}
+
+/// Watches `path` recursively, indexes what's already there, and remembers
+/// it across restarts.
+#[tauri::command]
+pub fn add_watched_folder(
+    path: String,
+    watcher: State<'_, LibraryWatcher>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    let root = absolute(Path::new(&path))?;
+    if !root.is_dir() {
+        return Err(format!("{path} is not a directory").into());
+    }
+    let key = root.to_string_lossy().into_owned();
+    let mut folders = watcher.folders.locked();
+    if folders.contains(&key) {
+        return Ok(());
+    }
+    watcher
+        .watcher
+        .locked()
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| format!("failed to watch {key}: {e}"))?;
+    let _ = watcher.events.send(Ok(rescan(root)));
+    folders.push(key);
+    settings.update(|s| s.watched_folders = folders.clone())
+}
+
+/// Stops watching `path`. Tracks already indexed from it are kept.
+#[tauri::command]
+pub fn remove_watched_folder(
+    path: String,
+    watcher: State<'_, LibraryWatcher>,
+    settings: State<'_, SettingsStore>,
+) -> Result<(), PlayerError> {
+    let key = absolute(Path::new(&path))
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or(path);
+    let mut folders = watcher.folders.locked();
+    let Some(i) = folders.iter().position(|f| *f == key) else {
+        return Ok(());
+    };
+    // A folder that has since been deleted is no longer watched anyway.
+    let _ = watcher.watcher.locked().unwatch(Path::new(&key));
+    folders.remove(i);
+    settings.update(|s| s.watched_folders = folders.clone())
+}
+
+#[tauri::command]
+pub fn list_watched_folders(watcher: State<'_, LibraryWatcher>) -> Vec<String> {
+    watcher.folders.locked().clone()
+}
@@ -0,0 +1,83 @@
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use tauri::{AppHandle, Manager};
+
+use crate::decoder;
+use crate::error::PlayerError;
+use crate::index::{index_key, source_mtime, LibraryIndex};
+
+pub const MAX_BUCKETS: usize = 20_000;
+
+/// Frames summarised into one min/max pair while decoding. Buckets are built
+/// from these, so the track length doesn't need to be known up front.
+const CHUNK_FRAMES: usize = 64;
+
+/// Decodes `path` to mono and returns `buckets` `[min, max]` pairs, flattened
+/// and scaled so the loudest peak reaches ±1.0.
+pub fn generate(path: &Path, buckets: usize) -> Result<Vec<f32>, String> {
+    let mut chunks: Vec<(f32, f32)> = Vec::new();
+    let mut current = (f32::MAX, f32::MIN);
+    let mut filled = 0;
+    decoder::decode_mono(path, |sample| {
+        current = (current.0.min(sample), current.1.max(sample));
+        filled += 1;
+        if filled == CHUNK_FRAMES {
+            chunks.push(current);
+            current = (f32::MAX, f32::MIN);
+            filled = 0;
+        }
+        ControlFlow::Continue(())
+    })?;
+    if filled > 0 {
+        chunks.push(current);
+    }
+    if chunks.is_empty() {
+        return Ok(vec![0.0; buckets * 2]);
+    }
+
+    let mut peaks = Vec::with_capacity(buckets * 2);
+    for bucket in 0..buckets {
+        let start = bucket * chunks.len() / buckets;
+        let end = ((bucket + 1) * chunks.len() / buckets).max(start + 1);
+        let (min, max) = chunks[start..end.min(chunks.len())]
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &(min, max)| {
+                (lo.min(min), hi.max(max))
+            });
+        peaks.push(min);
+        peaks.push(max);
+    }
+    let loudest = peaks.iter().fold(0.0f32, |m, p| m.max(p.abs()));
+    if loudest > 0.0 {
+        peaks.iter_mut().for_each(|p| *p /= loudest);
+    }
+    Ok(peaks)
+}
+
+/// Returns `2 * buckets` values, a `[min, max]` pair per bucket in `-1.0..=1.0`.
+/// Results are cached in the library index until the file changes.
+#[tauri::command]
+pub async fn generate_waveform(
+    path: String,
+    buckets: usize,
+    app: AppHandle,
+) -> Result<Vec<f32>, PlayerError> {
+    if buckets == 0 || buckets > MAX_BUCKETS {
+        return Err(format!("buckets must be between 1 and {MAX_BUCKETS}").into());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = Path::new(&path);
+        let key = index_key(path)?;
+        let mtime = source_mtime(path)?;
+        let index = app.state::<LibraryIndex>();
+        if let Some(peaks) = index.cached_waveform(&key, buckets, mtime)? {
+            return Ok(peaks);
+        }
+        let peaks = generate(path, buckets)?;
+        index.store_waveform(&key, buckets, mtime, &peaks)?;
+        Ok(peaks)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
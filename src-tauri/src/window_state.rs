@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, Manager, Monitor, PhysicalPosition, PhysicalSize, WebviewWindow, Window, WindowEvent,
+};
+
+use crate::settings::write_atomic;
+
+/// Kept apart from the session file, which is rewritten on its own schedule.
+pub const WINDOW_STATE_FILE: &str = "window-state.json";
+
+/// Quiet period after the last move or resize before the geometry is saved.
+pub const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How much of the window must overlap a monitor for it to count as reachable.
+const MIN_VISIBLE: u32 = 64;
+
+const MAIN_WINDOW: &str = "main";
+
+/// Main window placement in physical pixels. While maximized or fullscreen,
+/// the bounds are the ones to return to afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+    pub fullscreen: bool,
+}
+
+/// Saves the main window's geometry as it moves and resizes.
+pub struct WindowStateStore {
+    changes: Sender<()>,
+}
+
+impl WindowStateStore {
+    /// Restores the saved geometry onto the main window, then starts saving
+    /// changes to it. Register with `manage` after calling this, so the
+    /// restore itself isn't saved back.
+    pub fn start(app: &AppHandle) -> Result<Self, String> {
+        let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(WINDOW_STATE_FILE);
+        let saved = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<WindowGeometry>(&json).ok());
+        if let (Some(geometry), Some(window)) = (saved, app.get_webview_window(MAIN_WINDOW)) {
+            restore(&window, geometry);
+        }
+
+        let (changes, rx) = mpsc::channel();
+        let app = app.clone();
+        thread::Builder::new()
+            .name("window-state".into())
+            .spawn(move || run(rx, &app, path, saved))
+            .map_err(|e| e.to_string())?;
+        Ok(WindowStateStore { changes })
+    }
+}
+
+/// Handler for the builder's `on_window_event`, noting moves and resizes of
+/// the main window.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if !matches!(event, WindowEvent::Moved(_) | WindowEvent::Resized(_))
+        || window.label() != MAIN_WINDOW
+    {
+        return;
+    }
+    if let Some(store) = window.try_state::<WindowStateStore>() {
+        let _ = store.changes.send(());
+    }
+}
+
+/// Writes the geometry once changes pause, skipping writes that change nothing.
+fn run(rx: Receiver<()>, app: &AppHandle, path: PathBuf, mut saved: Option<WindowGeometry>) {
+    loop {
+        // Block until something changes, then wait for it to settle.
+        if rx.recv().is_err() {
+            return;
+        }
+        loop {
+            match rx.recv_timeout(SAVE_DEBOUNCE) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+            continue;
+        };
+        let Some(geometry) = capture(&window, saved) else {
+            continue;
+        };
+        if saved == Some(geometry) {
+            continue;
+        }
+        let Ok(json) = serde_json::to_string_pretty(&geometry) else {
+            continue;
+        };
+        if write_atomic(&path, json.as_bytes()).is_ok() {
+            saved = Some(geometry);
+        }
+    }
+}
+
+/// The window's current geometry, or `None` while minimized, when the
+/// reported position is meaningless.
+fn capture(window: &WebviewWindow, previous: Option<WindowGeometry>) -> Option<WindowGeometry> {
+    if window.is_minimized().ok()? {
+        return None;
+    }
+    let maximized = window.is_maximized().ok()?;
+    let fullscreen = window.is_fullscreen().ok()?;
+    let geometry = match previous {
+        Some(previous) if maximized || fullscreen => previous,
+        _ => {
+            let position = window.outer_position().ok()?;
+            let size = window.inner_size().ok()?;
+            WindowGeometry {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+                maximized,
+                fullscreen,
+            }
+        }
+    };
+    Some(WindowGeometry {
+        maximized,
+        fullscreen,
+        ..geometry
+    })
+}
+
+fn restore(window: &WebviewWindow, geometry: WindowGeometry) {
+    let monitors = window.available_monitors().unwrap_or_default();
+    let fallback = window
+        .primary_monitor()
+        .ok()
+        .flatten()
+        .or_else(|| monitors.first().cloned());
+    let geometry = onto_monitor(geometry, &monitors, fallback.as_ref());
+    let _ = window.set_fullscreen(geometry.fullscreen);
+    let _ = window.set_size(PhysicalSize::new(geometry.width, geometry.height));
+    let _ = window.set_position(PhysicalPosition::new(geometry.x, geometry.y));
+    if geometry.maximized {
+        let _ = window.maximize();
+    }
+}
+
+/// Length of the overlap of `a..a + a_len` and `b..b + b_len`.
+fn overlap(a: i32, a_len: u32, b: i32, b_len: u32) -> u32 {
+    let start = i64::from(a).max(i64::from(b));
+    let end = (i64::from(a) + i64::from(a_len)).min(i64::from(b) + i64::from(b_len));
+    (end - start).max(0) as u32
+}
+
+/// Keeps `geometry` where it is if enough of it shows on some monitor;
+/// otherwise (a monitor was unplugged, say) centers it on `fallback`,
+/// shrunk to fit.
+fn onto_monitor(
+    geometry: WindowGeometry,
+    monitors: &[Monitor],
+    fallback: Option<&Monitor>,
+) -> WindowGeometry {
+    let visible = monitors.iter().any(|monitor| {
+        let area = monitor.work_area();
+        let across = overlap(geometry.x, geometry.width, area.position.x, area.size.width);
+        let down = overlap(
+            geometry.y,
+            geometry.height,
+            area.position.y,
+            area.size.height,
+        );
+        across >= MIN_VISIBLE.min(geometry.width) && down >= MIN_VISIBLE.min(geometry.height)
+    });
+    let Some(monitor) = fallback.filter(|_| !visible) else {
+        return geometry;
+    };
+    let area = monitor.work_area();
+    let width = geometry.width.min(area.size.width);
+    let height = geometry.height.min(area.size.height);
+    WindowGeometry {
+        x: area.position.x + ((area.size.width - width) / 2) as i32,
+        y: area.position.y + ((area.size.height - height) / 2) as i32,
+        width,
+        height,
+        ..geometry
+    }
+}